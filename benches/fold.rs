@@ -0,0 +1,74 @@
+//! folding the joined rows of 10k todos with 5 labels each, next to the fold
+//! which scanned every entity folded so far for each row
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use todo::{fold_entities, Label, TodoEntity, TodoWithLabelFromRow};
+
+const TODOS: i32 = 10_000;
+const LABELS: i32 = 5;
+
+/// rows as `all` selects them, newest todo first
+fn rows() -> Vec<TodoWithLabelFromRow> {
+    (1..=TODOS)
+        .rev()
+        .flat_map(|id| {
+            (1..=LABELS).map(move |label_id| TodoWithLabelFromRow {
+                text: format!("todo {}", id),
+                id,
+                completed: id % 2 == 0,
+                label_id: Some(label_id),
+                label_name: Some(format!("label {}", label_id)),
+            })
+        })
+        .collect()
+}
+
+fn linear_scan(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
+    let mut accum: Vec<TodoEntity> = vec![];
+    'outer: for row in rows.iter() {
+        for todo in accum.iter_mut() {
+            if todo.id == row.id {
+                todo.labels.push(Label {
+                    id: row.label_id.unwrap(),
+                    name: row.label_name.clone().unwrap(),
+                });
+                continue 'outer;
+            }
+        }
+        accum.push(TodoEntity {
+            text: row.text.clone(),
+            id: row.id,
+            completed: row.completed,
+            labels: row
+                .label_id
+                .map(|id| Label {
+                    id,
+                    name: row.label_name.clone().unwrap(),
+                })
+                .into_iter()
+                .collect(),
+            items: vec![],
+            blocked: false,
+            blocked_by: vec![],
+        });
+    }
+    accum
+}
+
+fn fold(c: &mut Criterion) {
+    let rows = rows();
+    assert_eq!(fold_entities(rows.clone()), linear_scan(rows.clone()));
+
+    let mut group = c.benchmark_group("fold 10k todos x 5 labels");
+    group.sample_size(10);
+    group.bench_function("hash map", |b| {
+        b.iter_batched(|| rows.clone(), fold_entities, BatchSize::LargeInput)
+    });
+    group.bench_function("linear scan", |b| {
+        b.iter_batched(|| rows.clone(), linear_scan, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, fold);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE todo_items
+(
+    id SERIAL PRIMARY KEY,
+    todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    done BOOLEAN NOT NULL DEFAULT false,
+    position INTEGER NOT NULL
+);
+
+CREATE INDEX todo_items_todo_id_idx ON todo_items (todo_id);
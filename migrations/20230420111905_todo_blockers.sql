@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE todo_blockers
+(
+    todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    blocker_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    PRIMARY KEY (todo_id, blocker_id),
+    CHECK (todo_id <> blocker_id)
+);
+
+CREATE INDEX todo_blockers_blocker_id_idx ON todo_blockers (blocker_id);
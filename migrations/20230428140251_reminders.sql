@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE reminders
+(
+    id SERIAL PRIMARY KEY,
+    todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    remind_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ,
+    next_attempt_at TIMESTAMPTZ,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT
+);
+
+CREATE INDEX reminders_todo_id_idx ON reminders (todo_id);
+CREATE INDEX reminders_pending_idx ON reminders (next_attempt_at) WHERE sent_at IS NULL;
@@ -0,0 +1,32 @@
+-- Add migration script here
+CREATE TABLE users
+(
+    id            SERIAL PRIMARY KEY,
+    name          TEXT        NOT NULL UNIQUE,
+    password_hash TEXT        NOT NULL,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE user_tokens
+(
+    id         SERIAL PRIMARY KEY,
+    user_id    INTEGER     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash TEXT        NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+-- rows created before accounts existed have no owner and are not listed to anyone
+ALTER TABLE todos ADD COLUMN user_id INTEGER REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE labels ADD COLUMN user_id INTEGER REFERENCES users (id) ON DELETE CASCADE;
+CREATE INDEX todos_user_id_idx ON todos (user_id);
+CREATE INDEX labels_user_id_idx ON labels (user_id);
+
+-- removing a user removes its todos and labels, their links have to follow
+ALTER TABLE todo_labels
+    DROP CONSTRAINT todo_labels_todo_id_fkey,
+    DROP CONSTRAINT todo_labels_label_id_fkey,
+    ADD CONSTRAINT todo_labels_todo_id_fkey FOREIGN KEY (todo_id)
+        REFERENCES todos (id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+    ADD CONSTRAINT todo_labels_label_id_fkey FOREIGN KEY (label_id)
+        REFERENCES labels (id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED;
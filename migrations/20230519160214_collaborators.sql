@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE collaborators
+(
+    owner_id   INTEGER     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    user_id    INTEGER     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role       TEXT        NOT NULL CHECK (role IN ('viewer', 'editor', 'owner')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (owner_id, user_id),
+    CHECK (owner_id <> user_id)
+);
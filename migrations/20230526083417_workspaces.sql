@@ -0,0 +1,28 @@
+-- Add migration script here
+CREATE TABLE workspaces
+(
+    id         SERIAL PRIMARY KEY,
+    slug       TEXT        NOT NULL UNIQUE,
+    name       TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- everything created before workspaces existed belongs to the default one
+INSERT INTO workspaces (slug, name) VALUES ('default', 'Default');
+
+ALTER TABLE users ADD COLUMN workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id) ON DELETE CASCADE;
+ALTER TABLE todos ADD COLUMN workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id) ON DELETE CASCADE;
+ALTER TABLE labels ADD COLUMN workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id) ON DELETE CASCADE;
+ALTER TABLE users ALTER COLUMN workspace_id DROP DEFAULT;
+ALTER TABLE todos ALTER COLUMN workspace_id DROP DEFAULT;
+ALTER TABLE labels ALTER COLUMN workspace_id DROP DEFAULT;
+
+-- user names only have to be unique inside their workspace
+ALTER TABLE users
+    DROP CONSTRAINT users_name_key,
+    ADD CONSTRAINT users_workspace_id_name_key UNIQUE (workspace_id, name);
+
+DROP INDEX todos_user_id_idx;
+DROP INDEX labels_user_id_idx;
+CREATE INDEX todos_workspace_id_user_id_idx ON todos (workspace_id, user_id);
+CREATE INDEX labels_workspace_id_user_id_idx ON labels (workspace_id, user_id);
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- the history outlives the rows it is about, so nothing references todos or users
+CREATE TABLE todo_events
+(
+    id           BIGSERIAL PRIMARY KEY,
+    todo_id      INTEGER     NOT NULL,
+    workspace_id INTEGER     NOT NULL,
+    user_id      INTEGER     NOT NULL,
+    payload      JSONB       NOT NULL,
+    recorded_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX todo_events_todo_id_idx ON todo_events (todo_id, id);
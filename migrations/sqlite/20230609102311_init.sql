@@ -0,0 +1,119 @@
+-- the schema postgres has after 20230602091544_sessions in one go, timestamps
+-- are rfc3339 text and arrays are json text
+CREATE TABLE workspaces (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  slug TEXT NOT NULL UNIQUE,
+  name TEXT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO
+  workspaces (slug, name)
+VALUES
+  ('default', 'Default');
+CREATE TABLE users (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  workspace_id INTEGER NOT NULL REFERENCES workspaces (id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  password_hash TEXT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (workspace_id, name)
+);
+CREATE TABLE user_tokens (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  token_hash TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  expires_at TEXT NOT NULL
+);
+CREATE TABLE todos (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  text TEXT NOT NULL,
+  completed BOOLEAN NOT NULL DEFAULT false,
+  user_id INTEGER REFERENCES users (id) ON DELETE CASCADE,
+  workspace_id INTEGER NOT NULL REFERENCES workspaces (id) ON DELETE CASCADE
+);
+CREATE INDEX todos_workspace_id_user_id_idx ON todos (workspace_id, user_id);
+CREATE TABLE labels (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  user_id INTEGER REFERENCES users (id) ON DELETE CASCADE,
+  workspace_id INTEGER NOT NULL REFERENCES workspaces (id) ON DELETE CASCADE
+);
+CREATE INDEX labels_workspace_id_user_id_idx ON labels (workspace_id, user_id);
+CREATE TABLE todo_labels (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED,
+  label_id INTEGER NOT NULL REFERENCES labels (id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED
+);
+CREATE TABLE todo_items (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+  text TEXT NOT NULL,
+  done BOOLEAN NOT NULL DEFAULT false,
+  position INTEGER NOT NULL
+);
+CREATE INDEX todo_items_todo_id_idx ON todo_items (todo_id);
+CREATE TABLE todo_blockers (
+  todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+  blocker_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+  PRIMARY KEY (todo_id, blocker_id),
+  CHECK (todo_id <> blocker_id)
+);
+CREATE INDEX todo_blockers_blocker_id_idx ON todo_blockers (blocker_id);
+CREATE TABLE comments (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+  body TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+CREATE INDEX comments_todo_id_idx ON comments (todo_id);
+CREATE TABLE attachments (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+  file_name TEXT NOT NULL,
+  content_type TEXT NOT NULL,
+  size BIGINT NOT NULL,
+  created_at TEXT NOT NULL
+);
+CREATE INDEX attachments_todo_id_idx ON attachments (todo_id);
+CREATE TABLE reminders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  todo_id INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+  remind_at TEXT NOT NULL,
+  sent_at TEXT,
+  next_attempt_at TEXT,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  last_error TEXT
+);
+CREATE INDEX reminders_todo_id_idx ON reminders (todo_id);
+CREATE INDEX reminders_pending_idx ON reminders (next_attempt_at)
+WHERE
+  sent_at IS NULL;
+CREATE TABLE personal_tokens (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  scopes TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT,
+  last_used_at TEXT
+);
+CREATE INDEX personal_tokens_user_id_idx ON personal_tokens (user_id);
+CREATE TABLE collaborators (
+  owner_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  role TEXT NOT NULL CHECK (role IN ('viewer', 'editor', 'owner')),
+  created_at TEXT NOT NULL,
+  PRIMARY KEY (owner_id, user_id),
+  CHECK (owner_id <> user_id)
+);
+CREATE TABLE sessions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  token_hash TEXT NOT NULL UNIQUE,
+  csrf_token TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL
+);
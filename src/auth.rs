@@ -0,0 +1,600 @@
+use anyhow::Context;
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    async_trait,
+    body::BoxBody,
+    extract::{FromRequest, RequestParts},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tower_http::validate_request::{ValidateRequest, ValidateRequestHeaderLayer};
+
+use crate::{
+    handlers::error_status,
+    repositories::{
+        collaborator::{CollaboratorRepository, Role},
+        session::{Session, SessionRepository},
+        token::{PersonalTokenRepository, Scope},
+        user::{User, UserRepository},
+        workspace::{WorkspaceRepository, DEFAULT_WORKSPACE},
+    },
+};
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// random login token handed to the client once, only its hash is stored
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// user a request was authenticated as, rejects with 401 when there is none
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthUser {
+    pub id: i32,
+    pub workspace_id: i32,
+}
+
+impl From<User> for AuthUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            workspace_id: user.workspace_id,
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for AuthUser {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .and_then(|extensions| extensions.get::<AuthUser>())
+            .copied()
+            .ok_or_else(|| unauthorized(None))
+    }
+}
+
+/// 401 with the challenge of RFC 6750, `error` is set when a token was rejected
+fn unauthorized(error: Option<&str>) -> Response {
+    let challenge = match error {
+        Some(error) => format!("Bearer realm=\"todo\", error=\"{}\"", error),
+        None => "Bearer realm=\"todo\"".to_string(),
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_str(&challenge).unwrap(),
+    );
+    (StatusCode::UNAUTHORIZED, headers).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    /// user id
+    pub sub: String,
+    pub exp: usize,
+    /// slug of the workspace the token was issued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+}
+
+/// checks signed JWTs, either HS256 with a shared secret or RS256 with a public key
+#[derive(Clone)]
+pub struct JwtVerifier {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtVerifier {
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self {
+            key: DecodingKey::from_secret(secret),
+            validation: Validation::new(Algorithm::HS256),
+        }
+    }
+
+    /// `path` is a PEM encoded RSA public key
+    pub fn rs256(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let pem = std::fs::read(path.as_ref())
+            .with_context(|| format!("failed to read {}", path.as_ref().display()))?;
+        Ok(Self {
+            key: DecodingKey::from_rsa_pem(&pem)?,
+            validation: Validation::new(Algorithm::RS256),
+        })
+    }
+
+    pub fn verify(&self, token: &str) -> anyhow::Result<Claims> {
+        let data = jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)?;
+        Ok(data.claims)
+    }
+}
+
+/// personal access tokens carry this prefix, telling them apart from login tokens
+pub const PERSONAL_TOKEN_PREFIX: &str = "pat_";
+
+pub fn generate_personal_token() -> String {
+    format!("{}{}", PERSONAL_TOKEN_PREFIX, generate_token())
+}
+
+/// scopes granted to the personal access token a request was made with.
+/// requests authenticated otherwise have no `TokenScopes` and may do anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenScopes(pub Vec<Scope>);
+
+/// slug of the workspace named by the `workspace` claim of a JWT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceClaim(pub String);
+
+/// what a bearer token resolved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Authenticated {
+    pub user: AuthUser,
+    /// only set for personal access tokens
+    pub scopes: Option<TokenScopes>,
+    pub workspace: Option<WorkspaceClaim>,
+}
+
+/// resolves bearer tokens: personal access tokens, JWTs when a verifier is
+/// configured, and login tokens; and session cookies
+#[derive(Clone)]
+pub struct Authenticator<U: UserRepository, P: PersonalTokenRepository, S: SessionRepository> {
+    users: U,
+    personal_tokens: P,
+    sessions: S,
+    jwt: Option<JwtVerifier>,
+}
+
+impl<U: UserRepository, P: PersonalTokenRepository, S: SessionRepository> Authenticator<U, P, S> {
+    pub fn new(users: U, personal_tokens: P, sessions: S, jwt: Option<JwtVerifier>) -> Self {
+        Self {
+            users,
+            personal_tokens,
+            sessions,
+            jwt,
+        }
+    }
+
+    pub async fn authenticate_session(&self, token: &str) -> anyhow::Result<(AuthUser, Session)> {
+        let session = self.sessions.find_by_hash(&hash_token(token)).await?;
+        let user = self.users.find(session.user_id).await?;
+        Ok((user.into(), session))
+    }
+
+    pub async fn authenticate(&self, token: &str) -> anyhow::Result<Authenticated> {
+        if token.starts_with(PERSONAL_TOKEN_PREFIX) {
+            let personal_token = self
+                .personal_tokens
+                .find_by_hash(&hash_token(token))
+                .await?;
+            let user = self.users.find(personal_token.user_id).await?;
+            return Ok(Authenticated {
+                user: user.into(),
+                scopes: Some(TokenScopes(personal_token.scopes)),
+                workspace: None,
+            });
+        }
+        match &self.jwt {
+            // login tokens are hex, so anything with dots is meant to be a JWT
+            Some(jwt) if token.contains('.') => {
+                let claims = jwt.verify(token)?;
+                let user = self.users.find(claims.sub.parse()?).await?;
+                Ok(Authenticated {
+                    user: user.into(),
+                    scopes: None,
+                    workspace: claims.workspace.map(WorkspaceClaim),
+                })
+            }
+            _ => {
+                let user = self.users.find_by_token(&hash_token(token)).await?;
+                Ok(Authenticated {
+                    user: user.into(),
+                    scopes: None,
+                    workspace: None,
+                })
+            }
+        }
+    }
+}
+
+/// layer for a handler which needs `scope` when called with a personal access token
+pub fn scope(scope: Scope) -> ValidateRequestHeaderLayer<RequireScope> {
+    ValidateRequestHeaderLayer::custom(RequireScope(scope))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequireScope(Scope);
+
+impl<B> ValidateRequest<B> for RequireScope {
+    type ResponseBody = BoxBody;
+
+    fn validate(&mut self, request: &mut Request<B>) -> Result<(), Response> {
+        match request.extensions().get::<TokenScopes>() {
+            Some(TokenScopes(scopes)) if !scopes.contains(&self.0) => {
+                let challenge = format!(
+                    "Bearer realm=\"todo\", error=\"insufficient_scope\", scope=\"{}\"",
+                    self.0
+                );
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_str(&challenge).unwrap(),
+                );
+                Err((StatusCode::FORBIDDEN, headers).into_response())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// cookie holding the token of a browser session
+pub const SESSION_COOKIE: &str = "session";
+
+/// header repeating the CSRF token of a session on requests which may change data
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// attributes of the session cookie, `secure` may only be turned off for plain HTTP setups
+#[derive(Debug, Clone)]
+pub struct SessionCookie {
+    pub secure: bool,
+    pub ttl: chrono::Duration,
+}
+
+impl Default for SessionCookie {
+    fn default() -> Self {
+        Self {
+            secure: true,
+            ttl: chrono::Duration::days(7),
+        }
+    }
+}
+
+impl SessionCookie {
+    pub fn set(&self, token: &str) -> HeaderValue {
+        self.header(token, self.ttl.num_seconds())
+    }
+
+    pub fn clear(&self) -> HeaderValue {
+        self.header("", 0)
+    }
+
+    fn header(&self, token: &str, max_age: i64) -> HeaderValue {
+        let mut cookie = format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict",
+            SESSION_COOKIE, token, max_age
+        );
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        HeaderValue::from_str(&cookie).unwrap()
+    }
+}
+
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (name, token) = pair.trim().split_once('=')?;
+            (name == SESSION_COOKIE).then(|| token.to_string())
+        })
+}
+
+/// resolves `Authorization: Bearer <token>`, else the session cookie, into an
+/// [`AuthUser`] extension. requests without either pass through, handlers
+/// asking for an `AuthUser` reject them.
+pub async fn authenticate<
+    B,
+    U: UserRepository,
+    P: PersonalTokenRepository,
+    S: SessionRepository,
+>(
+    mut req: Request<B>,
+    next: Next<B>,
+    authenticator: Authenticator<U, P, S>,
+) -> Response {
+    let token = match req.headers().get(header::AUTHORIZATION) {
+        Some(value) => match value
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            Some(token) => token.trim().to_string(),
+            None => return unauthorized(Some("invalid_request")),
+        },
+        None => {
+            return match session_token(req.headers()) {
+                Some(token) => authenticate_session(req, next, authenticator, &token).await,
+                None => next.run(req).await,
+            }
+        }
+    };
+    match authenticator.authenticate(&token).await {
+        Ok(authenticated) => {
+            req.extensions_mut().insert(authenticated.user);
+            if let Some(scopes) = authenticated.scopes {
+                req.extensions_mut().insert(scopes);
+            }
+            if let Some(workspace) = authenticated.workspace {
+                req.extensions_mut().insert(workspace);
+            }
+            next.run(req).await
+        }
+        Err(e) => {
+            tracing::debug!("rejected bearer token: {}", e);
+            unauthorized(Some("invalid_token"))
+        }
+    }
+}
+
+/// browsers send the cookie on their own, so a session only counts for requests
+/// which may change data when they carry its CSRF token, 403 otherwise. an
+/// unknown or expired cookie is ignored, leaving the user able to log in again.
+async fn authenticate_session<
+    B,
+    U: UserRepository,
+    P: PersonalTokenRepository,
+    S: SessionRepository,
+>(
+    mut req: Request<B>,
+    next: Next<B>,
+    authenticator: Authenticator<U, P, S>,
+    token: &str,
+) -> Response {
+    let (user, session) = match authenticator.authenticate_session(token).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => {
+            tracing::debug!("ignored session cookie: {}", e);
+            return next.run(req).await;
+        }
+    };
+    let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let csrf_token = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    if !safe && csrf_token != Some(session.csrf_token.as_str()) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(session);
+    next.run(req).await
+}
+
+/// header naming the slug of the workspace a request works in
+pub const WORKSPACE_HEADER: &str = "x-workspace";
+
+/// finds the workspace of a request, named by [`WORKSPACE_HEADER`], else by a
+/// subdomain of `domain`, else by the claim of a JWT, else the default one
+#[derive(Clone)]
+pub struct WorkspaceResolver<W: WorkspaceRepository> {
+    repository: W,
+    domain: Option<String>,
+}
+
+impl<W: WorkspaceRepository> WorkspaceResolver<W> {
+    pub fn new(repository: W, domain: Option<String>) -> Self {
+        Self { repository, domain }
+    }
+
+    fn slug<B>(&self, req: &Request<B>) -> Option<String> {
+        if let Some(value) = req.headers().get(WORKSPACE_HEADER) {
+            return value.to_str().ok().map(str::to_string);
+        }
+        let subdomain = self.domain.as_ref().and_then(|domain| {
+            let host = req.headers().get(header::HOST)?.to_str().ok()?;
+            let host = host.split(':').next()?;
+            let subdomain = host.strip_suffix(domain.as_str())?.strip_suffix('.')?;
+            (!subdomain.is_empty() && !subdomain.contains('.')).then(|| subdomain.to_string())
+        });
+        subdomain.or_else(|| {
+            req.extensions()
+                .get::<WorkspaceClaim>()
+                .map(|claim| claim.0.clone())
+        })
+    }
+}
+
+/// inserts the [`Workspace`](crate::repositories::workspace::Workspace) of a
+/// request, 404 when it does not exist and 403 when the authenticated user
+/// belongs to another one
+pub async fn resolve_workspace<B, W: WorkspaceRepository>(
+    mut req: Request<B>,
+    next: Next<B>,
+    resolver: WorkspaceResolver<W>,
+) -> Response {
+    let slug = resolver
+        .slug(&req)
+        .unwrap_or_else(|| DEFAULT_WORKSPACE.to_string());
+    let workspace = match resolver.repository.find_by_slug(&slug).await {
+        Ok(workspace) => workspace,
+        Err(e) => return error_status(&e).into_response(),
+    };
+    if let Some(user) = req.extensions().get::<AuthUser>() {
+        if user.workspace_id != workspace.id {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    }
+    req.extensions_mut().insert(workspace);
+    next.run(req).await
+}
+
+/// header naming the id of the user whose collection a request works on,
+/// without it requests work on the collection of the authenticated user
+pub const COLLECTION_HEADER: &str = "x-todo-collection";
+
+/// collection of TODOs and labels a request works on, with the role of the
+/// authenticated user in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collection {
+    pub workspace_id: i32,
+    pub owner_id: i32,
+    pub role: Role,
+}
+
+impl Collection {
+    /// 403 unless the role is at least `role`
+    pub fn require(&self, role: Role) -> Result<(), StatusCode> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Collection {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        req.extensions()
+            .and_then(|extensions| extensions.get::<Collection>())
+            .copied()
+            .ok_or_else(|| unauthorized(None))
+    }
+}
+
+/// resolves the [`Collection`] of an authenticated request, 403 when it is not
+/// shared with the user
+pub async fn resolve_collection<B, C: CollaboratorRepository>(
+    mut req: Request<B>,
+    next: Next<B>,
+    repository: C,
+) -> Response {
+    let user = match req.extensions().get::<AuthUser>() {
+        Some(user) => *user,
+        None => return next.run(req).await,
+    };
+    let owner_id = match req.headers().get(COLLECTION_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(|value| value.parse().ok()) {
+            Some(owner_id) => owner_id,
+            None => return StatusCode::BAD_REQUEST.into_response(),
+        },
+        None => user.id,
+    };
+    let role = if owner_id == user.id {
+        Role::Owner
+    } else {
+        match repository.role(owner_id, user.id).await {
+            Ok(Some(role)) => role,
+            Ok(None) => return StatusCode::FORBIDDEN.into_response(),
+            Err(e) => {
+                tracing::error!("failed to resolve collection: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    };
+    req.extensions_mut().insert(Collection {
+        workspace_id: user.workspace_id,
+        owner_id,
+        role,
+    });
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    #[test]
+    fn password_test() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+    }
+
+    #[test]
+    fn token_test() {
+        let token = generate_token();
+        assert_eq!(64, token.len());
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(token, hash_token(&token));
+    }
+
+    fn claims(sub: &str, valid_for: i64) -> Claims {
+        Claims {
+            sub: sub.to_string(),
+            exp: (chrono::Utc::now().timestamp() + valid_for) as usize,
+            workspace: None,
+        }
+    }
+
+    #[test]
+    fn jwt_hs256_test() {
+        let verifier = JwtVerifier::hs256(b"secret");
+        let sign = |claims: &Claims, secret: &[u8]| {
+            encode(
+                &Header::new(Algorithm::HS256),
+                claims,
+                &EncodingKey::from_secret(secret),
+            )
+            .unwrap()
+        };
+        let token = sign(&claims("7", 60), b"secret");
+        assert_eq!("7", verifier.verify(&token).unwrap().sub);
+        assert!(verifier
+            .verify(&sign(&claims("7", 60), b"other secret"))
+            .is_err());
+        assert!(verifier
+            .verify(&sign(&claims("7", -120), b"secret"))
+            .is_err());
+    }
+
+    #[test]
+    fn jwt_rs256_test() {
+        let verifier = JwtVerifier::rs256("fixtures/jwt/public.pem").unwrap();
+        let key = EncodingKey::from_rsa_pem(include_bytes!("../fixtures/jwt/private.pem")).unwrap();
+        let token = encode(&Header::new(Algorithm::RS256), &claims("7", 60), &key).unwrap();
+        assert_eq!("7", verifier.verify(&token).unwrap().sub);
+        // a HS256 token signed with the public key must not pass
+        let forged = encode(
+            &Header::new(Algorithm::HS256),
+            &claims("7", 60),
+            &EncodingKey::from_secret(include_bytes!("../fixtures/jwt/public.pem")),
+        )
+        .unwrap();
+        assert!(verifier.verify(&forged).is_err());
+        assert!(JwtVerifier::rs256("fixtures/jwt/missing.pem").is_err());
+    }
+}
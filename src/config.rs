@@ -0,0 +1,1012 @@
+use axum::http::{HeaderValue, Uri};
+use clap::{Parser, ValueEnum};
+use mime::Mime;
+use serde::Deserialize;
+use sqlx::{pool::PoolOptions, postgres::PgConnectOptions, Database};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid {field}: {reason}")]
+    Invalid { field: &'static str, reason: String },
+}
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        field,
+        reason: reason.into(),
+    }
+}
+
+/// command line flags, each one falls back to its environment variable and
+/// both take precedence over the config file
+#[derive(Debug, Default, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML file with settings
+    #[arg(long, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "LISTEN_ADDR")]
+    pub listen: Option<SocketAddr>,
+    /// how long in-flight requests may run after SIGINT or SIGTERM
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECS")]
+    pub shutdown_timeout_secs: Option<u64>,
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+    /// read-only postgres replica for listing todos and labels
+    #[arg(long, env = "DATABASE_REPLICA_URL")]
+    pub database_replica_url: Option<String>,
+    #[arg(long, env = "DATABASE_REPLICA_LAG_SECS")]
+    pub database_replica_lag_secs: Option<u64>,
+    #[arg(long, env = "DATABASE_MAX_CONNECTIONS")]
+    pub database_max_connections: Option<u32>,
+    #[arg(long, env = "DATABASE_MIN_CONNECTIONS")]
+    pub database_min_connections: Option<u32>,
+    #[arg(long, env = "DATABASE_ACQUIRE_TIMEOUT_SECS")]
+    pub database_acquire_timeout_secs: Option<u64>,
+    #[arg(long, env = "DATABASE_IDLE_TIMEOUT_SECS")]
+    pub database_idle_timeout_secs: Option<u64>,
+    #[arg(long, env = "DATABASE_STATEMENT_TIMEOUT_SECS")]
+    pub database_statement_timeout_secs: Option<u64>,
+    /// how long to keep retrying while the database is not up yet
+    #[arg(long, env = "DATABASE_CONNECT_RETRY_SECS")]
+    pub database_connect_retry_secs: Option<u64>,
+    /// apply pending migrations at startup
+    #[arg(long, env = "DATABASE_MIGRATE", num_args = 0..=1, default_missing_value = "true")]
+    pub migrate: Option<bool>,
+    /// apply pending migrations and exit
+    #[arg(long)]
+    pub migrate_only: bool,
+    /// record todos as events and project them into the todos table
+    #[arg(long, env = "DATABASE_EVENT_SOURCING", num_args = 0..=1, default_missing_value = "true")]
+    pub event_sourcing: Option<bool>,
+    /// rebuild the todos table from the recorded todo events and exit, todos
+    /// written while event sourcing was off are recorded first
+    #[arg(long)]
+    pub rebuild_projections: bool,
+    /// origin allowed to call the API with credentials, may be repeated
+    #[arg(long = "cors-origin", env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Vec<String>,
+    /// filter in the syntax of RUST_LOG, e.g. `info,todo=debug`
+    #[arg(long, env = "RUST_LOG")]
+    pub log_level: Option<String>,
+    #[arg(long, env = "LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+    #[arg(long, env = "ATTACHMENT_DIR")]
+    pub attachment_dir: Option<PathBuf>,
+    #[arg(long, env = "ATTACHMENT_MAX_BYTES")]
+    pub attachment_max_bytes: Option<usize>,
+    /// content type accepted for uploads, `type/*` allows every subtype, may be repeated
+    #[arg(
+        long = "attachment-allowed-type",
+        env = "ATTACHMENT_ALLOWED_TYPES",
+        value_delimiter = ','
+    )]
+    pub attachment_allowed_types: Vec<String>,
+    #[arg(long, env = "REMINDER_INTERVAL_SECS")]
+    pub reminder_interval_secs: Option<u64>,
+    #[arg(long, env = "REMINDER_NOTIFIER", value_enum)]
+    pub reminder_notifier: Option<NotifierKind>,
+    #[arg(long, env = "REMINDER_WEBHOOK_URL")]
+    pub reminder_webhook_url: Option<String>,
+    #[arg(long, env = "SMTP_ADDR")]
+    pub smtp_addr: Option<String>,
+    #[arg(long, env = "SMTP_FROM")]
+    pub smtp_from: Option<String>,
+    #[arg(long, env = "SMTP_TO")]
+    pub smtp_to: Option<String>,
+    /// shared secret of HS256 signed bearer tokens
+    #[arg(long, env = "JWT_HS256_SECRET", hide_env_values = true)]
+    pub jwt_hs256_secret: Option<String>,
+    /// PEM file with the public key of RS256 signed bearer tokens
+    #[arg(long, env = "JWT_RS256_PUBLIC_KEY")]
+    pub jwt_rs256_public_key: Option<PathBuf>,
+    /// how long reads of todos and labels are cached, 0 turns the cache off
+    #[arg(long, env = "CACHE_TTL_SECS")]
+    pub cache_ttl_secs: Option<u64>,
+    #[arg(long, env = "CACHE_CAPACITY")]
+    pub cache_capacity: Option<usize>,
+    #[arg(long, env = "SESSION_COOKIE_SECURE")]
+    pub session_cookie_secure: Option<bool>,
+    /// with todo.example.com, acme.todo.example.com is the workspace acme
+    #[arg(long, env = "WORKSPACE_DOMAIN")]
+    pub workspace_domain: Option<String>,
+    #[arg(long, env = "FEATURE_REMINDERS")]
+    pub reminders: Option<bool>,
+    #[arg(long, env = "FEATURE_REGISTRATION")]
+    pub registration: Option<bool>,
+}
+
+/// settings of the server, missing entries of the file keep their defaults
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listen: SocketAddr,
+    pub shutdown_timeout_secs: u64,
+    pub database: DatabaseConfig,
+    pub cors: CorsConfig,
+    pub log: LogConfig,
+    pub attachments: AttachmentConfig,
+    pub reminders: ReminderConfig,
+    pub cache: CacheConfig,
+    pub session: SessionConfig,
+    pub jwt: JwtConfig,
+    pub workspace_domain: Option<String>,
+    pub features: Features,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            shutdown_timeout_secs: 30,
+            database: DatabaseConfig::default(),
+            cors: CorsConfig::default(),
+            log: LogConfig::default(),
+            attachments: AttachmentConfig::default(),
+            reminders: ReminderConfig::default(),
+            cache: CacheConfig::default(),
+            session: SessionConfig::default(),
+            jwt: JwtConfig::default(),
+            workspace_domain: None,
+            features: Features::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// `postgres://`, `sqlite:` or `journal:<dir>`, the scheme picks the backend
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_secs: u64,
+    /// idle connections above `min_connections` are closed after this long
+    pub idle_timeout_secs: Option<u64>,
+    /// postgres cancels statements running longer than this
+    pub statement_timeout_secs: Option<u64>,
+    /// failed connects at startup are retried with exponential backoff for this long
+    pub connect_retry_secs: u64,
+    /// apply pending migrations at startup
+    pub migrate: bool,
+    /// read-only replica of a postgres `url`, `find` and `all` of todos and labels go to it
+    pub replica_url: Option<String>,
+    /// reads of a user stay on the primary for this long after they wrote
+    pub replica_lag_secs: u64,
+    /// todos are an append-only stream of events in postgres, `todos` and
+    /// `todo_labels` only hold their projection
+    pub event_sourcing: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: Some(600),
+            statement_timeout_secs: None,
+            connect_retry_secs: 30,
+            migrate: false,
+            replica_url: None,
+            replica_lag_secs: 5,
+            event_sourcing: false,
+        }
+    }
+}
+
+/// storage the repositories run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Postgres,
+    Sqlite,
+    /// the repositories kept in memory and persisted to a journal in a directory
+    Journal,
+}
+
+impl DatabaseConfig {
+    pub fn backend(&self) -> Option<Backend> {
+        match self.url.split_once(':')?.0 {
+            "postgres" | "postgresql" => Some(Backend::Postgres),
+            "sqlite" => Some(Backend::Sqlite),
+            "journal" => Some(Backend::Journal),
+            _ => None,
+        }
+    }
+
+    pub fn pool_options<DB: Database>(&self) -> PoolOptions<DB> {
+        PoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(Duration::from_secs(self.acquire_timeout_secs))
+            .idle_timeout(self.idle_timeout_secs.map(Duration::from_secs))
+    }
+
+    /// sets the session parameters of every postgres connection
+    pub fn pg_session(&self, options: PgConnectOptions) -> PgConnectOptions {
+        match self.statement_timeout_secs {
+            Some(secs) => options.options([("statement_timeout", format!("{}s", secs))]),
+            None => options,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["http://localhost:3001".to_string()],
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn origins(&self) -> Vec<HeaderValue> {
+        self.allowed_origins
+            .iter()
+            .filter_map(|origin| origin.parse().ok())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Full,
+    Compact,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Full,
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn init(&self) {
+        let builder = tracing_subscriber::fmt().with_env_filter(EnvFilter::new(&self.level));
+        match self.format {
+            LogFormat::Full => builder.init(),
+            LogFormat::Compact => builder.compact().init(),
+            LogFormat::Json => builder.json().init(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttachmentConfig {
+    pub dir: PathBuf,
+    pub max_bytes: usize,
+    /// downloads are served with the content type of the upload, so types a
+    /// browser renders as a page, like text/html or image/svg+xml, are left out
+    pub allowed_types: Vec<String>,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("attachments"),
+            max_bytes: 10 * 1024 * 1024,
+            allowed_types: [
+                "image/png",
+                "image/jpeg",
+                "image/gif",
+                "image/webp",
+                "application/pdf",
+                "text/plain",
+            ]
+            .iter()
+            .map(|allowed| allowed.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl AttachmentConfig {
+    pub fn mime_types(&self) -> Vec<Mime> {
+        self.allowed_types
+            .iter()
+            .filter_map(|allowed| allowed.parse().ok())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReminderConfig {
+    pub interval_secs: u64,
+    /// where due reminders are delivered
+    pub notifier: NotifierKind,
+    /// for the webhook notifier
+    pub webhook_url: Option<String>,
+    pub smtp: SmtpConfig,
+}
+
+impl Default for ReminderConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            notifier: NotifierKind::Log,
+            webhook_url: None,
+            smtp: SmtpConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifierKind {
+    Log,
+    Smtp,
+    Webhook,
+}
+
+/// for the smtp notifier, `from` and `to` have no defaults
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub addr: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            addr: "localhost:25".to_string(),
+            from: None,
+            to: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// off by default, other servers writing to the same database are only
+    /// seen once the cached reads expire
+    pub ttl_secs: u64,
+    /// users whose reads are kept at a time
+    pub capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 0,
+            capacity: 10_000,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// only turn off for plain HTTP setups
+    pub cookie_secure: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            cookie_secure: true,
+        }
+    }
+}
+
+/// bearer tokens issued elsewhere are accepted once one of the keys is set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    pub hs256_secret: Option<String>,
+    pub rs256_public_key: Option<PathBuf>,
+}
+
+/// parts of the server which can be switched off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// deliver due reminders
+    pub reminders: bool,
+    /// let anyone register with `POST /users`
+    pub registration: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            reminders: true,
+            registration: true,
+        }
+    }
+}
+
+impl Config {
+    /// the file named by `cli`, overridden by its flags, checked before use
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        if cli.rebuild_projections && !config.database.event_sourcing {
+            return Err(invalid(
+                "database.event_sourcing",
+                "has to be on for --rebuild-projections",
+            ));
+        }
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply(&mut self, cli: &Cli) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+        set(&mut self.listen, &cli.listen);
+        set(&mut self.shutdown_timeout_secs, &cli.shutdown_timeout_secs);
+        set(&mut self.database.url, &cli.database_url);
+        set(
+            &mut self.database.max_connections,
+            &cli.database_max_connections,
+        );
+        set(
+            &mut self.database.min_connections,
+            &cli.database_min_connections,
+        );
+        set(
+            &mut self.database.acquire_timeout_secs,
+            &cli.database_acquire_timeout_secs,
+        );
+        if cli.database_idle_timeout_secs.is_some() {
+            self.database.idle_timeout_secs = cli.database_idle_timeout_secs;
+        }
+        if cli.database_statement_timeout_secs.is_some() {
+            self.database.statement_timeout_secs = cli.database_statement_timeout_secs;
+        }
+        set(
+            &mut self.database.connect_retry_secs,
+            &cli.database_connect_retry_secs,
+        );
+        if cli.database_replica_url.is_some() {
+            self.database.replica_url = cli.database_replica_url.clone();
+        }
+        set(
+            &mut self.database.replica_lag_secs,
+            &cli.database_replica_lag_secs,
+        );
+        set(&mut self.database.migrate, &cli.migrate);
+        set(&mut self.database.event_sourcing, &cli.event_sourcing);
+        if cli.migrate_only {
+            self.database.migrate = true;
+        }
+        if !cli.cors_origins.is_empty() {
+            self.cors.allowed_origins = cli.cors_origins.clone();
+        }
+        set(&mut self.log.level, &cli.log_level);
+        set(&mut self.log.format, &cli.log_format);
+        set(&mut self.attachments.dir, &cli.attachment_dir);
+        set(&mut self.attachments.max_bytes, &cli.attachment_max_bytes);
+        if !cli.attachment_allowed_types.is_empty() {
+            self.attachments.allowed_types = cli.attachment_allowed_types.clone();
+        }
+        set(
+            &mut self.reminders.interval_secs,
+            &cli.reminder_interval_secs,
+        );
+        set(&mut self.reminders.notifier, &cli.reminder_notifier);
+        if cli.reminder_webhook_url.is_some() {
+            self.reminders.webhook_url = cli.reminder_webhook_url.clone();
+        }
+        set(&mut self.reminders.smtp.addr, &cli.smtp_addr);
+        if cli.smtp_from.is_some() {
+            self.reminders.smtp.from = cli.smtp_from.clone();
+        }
+        if cli.smtp_to.is_some() {
+            self.reminders.smtp.to = cli.smtp_to.clone();
+        }
+        // a key from the flags replaces the one of the file, whichever kind it is
+        if cli.jwt_hs256_secret.is_some() || cli.jwt_rs256_public_key.is_some() {
+            self.jwt = JwtConfig {
+                hs256_secret: cli.jwt_hs256_secret.clone(),
+                rs256_public_key: cli.jwt_rs256_public_key.clone(),
+            };
+        }
+        set(&mut self.cache.ttl_secs, &cli.cache_ttl_secs);
+        set(&mut self.cache.capacity, &cli.cache_capacity);
+        set(&mut self.session.cookie_secure, &cli.session_cookie_secure);
+        if cli.workspace_domain.is_some() {
+            self.workspace_domain = cli.workspace_domain.clone();
+        }
+        set(&mut self.features.reminders, &cli.reminders);
+        set(&mut self.features.registration, &cli.registration);
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let database = &self.database;
+        if database.url.is_empty() {
+            return Err(invalid(
+                "database.url",
+                "not set, pass --database-url or set DATABASE_URL",
+            ));
+        }
+        if database.backend().is_none() {
+            return Err(invalid(
+                "database.url",
+                "has to start with postgres://, sqlite: or journal:",
+            ));
+        }
+        if let Some(url) = &database.replica_url {
+            if database.backend() != Some(Backend::Postgres) {
+                return Err(invalid(
+                    "database.replica_url",
+                    "only works with a postgres:// url",
+                ));
+            }
+            if !url.starts_with("postgres://") && !url.starts_with("postgresql://") {
+                return Err(invalid(
+                    "database.replica_url",
+                    "has to start with postgres://",
+                ));
+            }
+        }
+        if database.event_sourcing && database.backend() != Some(Backend::Postgres) {
+            return Err(invalid(
+                "database.event_sourcing",
+                "only works with a postgres:// url",
+            ));
+        }
+        if database.statement_timeout_secs.is_some()
+            && database.backend() != Some(Backend::Postgres)
+        {
+            return Err(invalid(
+                "database.statement_timeout_secs",
+                "only works with a postgres:// url",
+            ));
+        }
+        if database.max_connections == 0 {
+            return Err(invalid("database.max_connections", "has to be at least 1"));
+        }
+        if database.min_connections > database.max_connections {
+            return Err(invalid(
+                "database.min_connections",
+                format!(
+                    "{} is more than max_connections {}",
+                    database.min_connections, database.max_connections
+                ),
+            ));
+        }
+        if database.acquire_timeout_secs == 0 {
+            return Err(invalid(
+                "database.acquire_timeout_secs",
+                "has to be at least 1",
+            ));
+        }
+        if self.cors.allowed_origins.is_empty() {
+            return Err(invalid("cors.allowed_origins", "needs at least one origin"));
+        }
+        for origin in &self.cors.allowed_origins {
+            let valid = (origin.starts_with("http://") || origin.starts_with("https://"))
+                && !origin.ends_with('/')
+                && origin.parse::<HeaderValue>().is_ok();
+            if !valid {
+                return Err(invalid(
+                    "cors.allowed_origins",
+                    format!(
+                        "{:?} is not an origin like https://todo.example.com",
+                        origin
+                    ),
+                ));
+            }
+        }
+        if let Err(e) = EnvFilter::try_new(&self.log.level) {
+            return Err(invalid("log.level", e.to_string()));
+        }
+        if self.attachments.max_bytes == 0 {
+            return Err(invalid("attachments.max_bytes", "has to be at least 1"));
+        }
+        if self.attachments.allowed_types.is_empty() {
+            return Err(invalid(
+                "attachments.allowed_types",
+                "needs at least one content type",
+            ));
+        }
+        for allowed in &self.attachments.allowed_types {
+            if allowed.parse::<Mime>().is_err() {
+                return Err(invalid(
+                    "attachments.allowed_types",
+                    format!("{:?} is not a content type like image/png", allowed),
+                ));
+            }
+        }
+        if self.reminders.interval_secs == 0 {
+            return Err(invalid("reminders.interval_secs", "has to be at least 1"));
+        }
+        let reminders = &self.reminders;
+        if let Some(url) = &reminders.webhook_url {
+            let valid = url
+                .parse::<Uri>()
+                .is_ok_and(|uri| matches!(uri.scheme_str(), Some("http" | "https")));
+            if !valid {
+                return Err(invalid(
+                    "reminders.webhook_url",
+                    format!(
+                        "{:?} is not an url like https://hooks.example.com/todo",
+                        url
+                    ),
+                ));
+            }
+        }
+        match reminders.notifier {
+            NotifierKind::Webhook if reminders.webhook_url.is_none() => {
+                return Err(invalid(
+                    "reminders.webhook_url",
+                    "has to be set for the webhook notifier",
+                ));
+            }
+            NotifierKind::Smtp if reminders.smtp.from.is_none() => {
+                return Err(invalid(
+                    "reminders.smtp.from",
+                    "has to be set for the smtp notifier",
+                ));
+            }
+            NotifierKind::Smtp if reminders.smtp.to.is_none() => {
+                return Err(invalid(
+                    "reminders.smtp.to",
+                    "has to be set for the smtp notifier",
+                ));
+            }
+            _ => {}
+        }
+        match (&self.jwt.hs256_secret, &self.jwt.rs256_public_key) {
+            (Some(_), Some(_)) => {
+                return Err(invalid(
+                    "jwt",
+                    "set only one of hs256_secret and rs256_public_key",
+                ));
+            }
+            (Some(secret), None) if secret.is_empty() => {
+                return Err(invalid("jwt.hs256_secret", "can not be empty"));
+            }
+            _ => {}
+        }
+        if self.cache.ttl_secs > 0 && self.cache.capacity == 0 {
+            return Err(invalid("cache.capacity", "has to be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    /// flags only, the environment of the test process is ignored
+    fn parse(args: &[&str]) -> Cli {
+        let matches = Cli::command()
+            .mut_args(|arg| arg.env(None::<&str>))
+            .try_get_matches_from(std::iter::once("todo").chain(args.iter().copied()))
+            .unwrap();
+        Cli::from_arg_matches(&matches).unwrap()
+    }
+
+    /// flags and the environment variables in `vars`, the other variables are ignored
+    fn parse_with_env(args: &[&str], vars: &[&str]) -> Cli {
+        let matches = Cli::command()
+            .mut_args(|arg| match arg.get_env() {
+                Some(var) if vars.iter().any(|name| var == *name) => arg,
+                _ => arg.env(None::<&str>),
+            })
+            .try_get_matches_from(std::iter::once("todo").chain(args.iter().copied()))
+            .unwrap();
+        Cli::from_arg_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn precedence_test() {
+        let dir = std::env::temp_dir().join(format!("todo-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo.toml");
+        std::fs::write(
+            &path,
+            r#"
+            listen = "127.0.0.1:8080"
+
+            [database]
+            url = "postgres://file"
+            max_connections = 4
+
+            [log]
+            format = "json"
+
+            [features]
+            registration = false
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::load(&parse(&["--config", path.to_str().unwrap()])).unwrap();
+        assert_eq!(SocketAddr::from(([127, 0, 0, 1], 8080)), config.listen);
+        assert_eq!("postgres://file", config.database.url);
+        assert_eq!(4, config.database.max_connections);
+        assert_eq!(LogFormat::Json, config.log.format);
+        assert!(!config.features.registration);
+        // untouched entries keep their defaults
+        assert!(config.features.reminders);
+        assert_eq!(CorsConfig::default(), config.cors);
+
+        let config = Config::load(&parse(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--database-url",
+            "postgres://flag",
+            "--cors-origin",
+            "https://a.example.com",
+            "--cors-origin",
+            "https://b.example.com",
+            "--registration",
+            "true",
+            "--migrate",
+        ]))
+        .unwrap();
+        assert!(config.database.migrate);
+        assert_eq!("postgres://flag", config.database.url);
+        assert_eq!(4, config.database.max_connections);
+        assert_eq!(2, config.cors.origins().len());
+        assert!(config.features.registration);
+
+        std::fs::write(&path, "[database]\nurl = 1\n").unwrap();
+        let err = Config::load(&parse(&["--config", path.to_str().unwrap()])).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        std::fs::write(&path, "[databse]\n").unwrap();
+        let err = Config::load(&parse(&["--config", path.to_str().unwrap()])).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn env_precedence_test() {
+        let dir = std::env::temp_dir().join(format!("todo-config-env-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo.toml");
+        std::fs::write(
+            &path,
+            r#"
+            listen = "127.0.0.1:8080"
+
+            [database]
+            url = "postgres://file"
+
+            [reminders]
+            notifier = "webhook"
+            webhook_url = "https://hooks.example.com/file"
+
+            [jwt]
+            hs256_secret = "file secret"
+            "#,
+        )
+        .unwrap();
+        // nothing else in the tests reads these
+        let vars = [
+            ("LISTEN_ADDR", "127.0.0.1:9090"),
+            ("REMINDER_NOTIFIER", "smtp"),
+            ("SMTP_FROM", "todo@example.com"),
+            ("SMTP_TO", "team@example.com"),
+            ("JWT_RS256_PUBLIC_KEY", "/etc/todo/jwt.pem"),
+        ];
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        let names = vars.map(|(name, _)| name);
+
+        let config = Config::load(&parse_with_env(
+            &["--config", path.to_str().unwrap()],
+            &names,
+        ))
+        .unwrap();
+        assert_eq!(SocketAddr::from(([127, 0, 0, 1], 9090)), config.listen);
+        assert_eq!(NotifierKind::Smtp, config.reminders.notifier);
+        assert_eq!(
+            Some("todo@example.com"),
+            config.reminders.smtp.from.as_deref()
+        );
+        // entries without a variable come from the file
+        assert_eq!("postgres://file", config.database.url);
+        assert_eq!(
+            Some("https://hooks.example.com/file"),
+            config.reminders.webhook_url.as_deref()
+        );
+        // the key from the environment replaces the secret of the file
+        assert_eq!(
+            JwtConfig {
+                hs256_secret: None,
+                rs256_public_key: Some(PathBuf::from("/etc/todo/jwt.pem")),
+            },
+            config.jwt
+        );
+
+        // flags win over both
+        let config = Config::load(&parse_with_env(
+            &[
+                "--config",
+                path.to_str().unwrap(),
+                "--listen",
+                "127.0.0.1:7070",
+                "--reminder-notifier",
+                "log",
+            ],
+            &names,
+        ))
+        .unwrap();
+        assert_eq!(SocketAddr::from(([127, 0, 0, 1], 7070)), config.listen);
+        assert_eq!(NotifierKind::Log, config.reminders.notifier);
+
+        for name in names {
+            std::env::remove_var(name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_test() {
+        let valid = Config {
+            database: DatabaseConfig {
+                url: "postgres://localhost/todos".to_string(),
+                ..DatabaseConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(valid.validate().is_ok());
+
+        let invalid_field = |config: Config| match config.validate() {
+            Err(ConfigError::Invalid { field, .. }) => field,
+            other => panic!("expected an invalid field, got {:?}", other),
+        };
+        assert_eq!("database.url", invalid_field(Config::default()));
+        let mut config = valid.clone();
+        config.database.url = "mysql://localhost/todos".to_string();
+        assert_eq!("database.url", invalid_field(config));
+        let mut config = valid.clone();
+        config.database.url = "sqlite:todos.db".to_string();
+        assert_eq!(Some(Backend::Sqlite), config.database.backend());
+        assert!(config.validate().is_ok());
+        config.database.url = "journal:./data".to_string();
+        assert_eq!(Some(Backend::Journal), config.database.backend());
+        config.database.replica_url = Some("postgres://replica/todos".to_string());
+        assert_eq!("database.replica_url", invalid_field(config));
+        let mut config = valid.clone();
+        config.database.replica_url = Some("postgres://replica/todos".to_string());
+        assert!(config.validate().is_ok());
+        config.database.replica_url = Some("sqlite:replica.db".to_string());
+        assert_eq!("database.replica_url", invalid_field(config));
+        let mut config = valid.clone();
+        config.database.event_sourcing = true;
+        assert!(config.validate().is_ok());
+        config.database.url = "sqlite:todos.db".to_string();
+        assert_eq!("database.event_sourcing", invalid_field(config));
+        let mut config = valid.clone();
+        config.database.statement_timeout_secs = Some(5);
+        assert!(config.validate().is_ok());
+        config.database.url = "sqlite:todos.db".to_string();
+        assert_eq!("database.statement_timeout_secs", invalid_field(config));
+        let mut config = valid.clone();
+        config.database.min_connections = config.database.max_connections + 1;
+        assert_eq!("database.min_connections", invalid_field(config));
+        let mut config = valid.clone();
+        config.cors.allowed_origins = vec!["localhost:3001".to_string()];
+        assert_eq!("cors.allowed_origins", invalid_field(config));
+        let mut config = valid.clone();
+        config.cors.allowed_origins = vec!["https://todo.example.com/".to_string()];
+        assert_eq!("cors.allowed_origins", invalid_field(config));
+        let mut config = valid.clone();
+        config.log.level = "info,todo=loud".to_string();
+        assert_eq!("log.level", invalid_field(config));
+        let mut config = valid.clone();
+        config.attachments.allowed_types = vec!["image".to_string()];
+        assert_eq!("attachments.allowed_types", invalid_field(config));
+        let mut config = valid.clone();
+        config.attachments.allowed_types.clear();
+        assert_eq!("attachments.allowed_types", invalid_field(config));
+        let mut config = valid.clone();
+        config.reminders.notifier = NotifierKind::Smtp;
+        config.reminders.smtp.from = Some("todo@example.com".to_string());
+        assert_eq!("reminders.smtp.to", invalid_field(config.clone()));
+        config.reminders.smtp.to = Some("team@example.com".to_string());
+        assert!(config.validate().is_ok());
+        let mut config = valid.clone();
+        config.reminders.notifier = NotifierKind::Webhook;
+        assert_eq!("reminders.webhook_url", invalid_field(config.clone()));
+        config.reminders.webhook_url = Some("hooks.example.com".to_string());
+        assert_eq!("reminders.webhook_url", invalid_field(config.clone()));
+        config.reminders.webhook_url = Some("https://hooks.example.com/todo".to_string());
+        assert!(config.validate().is_ok());
+        let mut config = valid.clone();
+        config.jwt.hs256_secret = Some(String::new());
+        assert_eq!("jwt.hs256_secret", invalid_field(config.clone()));
+        config.jwt.hs256_secret = Some("secret".to_string());
+        config.jwt.rs256_public_key = Some(PathBuf::from("jwt.pem"));
+        assert_eq!("jwt", invalid_field(config));
+        let mut config = valid;
+        config.cache.capacity = 0;
+        assert!(config.validate().is_ok());
+        config.cache.ttl_secs = 5;
+        assert_eq!("cache.capacity", invalid_field(config));
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn pg_session_test() {
+        dotenv::dotenv().ok();
+        let database = DatabaseConfig {
+            url: std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]"),
+            statement_timeout_secs: Some(3),
+            ..DatabaseConfig::default()
+        };
+        let options = database.pg_session(database.url.parse().unwrap());
+        let pool = database
+            .pool_options::<sqlx::Postgres>()
+            .connect_with(options)
+            .await
+            .expect("fail connect database");
+        let timeout: String = sqlx::query_scalar("show statement_timeout")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!("3s", timeout);
+    }
+}
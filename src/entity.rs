@@ -0,0 +1,95 @@
+//! what todos are read into, defined here once for the server and the
+//! benchmarks alike
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct Label {
+    pub name: String,
+    pub id: i32,
+}
+
+impl Label {
+    pub fn new(id: i32, name: String) -> Self {
+        Self { id, name }
+    }
+}
+
+/// checklist item owned by a TODO
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct TodoItem {
+    pub id: i32,
+    pub todo_id: i32,
+    pub text: String,
+    pub done: bool,
+    pub position: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TodoEntity {
+    pub text: String,
+    pub id: i32,
+    pub completed: bool,
+    pub labels: Vec<Label>,
+    pub items: Vec<TodoItem>,
+    /// true while any of `blocked_by` is not completed
+    pub blocked: bool,
+    pub blocked_by: Vec<i32>,
+}
+
+impl TodoEntity {
+    /// an open todo without items or blockers
+    pub fn new(id: i32, text: String, labels: Vec<Label>) -> Self {
+        Self {
+            id,
+            text,
+            completed: false,
+            labels,
+            items: vec![],
+            blocked: false,
+            blocked_by: vec![],
+        }
+    }
+}
+
+/// one entity per todo of the joined rows, in the order each todo first shows up,
+/// the rows of a todo need not be next to each other
+pub fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
+    let mut accum: Vec<TodoEntity> = vec![];
+    let mut positions: HashMap<i32, usize> = HashMap::new();
+
+    for row in rows {
+        let position = match positions.get(&row.id) {
+            Some(position) => *position,
+            None => {
+                positions.insert(row.id, accum.len());
+                accum.push(TodoEntity {
+                    text: row.text,
+                    id: row.id,
+                    completed: row.completed,
+                    labels: vec![],
+                    items: vec![],
+                    blocked: false,
+                    blocked_by: vec![],
+                });
+                accum.len() - 1
+            }
+        };
+        if let (Some(id), Some(name)) = (row.label_id, row.label_name) {
+            accum[position].labels.push(Label { id, name });
+        }
+    }
+
+    accum
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct TodoWithLabelFromRow {
+    pub text: String,
+    pub id: i32,
+    pub completed: bool,
+    pub label_id: Option<i32>,
+    pub label_name: Option<String>,
+}
@@ -1,5 +1,16 @@
+pub mod attachment;
+pub mod broadcast;
+pub mod collaborator;
+pub mod comment;
+pub mod health;
+pub mod item;
 pub mod label;
+pub mod reminder;
+pub mod session;
+pub mod socket;
 pub mod todo;
+pub mod token;
+pub mod user;
 
 use axum::{
     async_trait,
@@ -11,6 +22,8 @@ use axum::{
 use serde::de::DeserializeOwned;
 use validator::Validate;
 
+use crate::repositories::RepositoryError;
+
 #[derive(Debug)]
 pub struct ValidatedJson<T>(T);
 
@@ -29,10 +42,27 @@ where
             let message = format!("Json parse error: [{}]", rejection);
             (StatusCode::BAD_REQUEST, message)
         })?;
-        value.validate().map_err(|rejection| {
-            let message = format!("Validation error: [{}]", rejection).replace('\n', ", ");
-            (StatusCode::BAD_REQUEST, message)
-        })?;
-        Ok(ValidatedJson(value))
+        validated(value).map(ValidatedJson)
+    }
+}
+
+/// applies the validation rules of `T`, for payloads arriving other than as a request body
+pub(crate) fn validated<T: Validate>(value: T) -> Result<T, (StatusCode, String)> {
+    value.validate().map_err(|rejection| {
+        let message = format!("Validation error: [{}]", rejection).replace('\n', ", ");
+        (StatusCode::BAD_REQUEST, message)
+    })?;
+    Ok(value)
+}
+
+/// response status for an error returned from a repository
+pub(crate) fn error_status(e: &anyhow::Error) -> StatusCode {
+    match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND,
+        Some(RepositoryError::Duplicate(_))
+        | Some(RepositoryError::Cycle(_))
+        | Some(RepositoryError::Blocked(_)) => StatusCode::CONFLICT,
+        Some(RepositoryError::NoHistory(_)) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
@@ -0,0 +1,236 @@
+use axum::{
+    extract::{Extension, Multipart, Path},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use futures::StreamExt;
+use hyper::body::Bytes;
+use mime::Mime;
+use std::sync::Arc;
+
+use crate::{
+    auth::Collection,
+    config::AttachmentConfig,
+    repositories::{
+        attachment::{AttachmentRepository, CreateAttachment},
+        collaborator::Role,
+        todo::TodoRepository,
+        RepositoryError,
+    },
+    storage::Storage,
+};
+
+/// upload policy for attachments
+#[derive(Debug, Clone)]
+pub struct AttachmentLimits {
+    pub max_size: usize,
+    pub allowed_types: Vec<Mime>,
+}
+
+impl AttachmentLimits {
+    /// `type/*` entries allow every subtype
+    pub fn allows(&self, content_type: &Mime) -> bool {
+        self.allowed_types.iter().any(|allowed| {
+            allowed.type_() == content_type.type_()
+                && (allowed.subtype() == mime::STAR || allowed.subtype() == content_type.subtype())
+        })
+    }
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        let config = AttachmentConfig::default();
+        Self {
+            max_size: config.max_bytes,
+            allowed_types: config.mime_types(),
+        }
+    }
+}
+
+/// expects the file in a multipart field named `file`
+pub async fn create_attachment<T: TodoRepository, A: AttachmentRepository, S: Storage>(
+    collection: Collection,
+    Path(todo_id): Path<i32>,
+    mut multipart: Multipart,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<A>>,
+    Extension(storage): Extension<Arc<S>>,
+    Extension(limits): Extension<Arc<AttachmentLimits>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    collection
+        .require(Role::Editor)
+        .map_err(|status| (status, String::new()))?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err((StatusCode::NOT_FOUND, String::new())))?;
+
+    let bad_request = |e: axum::extract::multipart::MultipartError| {
+        (StatusCode::BAD_REQUEST, format!("Multipart error: [{}]", e))
+    };
+    let mut field = loop {
+        match multipart.next_field().await.map_err(bad_request)? {
+            Some(field) if field.name() == Some("file") => break field,
+            Some(_) => continue,
+            None => {
+                return Err((StatusCode::BAD_REQUEST, "Missing field: [file]".to_string()));
+            }
+        }
+    };
+
+    let content_type = field
+        .content_type()
+        .cloned()
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    if !limits.allows(&content_type) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Unsupported content type: [{}]", content_type),
+        ));
+    }
+    let file_name = field.file_name().unwrap_or("attachment").to_string();
+
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(bad_request)?;
+        if data.len() + chunk.len() > limits.max_size {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Over file size: [{} bytes]", limits.max_size),
+            ));
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    let internal_error = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let attachment = repository
+        .create(
+            todo_id,
+            CreateAttachment {
+                file_name,
+                content_type: content_type.to_string(),
+                size: data.len() as i64,
+            },
+        )
+        .await
+        .map_err(internal_error)?;
+    if let Err(e) = storage
+        .put(&attachment.storage_key(), Bytes::from(data))
+        .await
+    {
+        repository.delete(todo_id, attachment.id).await.ok();
+        return Err(internal_error(e));
+    }
+    // the todo may have been deleted since, along with the row and every blob
+    // stored before; this one came too late for that sweep
+    if let Err(e) = repository.find(todo_id, attachment.id).await {
+        if let Some(RepositoryError::NotFound(_)) = e.downcast_ref::<RepositoryError>() {
+            storage.delete(&attachment.storage_key()).await.ok();
+            return Err((StatusCode::NOT_FOUND, String::new()));
+        }
+    }
+
+    Ok((StatusCode::CREATED, Json(attachment)))
+}
+
+pub async fn all_attachment<T: TodoRepository, A: AttachmentRepository>(
+    collection: Collection,
+    Path(todo_id): Path<i32>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<A>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Viewer)?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let attachments = repository
+        .all(todo_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(attachments)))
+}
+
+pub async fn download_attachment<T: TodoRepository, A: AttachmentRepository, S: Storage>(
+    collection: Collection,
+    Path((todo_id, id)): Path<(i32, i32)>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<A>>,
+    Extension(storage): Extension<Arc<S>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Viewer)?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let attachment = repository
+        .find(todo_id, id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let data: Bytes = storage
+        .get(&attachment.storage_key())
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+
+    let content_type = HeaderValue::from_str(&attachment.content_type)
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    let disposition = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}\"",
+        attachment.file_name.replace(['"', '\\'], "_")
+    ))
+    .unwrap_or(HeaderValue::from_static("attachment"));
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, content_type);
+    headers.insert(header::CONTENT_DISPOSITION, disposition);
+    // the browser has to take the stored type as it is instead of guessing one
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    Ok((StatusCode::OK, headers, data))
+}
+
+pub async fn delete_attachment<T: TodoRepository, A: AttachmentRepository, S: Storage>(
+    collection: Collection,
+    Path((todo_id, id)): Path<(i32, i32)>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<A>>,
+    Extension(storage): Extension<Arc<S>>,
+) -> StatusCode {
+    if let Err(status) = collection.require(Role::Editor) {
+        return status;
+    }
+    if todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .is_err()
+    {
+        return StatusCode::NOT_FOUND;
+    }
+    let attachment = match repository.find(todo_id, id).await {
+        Ok(attachment) => attachment,
+        Err(_) => return StatusCode::NOT_FOUND,
+    };
+    if repository.delete(todo_id, id).await.is_err() {
+        return StatusCode::NOT_FOUND;
+    }
+    if let Err(e) = storage.delete(&attachment.storage_key()).await {
+        tracing::warn!("failed to remove blob {}: {}", attachment.storage_key(), e);
+    }
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits_allows_test() {
+        let limits = AttachmentLimits::default();
+        assert!(limits.allows(&mime::IMAGE_PNG));
+        assert!(limits.allows(&mime::TEXT_PLAIN_UTF_8));
+        assert!(limits.allows(&mime::APPLICATION_PDF));
+        assert!(!limits.allows(&mime::APPLICATION_OCTET_STREAM));
+    }
+}
@@ -0,0 +1,74 @@
+use axum::{
+    extract::Extension,
+    http::{HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream, StreamExt};
+use std::{convert::Infallible, sync::Arc};
+
+use crate::{
+    auth::{AuthUser, Collection},
+    repositories::{
+        broadcast::{Broadcast, Message},
+        collaborator::{CollaboratorRepository, Role},
+    },
+    shutdown::Shutdown,
+};
+
+/// sent by an `EventSource` when it reconnects
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// todo and label changes of the collection as server-sent events, after a
+/// `reset` event the collection has to be reloaded since changes were missed.
+/// a collaborator has the role checked again before each event, the stream
+/// ends once access to the collection is gone
+pub async fn events<C: CollaboratorRepository>(
+    user: AuthUser,
+    collection: Collection,
+    headers: HeaderMap,
+    Extension(collaborator_repository): Extension<Arc<C>>,
+    Extension(broadcast): Extension<Broadcast>,
+    Extension(shutdown): Extension<Shutdown>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    collection.require(Role::Viewer)?;
+    let last_event_id = headers
+        .get(LAST_EVENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let subscription =
+        broadcast.subscribe(collection.workspace_id, collection.owner_id, last_event_id);
+    let owner_id = collection.owner_id;
+    let events = stream::unfold(subscription, move |mut subscription| {
+        let collaborator_repository = collaborator_repository.clone();
+        async move {
+            let message = subscription.next().await?;
+            if owner_id != user.id {
+                match collaborator_repository.role(owner_id, user.id).await {
+                    Ok(Some(_)) => {}
+                    Ok(None) => return None,
+                    Err(e) => {
+                        tracing::error!("failed to check collaborator role: {}", e);
+                        return None;
+                    }
+                }
+            }
+            Some((Ok(sse_event(message)), subscription))
+        }
+    });
+    // an open stream would hold up the graceful shutdown until it times out
+    Ok(Sse::new(events.take_until(shutdown.wait())).keep_alive(KeepAlive::default()))
+}
+
+fn sse_event(message: Message) -> Event {
+    match message {
+        Message::Event(event) => Event::default()
+            .id(event.id.to_string())
+            .event(event.kind)
+            .data(event.data),
+        Message::Reset(Some(id)) => Event::default()
+            .id(id.to_string())
+            .event("reset")
+            .data("{}"),
+        Message::Reset(None) => Event::default().event("reset").data("{}"),
+    }
+}
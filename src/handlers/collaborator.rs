@@ -0,0 +1,53 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+
+use super::{error_status, ValidatedJson};
+use crate::{
+    auth::Collection,
+    repositories::collaborator::{CollaboratorRepository, Role, UpsertCollaborator},
+};
+
+pub async fn upsert_collaborator<C: CollaboratorRepository>(
+    collection: Collection,
+    ValidatedJson(payload): ValidatedJson<UpsertCollaborator>,
+    Extension(repository): Extension<Arc<C>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Owner)?;
+    let collaborator = repository
+        .upsert(collection.owner_id, payload)
+        .await
+        .map_err(|e| error_status(&e))?;
+    Ok((StatusCode::OK, Json(collaborator)))
+}
+
+pub async fn all_collaborator<C: CollaboratorRepository>(
+    collection: Collection,
+    Extension(repository): Extension<Arc<C>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Viewer)?;
+    let collaborators = repository
+        .all(collection.owner_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(collaborators)))
+}
+
+pub async fn delete_collaborator<C: CollaboratorRepository>(
+    collection: Collection,
+    Path(user_id): Path<i32>,
+    Extension(repository): Extension<Arc<C>>,
+) -> StatusCode {
+    if let Err(status) = collection.require(Role::Owner) {
+        return status;
+    }
+    repository
+        .delete(collection.owner_id, user_id)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or_else(|e| error_status(&e))
+}
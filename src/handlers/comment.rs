@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+
+use super::ValidatedJson;
+
+use crate::{
+    auth::Collection,
+    repositories::{
+        collaborator::Role,
+        comment::{CommentRepository, CreateComment, UpdateComment},
+        todo::TodoRepository,
+    },
+};
+
+pub async fn create_comment<T: TodoRepository, C: CommentRepository>(
+    collection: Collection,
+    Path(todo_id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<CreateComment>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<C>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let comment = repository
+        .create(todo_id, payload)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok((StatusCode::CREATED, Json(comment)))
+}
+
+pub async fn all_comment<T: TodoRepository, C: CommentRepository>(
+    collection: Collection,
+    Path(todo_id): Path<i32>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<C>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Viewer)?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let comments = repository
+        .all(todo_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(comments)))
+}
+
+pub async fn update_comment<T: TodoRepository, C: CommentRepository>(
+    collection: Collection,
+    Path((todo_id, id)): Path<(i32, i32)>,
+    ValidatedJson(payload): ValidatedJson<UpdateComment>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<C>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let comment = repository
+        .update(todo_id, id, payload)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    Ok((StatusCode::OK, Json(comment)))
+}
+
+pub async fn delete_comment<T: TodoRepository, C: CommentRepository>(
+    collection: Collection,
+    Path((todo_id, id)): Path<(i32, i32)>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<C>>,
+) -> StatusCode {
+    if let Err(status) = collection.require(Role::Editor) {
+        return status;
+    }
+    if todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .is_err()
+    {
+        return StatusCode::NOT_FOUND;
+    }
+    repository
+        .delete(todo_id, id)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or(StatusCode::NOT_FOUND)
+}
@@ -0,0 +1,122 @@
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use crate::{
+    repositories::{
+        cache::Cache,
+        health::{HealthRepository, MigrationStatus},
+    },
+    shutdown::Shutdown,
+};
+
+/// the readiness probe fails when the database takes longer than this
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    migrations: Option<MigrationStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Readiness {
+    fn unavailable(status: &'static str, error: Option<String>) -> (StatusCode, Json<Self>) {
+        let readiness = Self {
+            status,
+            migrations: None,
+            error,
+        };
+        (StatusCode::SERVICE_UNAVAILABLE, Json(readiness))
+    }
+}
+
+/// the process is up, says nothing about its dependencies
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+pub async fn readyz<H: HealthRepository>(
+    Extension(repository): Extension<Arc<H>>,
+    Extension(shutdown): Extension<Shutdown>,
+) -> impl IntoResponse {
+    if shutdown.is_triggered() {
+        return Readiness::unavailable("shutting down", None);
+    }
+    let migrations = match tokio::time::timeout(READINESS_TIMEOUT, repository.migrations()).await {
+        Ok(Ok(migrations)) => migrations,
+        Ok(Err(e)) => return Readiness::unavailable("unavailable", Some(e.to_string())),
+        Err(_) => {
+            let error = format!("database did not answer within {:?}", READINESS_TIMEOUT);
+            return Readiness::unavailable("unavailable", Some(error));
+        }
+    };
+    let status = if migrations.dirty {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    let readiness = Readiness {
+        status: if migrations.dirty { "dirty" } else { "ready" },
+        migrations: Some(migrations),
+        error: None,
+    };
+    (status, Json(readiness))
+}
+
+/// git sha and build time are set by the build script
+pub async fn version() -> impl IntoResponse {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("GIT_SHA"),
+        "build_time": env!("BUILD_TIME"),
+    }))
+}
+
+/// counters in the Prometheus text format
+pub async fn metrics(Extension(cache): Extension<Cache>) -> impl IntoResponse {
+    let repositories = [("todos", cache.todos()), ("labels", cache.labels())];
+    let mut body = String::new();
+    body.push_str("# HELP todo_cache_requests_total reads of the repository cache\n");
+    body.push_str("# TYPE todo_cache_requests_total counter\n");
+    for (repository, counters) in repositories {
+        for (result, count) in [("hit", counters.hits()), ("miss", counters.misses())] {
+            let _ = writeln!(
+                body,
+                "todo_cache_requests_total{{repository=\"{}\",result=\"{}\"}} {}",
+                repository, result, count
+            );
+        }
+    }
+    body.push_str("# HELP todo_cache_hit_ratio share of reads answered by the cache\n");
+    body.push_str("# TYPE todo_cache_hit_ratio gauge\n");
+    for (repository, counters) in repositories {
+        let _ = writeln!(
+            body,
+            "todo_cache_hit_ratio{{repository=\"{}\"}} {}",
+            repository,
+            counters.hit_ratio()
+        );
+    }
+    body.push_str("# HELP todo_cache_invalidations_total writes which dropped cached reads\n");
+    body.push_str("# TYPE todo_cache_invalidations_total counter\n");
+    let _ = writeln!(
+        body,
+        "todo_cache_invalidations_total {}",
+        cache.invalidations()
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    (headers, body)
+}
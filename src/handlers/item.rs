@@ -0,0 +1,78 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+
+use super::ValidatedJson;
+
+use crate::{
+    auth::Collection,
+    repositories::{
+        collaborator::Role,
+        item::{CreateItem, UpdateItem},
+        todo::TodoRepository,
+    },
+};
+
+pub async fn create_item<T: TodoRepository>(
+    collection: Collection,
+    Path(todo_id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<CreateItem>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
+    let item = repository
+        .create_item(
+            collection.workspace_id,
+            collection.owner_id,
+            todo_id,
+            payload,
+        )
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+
+    Ok((StatusCode::CREATED, Json(item)))
+}
+
+pub async fn update_item<T: TodoRepository>(
+    collection: Collection,
+    Path((todo_id, item_id)): Path<(i32, i32)>,
+    ValidatedJson(payload): ValidatedJson<UpdateItem>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
+    let item = repository
+        .update_item(
+            collection.workspace_id,
+            collection.owner_id,
+            todo_id,
+            item_id,
+            payload,
+        )
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    Ok((StatusCode::OK, Json(item)))
+}
+
+pub async fn delete_item<T: TodoRepository>(
+    collection: Collection,
+    Path((todo_id, item_id)): Path<(i32, i32)>,
+    Extension(repository): Extension<Arc<T>>,
+) -> StatusCode {
+    if let Err(status) = collection.require(Role::Editor) {
+        return status;
+    }
+    repository
+        .delete_item(
+            collection.workspace_id,
+            collection.owner_id,
+            todo_id,
+            item_id,
+        )
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or(StatusCode::NOT_FOUND)
+}
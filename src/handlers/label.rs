@@ -1,5 +1,11 @@
-use super::ValidatedJson;
-use crate::repositories::label::{CreateLabel, LabelRepository};
+use super::{error_status, ValidatedJson};
+use crate::{
+    auth::Collection,
+    repositories::{
+        collaborator::Role,
+        label::{CreateLabel, LabelRepository, UpdateLabel},
+    },
+};
 use axum::{
     extract::{Extension, Path},
     http::StatusCode,
@@ -9,11 +15,13 @@ use axum::{
 use std::sync::Arc;
 
 pub async fn create_label<T: LabelRepository>(
+    collection: Collection,
     ValidatedJson(payload): ValidatedJson<CreateLabel>,
     Extension(repository): Extension<Arc<T>>,
 ) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
     let label = repository
-        .create(payload.name)
+        .create(collection.workspace_id, collection.owner_id, payload.name)
         .await
         .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
 
@@ -21,19 +29,47 @@ pub async fn create_label<T: LabelRepository>(
 }
 
 pub async fn all_label<T: LabelRepository>(
+    collection: Collection,
     Extension(repository): Extension<Arc<T>>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let labels = repository.all().await.unwrap();
+    collection.require(Role::Viewer)?;
+    let labels = repository
+        .all(collection.workspace_id, collection.owner_id)
+        .await
+        .unwrap();
     Ok((StatusCode::OK, Json(labels)))
 }
 
+pub async fn update_label<T: LabelRepository>(
+    collection: Collection,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<UpdateLabel>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
+    let label = repository
+        .update(
+            collection.workspace_id,
+            collection.owner_id,
+            id,
+            payload.name,
+        )
+        .await
+        .map_err(|e| error_status(&e))?;
+    Ok((StatusCode::OK, Json(label)))
+}
+
 pub async fn delete_label<T: LabelRepository>(
+    collection: Collection,
     Path(id): Path<i32>,
     Extension(repository): Extension<Arc<T>>,
 ) -> StatusCode {
+    if let Err(status) = collection.require(Role::Editor) {
+        return status;
+    }
     repository
-        .delete(id)
+        .delete(collection.workspace_id, collection.owner_id, id)
         .await
         .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        .unwrap_or_else(|e| error_status(&e))
 }
@@ -0,0 +1,79 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+
+use super::ValidatedJson;
+
+use crate::{
+    auth::Collection,
+    repositories::{
+        collaborator::Role,
+        reminder::{CreateReminder, ReminderRepository},
+        todo::TodoRepository,
+    },
+};
+
+pub async fn create_reminder<T: TodoRepository, R: ReminderRepository>(
+    collection: Collection,
+    Path(todo_id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<CreateReminder>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<R>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let reminder = repository
+        .create(todo_id, payload)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok((StatusCode::CREATED, Json(reminder)))
+}
+
+pub async fn all_reminder<T: TodoRepository, R: ReminderRepository>(
+    collection: Collection,
+    Path(todo_id): Path<i32>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<R>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Viewer)?;
+    todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let reminders = repository
+        .all(todo_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(reminders)))
+}
+
+pub async fn delete_reminder<T: TodoRepository, R: ReminderRepository>(
+    collection: Collection,
+    Path((todo_id, id)): Path<(i32, i32)>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(repository): Extension<Arc<R>>,
+) -> StatusCode {
+    if let Err(status) = collection.require(Role::Editor) {
+        return status;
+    }
+    if todo_repository
+        .find(collection.workspace_id, collection.owner_id, todo_id)
+        .await
+        .is_err()
+    {
+        return StatusCode::NOT_FOUND;
+    }
+    repository
+        .delete(todo_id, id)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or(StatusCode::NOT_FOUND)
+}
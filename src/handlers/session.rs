@@ -0,0 +1,88 @@
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+
+use super::{user::check_credentials, ValidatedJson};
+use crate::{
+    auth::{generate_token, hash_token, SessionCookie},
+    repositories::{
+        session::{Session, SessionRepository},
+        user::{Credentials, UserRepository},
+        workspace::Workspace,
+    },
+};
+
+/// the token the frontend has to send in the CSRF header, the session itself
+/// only travels in the cookie
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    csrf_token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl From<Session> for SessionResponse {
+    fn from(session: Session) -> Self {
+        Self {
+            csrf_token: session.csrf_token,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+pub async fn create_session<U: UserRepository, S: SessionRepository>(
+    Extension(workspace): Extension<Workspace>,
+    ValidatedJson(payload): ValidatedJson<Credentials>,
+    Extension(user_repository): Extension<Arc<U>>,
+    Extension(session_repository): Extension<Arc<S>>,
+    Extension(cookie): Extension<Arc<SessionCookie>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let user = check_credentials(user_repository.as_ref(), &workspace, &payload).await?;
+    let token = generate_token();
+    let session = session_repository
+        .create(
+            user.id,
+            hash_token(&token),
+            generate_token(),
+            Utc::now() + cookie.ttl,
+        )
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::SET_COOKIE, cookie.set(&token));
+    Ok((
+        StatusCode::CREATED,
+        headers,
+        Json(SessionResponse::from(session)),
+    ))
+}
+
+/// lets a reloaded frontend recover the CSRF token of its session
+pub async fn find_session(
+    session: Option<Extension<Session>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let Extension(session) = session.ok_or(StatusCode::UNAUTHORIZED)?;
+    Ok((StatusCode::OK, Json(SessionResponse::from(session))))
+}
+
+pub async fn delete_session<S: SessionRepository>(
+    session: Option<Extension<Session>>,
+    Extension(repository): Extension<Arc<S>>,
+    Extension(cookie): Extension<Arc<SessionCookie>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let Extension(session) = session.ok_or(StatusCode::UNAUTHORIZED)?;
+    repository
+        .delete(session.id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::SET_COOKIE, cookie.clear());
+    Ok((StatusCode::NO_CONTENT, headers))
+}
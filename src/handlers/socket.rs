@@ -0,0 +1,498 @@
+//! a two-way channel to a collection: the client subscribes to the todos of some
+//! labels and sends todo writes over it, each acknowledged in the order sent
+
+use axum::{
+    extract::{
+        ws::{self, WebSocket, WebSocketUpgrade},
+        Extension, Query,
+    },
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use validator::Validate;
+
+use super::{error_status, validated};
+
+use crate::{
+    auth::{AuthUser, Collection, TokenScopes},
+    repositories::{
+        broadcast::{Broadcast, Event, Message, Subscription},
+        collaborator::{CollaboratorRepository, Role},
+        session::Session,
+        todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+        token::Scope,
+    },
+    shutdown::Shutdown,
+};
+
+/// larger messages close the socket, a todo write is far smaller
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// a client which takes no message for this long is disconnected, so a slow
+/// reader costs neither memory nor a stuck task
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// how often a collaborator who only listens has the role checked again, a
+/// request checks it right away
+const ROLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `?csrf_token=` of the session, browsers can not set headers on a socket
+#[derive(Debug, Deserialize)]
+pub struct Connect {
+    csrf_token: Option<String>,
+}
+
+/// what the client sends, `ref` is echoed in the reply to tell replies apart
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// changes of the todos carrying any of `labels` from now on, of all todos
+    /// without labels, replacing an earlier subscription
+    Subscribe {
+        #[serde(rename = "ref")]
+        reference: Option<Value>,
+        #[serde(default)]
+        labels: Vec<i32>,
+    },
+    Create {
+        #[serde(rename = "ref")]
+        reference: Option<Value>,
+        todo: Value,
+    },
+    Update {
+        #[serde(rename = "ref")]
+        reference: Option<Value>,
+        id: i32,
+        todo: Value,
+    },
+    Delete {
+        #[serde(rename = "ref")]
+        reference: Option<Value>,
+        id: i32,
+    },
+}
+
+impl Request {
+    fn reference(&self) -> Option<Value> {
+        match self {
+            Request::Subscribe { reference, .. }
+            | Request::Create { reference, .. }
+            | Request::Update { reference, .. }
+            | Request::Delete { reference, .. } => reference.clone(),
+        }
+    }
+}
+
+/// what the server sends, `reset` when changes were missed and the client has
+/// to subscribe again
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+    Subscribed {
+        #[serde(rename = "ref")]
+        reference: Option<Value>,
+        todos: Vec<TodoEntity>,
+    },
+    Ack {
+        #[serde(rename = "ref")]
+        reference: Option<Value>,
+        status: u16,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        todo: Option<TodoEntity>,
+    },
+    Error {
+        #[serde(rename = "ref")]
+        reference: Option<Value>,
+        status: u16,
+        message: String,
+    },
+    Event {
+        id: u64,
+        kind: &'static str,
+        data: Value,
+    },
+    Reset,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn socket<T: TodoRepository, C: CollaboratorRepository>(
+    upgrade: WebSocketUpgrade,
+    user: AuthUser,
+    collection: Collection,
+    Query(Connect { csrf_token }): Query<Connect>,
+    scopes: Option<Extension<TokenScopes>>,
+    session: Option<Extension<Session>>,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(collaborator_repository): Extension<Arc<C>>,
+    Extension(broadcast): Extension<Broadcast>,
+    Extension(shutdown): Extension<Shutdown>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Viewer)?;
+    // the upgrade is a GET, so what a write needs is only known here,
+    // `is_none_or` would need rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    let writable = scopes.map_or(true, |Extension(TokenScopes(scopes))| {
+        scopes.contains(&Scope::TodosWrite)
+    }) && session.map_or(true, |Extension(session)| {
+        csrf_token.as_deref() == Some(session.csrf_token.as_str())
+    });
+    let connection = Connection {
+        user_id: user.id,
+        collection,
+        writable,
+        repository,
+        collaborator_repository,
+        broadcast,
+        watch: None,
+    };
+    Ok(upgrade
+        .max_message_size(MAX_MESSAGE_SIZE)
+        .on_upgrade(move |socket| connection.run(socket, shutdown)))
+}
+
+struct Connection<T, C> {
+    user_id: i32,
+    /// the role is the one of the last check, a shared collection can be
+    /// taken away while the socket is open
+    collection: Collection,
+    writable: bool,
+    repository: Arc<T>,
+    collaborator_repository: Arc<C>,
+    broadcast: Broadcast,
+    watch: Option<Watch>,
+}
+
+impl<T: TodoRepository, C: CollaboratorRepository> Connection<T, C> {
+    /// one request is handled at a time and changes are only taken from the
+    /// broadcast as fast as the client reads them, a client falling too far
+    /// behind gets a `reset`
+    async fn run(mut self, mut socket: WebSocket, shutdown: Shutdown) {
+        let shutdown = shutdown.wait();
+        tokio::pin!(shutdown);
+        let mut role_check = tokio::time::interval(ROLE_CHECK_INTERVAL);
+        role_check.tick().await;
+        loop {
+            let reply = tokio::select! {
+                message = socket.recv() => match message {
+                    Some(Ok(ws::Message::Text(text))) => self.handle(&text).await,
+                    Some(Ok(ws::Message::Binary(_))) => Reply::Error {
+                        reference: None,
+                        status: StatusCode::BAD_REQUEST.as_u16(),
+                        message: "Expected a text message".to_string(),
+                    },
+                    // pings are answered on their own
+                    Some(Ok(ws::Message::Ping(_) | ws::Message::Pong(_))) => continue,
+                    Some(Ok(ws::Message::Close(_))) | None => return,
+                    Some(Err(e)) => {
+                        tracing::debug!("websocket failed: {}", e);
+                        return;
+                    }
+                },
+                reply = next(&mut self.watch) => reply,
+                _ = role_check.tick(), if self.watch.is_some() => {
+                    if let Err((status, _)) = self.check_role().await {
+                        if status == StatusCode::FORBIDDEN {
+                            let _ = socket.close().await;
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                _ = &mut shutdown => {
+                    let _ = socket.close().await;
+                    return;
+                }
+            };
+            if matches!(reply, Reply::Reset) {
+                self.watch = None;
+            }
+            if !send(&mut socket, &reply).await {
+                return;
+            }
+        }
+    }
+
+    async fn handle(&mut self, text: &str) -> Reply {
+        let request: Request = match serde_json::from_str(text) {
+            Ok(request) => request,
+            Err(e) => {
+                return Reply::Error {
+                    reference: None,
+                    status: StatusCode::BAD_REQUEST.as_u16(),
+                    message: format!("Json parse error: [{}]", e),
+                }
+            }
+        };
+        let reference = request.reference();
+        self.reply(request)
+            .await
+            .unwrap_or_else(|(status, message)| Reply::Error {
+                reference,
+                status: status.as_u16(),
+                message,
+            })
+    }
+
+    async fn reply(&mut self, request: Request) -> Result<Reply, (StatusCode, String)> {
+        self.check_role().await?;
+        let Collection {
+            workspace_id,
+            owner_id,
+            ..
+        } = self.collection;
+        match request {
+            Request::Subscribe { reference, labels } => {
+                // subscribed first, so no change gets lost between the two
+                let subscription = self.broadcast.subscribe(workspace_id, owner_id, None);
+                let mut filter = Filter::new(labels);
+                let todos: Vec<TodoEntity> = self
+                    .repository
+                    .all(workspace_id, owner_id)
+                    .await
+                    .map_err(|e| reason(error_status(&e)))?
+                    .into_iter()
+                    .filter(|todo| filter.hold(todo.id, todo.labels.iter().map(|label| label.id)))
+                    .collect();
+                self.watch = Some(Watch {
+                    subscription,
+                    filter,
+                });
+                Ok(Reply::Subscribed { reference, todos })
+            }
+            Request::Create { reference, todo } => {
+                self.require_write()?;
+                let payload: CreateTodo = payload(todo)?;
+                let todo = self
+                    .repository
+                    .create(workspace_id, owner_id, payload)
+                    .await
+                    .or(Err(reason(StatusCode::NOT_FOUND)))?;
+                Ok(ack(reference, StatusCode::CREATED, Some(todo)))
+            }
+            Request::Update {
+                reference,
+                id,
+                todo,
+            } => {
+                self.require_write()?;
+                let payload: UpdateTodo = payload(todo)?;
+                let todo = self
+                    .repository
+                    .update(workspace_id, owner_id, id, payload)
+                    .await
+                    .map_err(|e| reason(error_status(&e)))?;
+                Ok(ack(reference, StatusCode::OK, Some(todo)))
+            }
+            Request::Delete { reference, id } => {
+                self.require_write()?;
+                self.repository
+                    .delete(workspace_id, owner_id, id)
+                    .await
+                    .map_err(|e| reason(error_status(&e)))?;
+                Ok(ack(reference, StatusCode::NO_CONTENT, None))
+            }
+        }
+    }
+
+    /// the role of a collaborator as it is now, 403 once the collection is no
+    /// longer shared with the user, who then gets no more changes either
+    async fn check_role(&mut self) -> Result<(), (StatusCode, String)> {
+        if self.collection.owner_id == self.user_id {
+            return Ok(());
+        }
+        match self
+            .collaborator_repository
+            .role(self.collection.owner_id, self.user_id)
+            .await
+        {
+            Ok(Some(role)) => {
+                self.collection.role = role;
+                Ok(())
+            }
+            Ok(None) => {
+                self.watch = None;
+                Err(reason(StatusCode::FORBIDDEN))
+            }
+            Err(e) => {
+                tracing::error!("failed to check collaborator role: {}", e);
+                Err(reason(StatusCode::INTERNAL_SERVER_ERROR))
+            }
+        }
+    }
+
+    /// 403 for viewers, for personal access tokens without `todos:write` and
+    /// for sessions which did not connect with their CSRF token
+    fn require_write(&self) -> Result<(), (StatusCode, String)> {
+        if !self.writable {
+            return Err(reason(StatusCode::FORBIDDEN));
+        }
+        self.collection.require(Role::Editor).map_err(reason)
+    }
+}
+
+fn ack(reference: Option<Value>, status: StatusCode, todo: Option<TodoEntity>) -> Reply {
+    Reply::Ack {
+        reference,
+        status: status.as_u16(),
+        todo,
+    }
+}
+
+fn reason(status: StatusCode) -> (StatusCode, String) {
+    let reason = status.canonical_reason().unwrap_or_default().to_string();
+    (status, reason)
+}
+
+/// parses and validates a todo the way `ValidatedJson` does a request body
+fn payload<P: DeserializeOwned + Validate>(value: Value) -> Result<P, (StatusCode, String)> {
+    let payload = serde_json::from_value(value).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Json parse error: [{}]", e),
+        )
+    })?;
+    validated(payload)
+}
+
+/// false once the client is gone or stopped reading
+async fn send(socket: &mut WebSocket, reply: &Reply) -> bool {
+    let text = match serde_json::to_string(reply) {
+        Ok(text) => text,
+        Err(e) => {
+            tracing::error!("failed to serialize websocket reply: {}", e);
+            return true;
+        }
+    };
+    match tokio::time::timeout(SEND_TIMEOUT, socket.send(ws::Message::Text(text))).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            tracing::debug!("websocket failed: {}", e);
+            false
+        }
+        Err(_) => {
+            tracing::debug!("dropped a websocket which stopped reading");
+            false
+        }
+    }
+}
+
+struct Watch {
+    subscription: Subscription,
+    filter: Filter,
+}
+
+/// the next change for the client, never without a subscription
+async fn next(watch: &mut Option<Watch>) -> Reply {
+    let watch = match watch {
+        Some(watch) => watch,
+        None => return std::future::pending().await,
+    };
+    loop {
+        match watch.subscription.next().await {
+            Some(Message::Event(event)) => {
+                if let Some(data) = watch.filter.admit(&event) {
+                    return Reply::Event {
+                        id: event.id,
+                        kind: event.kind,
+                        data,
+                    };
+                }
+            }
+            Some(Message::Reset(_)) => return Reply::Reset,
+            None => return std::future::pending().await,
+        }
+    }
+}
+
+/// which changes reach a client, every change of the collection without labels
+#[derive(Debug, Default)]
+struct Filter {
+    labels: HashSet<i32>,
+    /// todos the client holds, which it hears of again when they lose their
+    /// labels or are deleted
+    todos: HashSet<i32>,
+}
+
+impl Filter {
+    fn new(labels: Vec<i32>) -> Self {
+        Self {
+            labels: labels.into_iter().collect(),
+            todos: HashSet::new(),
+        }
+    }
+
+    /// whether a todo with these labels is of interest, remembering it if so
+    fn hold(&mut self, id: i32, mut labels: impl Iterator<Item = i32>) -> bool {
+        if self.labels.is_empty() || labels.any(|label| self.labels.contains(&label)) {
+            self.todos.insert(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// the data of the event when the client is to receive it
+    fn admit(&mut self, event: &Event) -> Option<Value> {
+        let data: Value = serde_json::from_str(&event.data).ok()?;
+        let id = data["id"].as_i64()? as i32;
+        let admitted = match event.kind {
+            "todo.created" | "todo.updated" => {
+                let labels = data["labels"].as_array().cloned().unwrap_or_default();
+                let labels = labels
+                    .iter()
+                    .filter_map(|label| label["id"].as_i64())
+                    .map(|label| label as i32);
+                // a todo which just lost its last watched label is sent once more
+                self.hold(id, labels) || self.todos.remove(&id)
+            }
+            "todo.deleted" => self.todos.remove(&id) || self.labels.is_empty(),
+            _ => self.labels.is_empty() || self.labels.contains(&id),
+        };
+        admitted.then_some(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(kind: &'static str, data: &str) -> Event {
+        Event {
+            id: 1,
+            workspace_id: 1,
+            owner_id: 1,
+            kind,
+            data: data.to_string(),
+        }
+    }
+
+    fn admits(filter: &mut Filter, kind: &'static str, data: &str) -> bool {
+        filter.admit(&event(kind, data)).is_some()
+    }
+
+    #[test]
+    fn filter_test() {
+        let mut all = Filter::new(vec![]);
+        assert!(admits(&mut all, "todo.created", r#"{"id":1,"labels":[]}"#));
+        assert!(admits(&mut all, "todo.deleted", r#"{"id":2}"#));
+        assert!(admits(&mut all, "label.created", r#"{"id":3}"#));
+
+        let mut work = Filter::new(vec![7]);
+        let labelled = r#"{"id":1,"labels":[{"id":7,"name":"work"}]}"#;
+        let unlabelled = r#"{"id":1,"labels":[]}"#;
+        assert!(!admits(&mut work, "todo.created", unlabelled));
+        assert!(admits(&mut work, "todo.updated", labelled));
+        // told once that it left the label, then no more
+        assert!(admits(&mut work, "todo.updated", unlabelled));
+        assert!(!admits(&mut work, "todo.updated", unlabelled));
+        assert!(!admits(&mut work, "todo.deleted", r#"{"id":1}"#));
+        assert!(admits(&mut work, "todo.created", labelled));
+        assert!(admits(&mut work, "todo.deleted", r#"{"id":1}"#));
+
+        assert!(admits(&mut work, "label.deleted", r#"{"id":7}"#));
+        assert!(!admits(&mut work, "label.created", r#"{"id":8}"#));
+    }
+}
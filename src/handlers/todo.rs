@@ -1,21 +1,46 @@
 use axum::{
-    extract::{Extension, Path},
+    extract::{Extension, Path, Query},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use super::ValidatedJson;
+use super::{error_status, ValidatedJson};
 
-use crate::repositories::todo::{CreateTodo, TodoRepository, UpdateTodo};
+use crate::{
+    auth::Collection,
+    repositories::{
+        collaborator::Role,
+        comment::CommentRepository,
+        todo::{AddBlocker, CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+    },
+};
+
+/// TODO listed together with the size of its comment thread
+#[derive(Debug, Serialize)]
+pub struct TodoSummary {
+    #[serde(flatten)]
+    todo: TodoEntity,
+    comment_count: i64,
+}
+
+/// `?as_of=2023-06-16T09:00:00Z` asks for the TODO as it was back then
+#[derive(Debug, Deserialize)]
+pub struct AsOf {
+    as_of: Option<DateTime<Utc>>,
+}
 
 pub async fn create_todo<T: TodoRepository>(
+    collection: Collection,
     ValidatedJson(payload): ValidatedJson<CreateTodo>,
     Extension(repository): Extension<Arc<T>>,
 ) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
     let todo = repository
-        .create(payload)
+        .create(collection.workspace_id, collection.owner_id, payload)
         .await
         .or(Err(StatusCode::NOT_FOUND))?;
 
@@ -23,38 +48,108 @@ pub async fn create_todo<T: TodoRepository>(
 }
 
 pub async fn find_todo<T: TodoRepository>(
+    collection: Collection,
     Path(id): Path<i32>,
+    Query(AsOf { as_of }): Query<AsOf>,
     Extension(repository): Extension<Arc<T>>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repository.find(id).await.or(Err(StatusCode::NOT_FOUND))?;
+    collection.require(Role::Viewer)?;
+    let todo = match as_of {
+        Some(at) => repository
+            .find_as_of(collection.workspace_id, collection.owner_id, id, at)
+            .await
+            .map_err(|e| error_status(&e))?,
+        None => repository
+            .find(collection.workspace_id, collection.owner_id, id)
+            .await
+            .or(Err(StatusCode::NOT_FOUND))?,
+    };
     Ok((StatusCode::OK, Json(todo)))
 }
 
-pub async fn all_todo<T: TodoRepository>(
+pub async fn all_todo<T: TodoRepository, C: CommentRepository>(
+    collection: Collection,
     Extension(repository): Extension<Arc<T>>,
+    Extension(comment_repository): Extension<Arc<C>>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let todos = repository.all().await.unwrap();
+    collection.require(Role::Viewer)?;
+    let todos = repository
+        .all(collection.workspace_id, collection.owner_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+    let counts = comment_repository
+        .count_by_todo(&ids)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    let todos: Vec<TodoSummary> = todos
+        .into_iter()
+        .map(|todo| TodoSummary {
+            comment_count: counts.get(&todo.id).copied().unwrap_or(0),
+            todo,
+        })
+        .collect();
     Ok((StatusCode::OK, Json(todos)))
 }
 
 pub async fn update_todo<T: TodoRepository>(
+    collection: Collection,
     Path(id): Path<i32>,
     ValidatedJson(payload): ValidatedJson<UpdateTodo>,
     Extension(repository): Extension<Arc<T>>,
 ) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
     let todo = repository
-        .update(id, payload)
+        .update(collection.workspace_id, collection.owner_id, id, payload)
         .await
-        .or(Err(StatusCode::NOT_FOUND))?;
+        .map_err(|e| error_status(&e))?;
+    Ok((StatusCode::CREATED, Json(todo)))
+}
+
+pub async fn add_blocker<T: TodoRepository>(
+    collection: Collection,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<AddBlocker>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    collection.require(Role::Editor)?;
+    let todo = repository
+        .add_blocker(
+            collection.workspace_id,
+            collection.owner_id,
+            id,
+            payload.blocker_id,
+        )
+        .await
+        .map_err(|e| error_status(&e))?;
     Ok((StatusCode::CREATED, Json(todo)))
 }
 
+pub async fn remove_blocker<T: TodoRepository>(
+    collection: Collection,
+    Path((id, blocker_id)): Path<(i32, i32)>,
+    Extension(repository): Extension<Arc<T>>,
+) -> StatusCode {
+    if let Err(status) = collection.require(Role::Editor) {
+        return status;
+    }
+    repository
+        .remove_blocker(collection.workspace_id, collection.owner_id, id, blocker_id)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or(StatusCode::NOT_FOUND)
+}
+
 pub async fn delete_todo<T: TodoRepository>(
+    collection: Collection,
     Path(id): Path<i32>,
     Extension(repository): Extension<Arc<T>>,
 ) -> StatusCode {
+    if let Err(status) = collection.require(Role::Editor) {
+        return status;
+    }
     repository
-        .delete(id)
+        .delete(collection.workspace_id, collection.owner_id, id)
         .await
         .map(|_| StatusCode::NO_CONTENT)
         .unwrap_or(StatusCode::NOT_FOUND)
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use super::{error_status, ValidatedJson};
+use crate::{
+    auth::{generate_personal_token, hash_token, AuthUser, TokenScopes},
+    repositories::token::{CreatePersonalToken, PersonalToken, PersonalTokenRepository},
+};
+
+/// the created token, the only time its secret is shown
+#[derive(Debug, Serialize)]
+pub struct CreatedPersonalToken {
+    #[serde(flatten)]
+    personal_token: PersonalToken,
+    token: String,
+}
+
+/// personal access tokens can not manage tokens, a leaked one could widen its own scopes
+fn forbid_personal_token(scopes: Option<Extension<TokenScopes>>) -> Result<(), StatusCode> {
+    match scopes {
+        Some(_) => Err(StatusCode::FORBIDDEN),
+        None => Ok(()),
+    }
+}
+
+pub async fn create_personal_token<P: PersonalTokenRepository>(
+    user: AuthUser,
+    scopes: Option<Extension<TokenScopes>>,
+    ValidatedJson(payload): ValidatedJson<CreatePersonalToken>,
+    Extension(repository): Extension<Arc<P>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    forbid_personal_token(scopes)?;
+    let token = generate_personal_token();
+    let personal_token = repository
+        .create(user.id, hash_token(&token), payload)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedPersonalToken {
+            personal_token,
+            token,
+        }),
+    ))
+}
+
+pub async fn all_personal_token<P: PersonalTokenRepository>(
+    user: AuthUser,
+    scopes: Option<Extension<TokenScopes>>,
+    Extension(repository): Extension<Arc<P>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    forbid_personal_token(scopes)?;
+    let tokens = repository
+        .all(user.id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(tokens)))
+}
+
+pub async fn delete_personal_token<P: PersonalTokenRepository>(
+    user: AuthUser,
+    scopes: Option<Extension<TokenScopes>>,
+    Path(id): Path<i32>,
+    Extension(repository): Extension<Arc<P>>,
+) -> StatusCode {
+    if let Err(status) = forbid_personal_token(scopes) {
+        return status;
+    }
+    repository
+        .delete(user.id, id)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or_else(|e| error_status(&e))
+}
@@ -0,0 +1,68 @@
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+
+use super::{error_status, ValidatedJson};
+use crate::{
+    auth::{generate_token, hash_password, hash_token, verify_password},
+    repositories::{
+        user::{Credentials, User, UserRepository},
+        workspace::Workspace,
+    },
+};
+
+/// login tokens stop working after this many days
+const TOKEN_TTL_DAYS: i64 = 30;
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    token: String,
+    expires_at: chrono::DateTime<Utc>,
+}
+
+/// users register in the workspace the request was resolved to
+pub async fn create_user<U: UserRepository>(
+    Extension(workspace): Extension<Workspace>,
+    ValidatedJson(payload): ValidatedJson<Credentials>,
+    Extension(repository): Extension<Arc<U>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let password_hash =
+        hash_password(&payload.password).or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    let user = repository
+        .create(workspace.id, payload.name, password_hash)
+        .await
+        .map_err(|e| error_status(&e))?;
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+/// user of the credentials in the workspace, 401 when the name or password is wrong
+pub(crate) async fn check_credentials<U: UserRepository>(
+    repository: &U,
+    workspace: &Workspace,
+    credentials: &Credentials,
+) -> Result<User, StatusCode> {
+    let user = repository
+        .find_by_name(workspace.id, &credentials.name)
+        .await
+        .or(Err(StatusCode::UNAUTHORIZED))?;
+    if !verify_password(&credentials.password, &user.password_hash) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(user)
+}
+
+pub async fn login<U: UserRepository>(
+    Extension(workspace): Extension<Workspace>,
+    ValidatedJson(payload): ValidatedJson<Credentials>,
+    Extension(repository): Extension<Arc<U>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let user = check_credentials(repository.as_ref(), &workspace, &payload).await?;
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::days(TOKEN_TTL_DAYS);
+    repository
+        .create_token(user.id, hash_token(&token), expires_at)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(LoginResponse { token, expires_at })))
+}
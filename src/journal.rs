@@ -0,0 +1,620 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+const SNAPSHOT: &str = "snapshot.json";
+const JOURNAL: &str = "journal.log";
+const LOCK: &str = "journal.lock";
+/// entries appended before they are folded into a new snapshot
+const COMPACT_AFTER: usize = 10_000;
+
+#[derive(Debug, Error)]
+pub enum JournalError {
+    #[error("{path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{path} is corrupt at line {line}: {source}")]
+    Corrupt {
+        path: PathBuf,
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("invalid row in table {table}: {source}")]
+    Row {
+        table: String,
+        source: serde_json::Error,
+    },
+    #[error("{path} is held by another process")]
+    Locked { path: PathBuf },
+    #[error("journal write did not finish: {0}")]
+    Interrupted(#[from] tokio::task::JoinError),
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> JournalError + '_ {
+    move |source| JournalError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// a table as it is written to disk, keys are the JSON text of the typed key
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RawTable {
+    last_id: i32,
+    rows: BTreeMap<String, Value>,
+}
+
+/// one committed transaction, a line of the journal
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    table: String,
+    last_id: i32,
+    /// `None` deletes the row
+    rows: Vec<(String, Option<Value>)>,
+}
+
+fn apply(tables: &mut BTreeMap<String, RawTable>, entry: Entry) {
+    let table = tables.entry(entry.table).or_default();
+    table.last_id = table.last_id.max(entry.last_id);
+    for (key, row) in entry.rows {
+        match row {
+            Some(row) => table.rows.insert(key, row),
+            None => table.rows.remove(&key),
+        };
+    }
+}
+
+/// takes an exclusive lock on `journal.lock` in `dir`, held as long as the
+/// returned file is open and released by the kernel when the process dies
+fn lock(dir: &Path) -> Result<File, JournalError> {
+    let path = dir.join(LOCK);
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(io_error(&path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        // SAFETY: the descriptor belongs to `file`, which is open
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let e = io::Error::last_os_error();
+            return Err(match e.kind() {
+                ErrorKind::WouldBlock => JournalError::Locked { path },
+                _ => io_error(&path)(e),
+            });
+        }
+    }
+    Ok(file)
+}
+
+/// the file side of a journal, `dir` is `None` for one that only lives in memory
+#[derive(Default)]
+struct Log {
+    dir: Option<PathBuf>,
+    file: Option<File>,
+    /// keeps other processes from opening the same journal
+    _lock: Option<File>,
+    /// what the snapshot and the journal add up to, written out on compaction
+    tables: BTreeMap<String, RawTable>,
+    appended: usize,
+}
+
+impl Log {
+    /// the entry is on disk once this returns, a failed write is cut off again
+    fn append(&mut self, entry: Entry) -> Result<(), JournalError> {
+        let (Some(dir), Some(file)) = (&self.dir, &mut self.file) else {
+            return Ok(());
+        };
+        let path = dir.join(JOURNAL);
+        let mut line = serde_json::to_vec(&entry).map_err(|source| JournalError::Row {
+            table: entry.table.clone(),
+            source,
+        })?;
+        line.push(b'\n');
+        let len = file.metadata().map_err(io_error(&path))?.len();
+        if let Err(e) = file.write_all(&line).and_then(|_| file.sync_data()) {
+            let _ = file.set_len(len);
+            return Err(io_error(&path)(e));
+        }
+        apply(&mut self.tables, entry);
+        self.appended += 1;
+        if self.appended >= COMPACT_AFTER {
+            // the entry is durable already, a failed compaction only leaves a longer journal
+            if let Err(e) = self.compact() {
+                tracing::warn!("journal compaction failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// writes the tables aside, renames them over the snapshot and empties the journal,
+    /// a crash in between replays entries the snapshot has already, which changes nothing
+    fn compact(&mut self) -> Result<(), JournalError> {
+        let (Some(dir), Some(file)) = (&self.dir, &mut self.file) else {
+            return Ok(());
+        };
+        let snapshot = dir.join(SNAPSHOT);
+        let tmp = snapshot.with_extension("json.tmp");
+        let data = serde_json::to_vec(&self.tables).map_err(|source| JournalError::Row {
+            table: String::new(),
+            source,
+        })?;
+        let mut out = File::create(&tmp).map_err(io_error(&tmp))?;
+        out.write_all(&data)
+            .and_then(|_| out.sync_all())
+            .map_err(io_error(&tmp))?;
+        fs::rename(&tmp, &snapshot).map_err(io_error(&snapshot))?;
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+        let journal = dir.join(JOURNAL);
+        file.set_len(0)
+            .and_then(|_| file.sync_all())
+            .map_err(io_error(&journal))?;
+        self.appended = 0;
+        Ok(())
+    }
+}
+
+/// embedded store of typed tables kept in memory, every committed write is
+/// appended to `journal.log` and the journal is folded into `snapshot.json`
+/// on startup and every `COMPACT_AFTER` entries
+#[derive(Clone, Default)]
+pub struct Journal {
+    dir: Option<PathBuf>,
+    /// only locked on blocking threads once the journal is open, an append holds
+    /// it for the fsync
+    log: Arc<Mutex<Log>>,
+    /// opened tables by name, repositories opening the same name share the rows
+    tables: Arc<Mutex<HashMap<String, Arc<dyn Any + Send + Sync>>>>,
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Journal").field("dir", &self.dir).finish()
+    }
+}
+
+impl Journal {
+    /// a journal that keeps nothing on disk
+    #[cfg(test)]
+    pub fn memory() -> Self {
+        Self::default()
+    }
+
+    /// recovers the tables from the snapshot and the journal in `dir`, created when missing
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, JournalError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(io_error(&dir))?;
+        let lock = lock(&dir)?;
+
+        let snapshot = dir.join(SNAPSHOT);
+        let mut tables = match fs::read(&snapshot) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|source| JournalError::Corrupt {
+                path: snapshot.clone(),
+                line: 1,
+                source,
+            })?,
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(io_error(&snapshot)(e)),
+        };
+
+        let journal = dir.join(JOURNAL);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&journal)
+            .map_err(io_error(&journal))?;
+        let mut data = vec![];
+        file.read_to_end(&mut data).map_err(io_error(&journal))?;
+        let mut replayed = 0;
+        let mut offset = 0;
+        for (index, line) in data.split_inclusive(|b| *b == b'\n').enumerate() {
+            if !line.ends_with(b"\n") {
+                // a crash while appending leaves a torn last line, the commit
+                // it belongs to never returned so it is dropped
+                tracing::warn!("dropping torn entry at the end of {}", journal.display());
+                file.set_len(offset as u64).map_err(io_error(&journal))?;
+                break;
+            }
+            let entry = serde_json::from_slice(line).map_err(|source| JournalError::Corrupt {
+                path: journal.clone(),
+                line: index + 1,
+                source,
+            })?;
+            apply(&mut tables, entry);
+            offset += line.len();
+            replayed += 1;
+        }
+
+        let mut log = Log {
+            dir: Some(dir.clone()),
+            file: Some(file),
+            _lock: Some(lock),
+            tables,
+            appended: replayed,
+        };
+        if replayed > 0 {
+            tracing::info!("recovered {} journal entries", replayed);
+            log.compact()?;
+        }
+        Ok(Self {
+            dir: Some(dir),
+            log: Arc::new(Mutex::new(log)),
+            tables: Arc::default(),
+        })
+    }
+
+    /// fails when the journal can not be reached anymore, e.g. its directory was removed
+    pub fn check(&self) -> Result<(), JournalError> {
+        if let Some(dir) = &self.dir {
+            let path = dir.join(JOURNAL);
+            fs::metadata(&path).map_err(io_error(&path))?;
+        }
+        Ok(())
+    }
+
+    /// the table `name`, loaded from disk on first use
+    pub fn table<K, V>(&self, name: &str) -> Result<Table<K, V>, JournalError>
+    where
+        K: Serialize + DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+        V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    {
+        let mut tables = self.tables.lock().unwrap();
+        let data = match tables.get(name) {
+            Some(data) => data
+                .clone()
+                .downcast::<RwLock<TableData<K, V>>>()
+                .unwrap_or_else(|_| panic!("table {} is opened with another row type", name)),
+            None => {
+                let log = self.log.lock().unwrap();
+                let mut data = TableData::default();
+                if let Some(raw) = log.tables.get(name) {
+                    let invalid = |source| JournalError::Row {
+                        table: name.to_string(),
+                        source,
+                    };
+                    data.last_id = raw.last_id;
+                    for (key, row) in &raw.rows {
+                        data.rows.insert(
+                            serde_json::from_str(key).map_err(invalid)?,
+                            serde_json::from_value(row.clone()).map_err(invalid)?,
+                        );
+                    }
+                }
+                let data = Arc::new(RwLock::new(data));
+                tables.insert(name.to_string(), data.clone());
+                data
+            }
+        };
+        Ok(Table {
+            name: name.into(),
+            data,
+            log: self.log.clone(),
+        })
+    }
+}
+
+struct TableData<K, V> {
+    rows: BTreeMap<K, V>,
+    /// ids handed out by `next_id`, deleting rows never gives them back
+    last_id: i32,
+}
+
+impl<K, V> Default for TableData<K, V> {
+    fn default() -> Self {
+        Self {
+            rows: BTreeMap::new(),
+            last_id: 0,
+        }
+    }
+}
+
+/// rows of one kind, reads share a lock and writes go through a `Transaction`
+pub struct Table<K, V> {
+    name: Arc<str>,
+    data: Arc<RwLock<TableData<K, V>>>,
+    log: Arc<Mutex<Log>>,
+}
+
+impl<K, V> Clone for Table<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            data: self.data.clone(),
+            log: self.log.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Table<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Table").field("name", &self.name).finish()
+    }
+}
+
+impl<K, V> Table<K, V>
+where
+    K: Serialize + Ord + Clone,
+    V: Serialize + Clone,
+{
+    pub async fn read(&self) -> Rows<'_, K, V> {
+        Rows(self.data.read().await)
+    }
+
+    /// holds the table until the transaction is committed or dropped
+    pub async fn write(&self) -> Transaction<'_, K, V> {
+        Transaction {
+            table: self,
+            data: self.data.write().await,
+            undo: BTreeMap::new(),
+        }
+    }
+
+    /// `write` unless the table is held, for seeding it while it is opened
+    pub fn try_write(&self) -> Option<Transaction<'_, K, V>> {
+        Some(Transaction {
+            table: self,
+            data: self.data.try_write().ok()?,
+            undo: BTreeMap::new(),
+        })
+    }
+}
+
+pub struct Rows<'a, K, V>(RwLockReadGuard<'a, TableData<K, V>>);
+
+impl<K, V> Deref for Rows<'_, K, V> {
+    type Target = BTreeMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.rows
+    }
+}
+
+/// changes to a table, written to the journal by `commit` and undone when
+/// dropped without it, so an early return leaves the table as it was
+pub struct Transaction<'a, K, V>
+where
+    K: Serialize + Ord + Clone,
+    V: Serialize + Clone,
+{
+    table: &'a Table<K, V>,
+    data: RwLockWriteGuard<'a, TableData<K, V>>,
+    /// rows before the transaction touched them, `None` for added ones
+    undo: BTreeMap<K, Option<V>>,
+}
+
+impl<K, V> Deref for Transaction<'_, K, V>
+where
+    K: Serialize + Ord + Clone,
+    V: Serialize + Clone,
+{
+    type Target = BTreeMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data.rows
+    }
+}
+
+impl<K, V> Transaction<'_, K, V>
+where
+    K: Serialize + Ord + Clone,
+    V: Serialize + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        if !self.undo.contains_key(key) {
+            let before = self.data.rows.get(key).cloned();
+            self.undo.insert(key.clone(), before);
+        }
+    }
+
+    /// an id no row of this table had before
+    pub fn next_id(&mut self) -> i32 {
+        self.data.last_id += 1;
+        self.data.last_id
+    }
+
+    pub fn insert(&mut self, key: K, row: V) -> Option<V> {
+        self.touch(&key);
+        self.data.rows.insert(key, row)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.data.rows.contains_key(key) {
+            return None;
+        }
+        self.touch(key);
+        self.data.rows.get_mut(key)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.touch(key);
+        self.data.rows.remove(key)
+    }
+
+    /// the entry `commit` writes, `None` when nothing changed
+    fn entry(&self) -> Result<Option<Entry>, JournalError> {
+        if self.undo.is_empty() {
+            return Ok(None);
+        }
+        let rows = self
+            .undo
+            .keys()
+            .map(|key| {
+                let row = self.data.rows.get(key).map(serde_json::to_value);
+                Ok((serde_json::to_string(key)?, row.transpose()?))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()
+            .map_err(|source| JournalError::Row {
+                table: self.table.name.to_string(),
+                source,
+            })?;
+        Ok(Some(Entry {
+            table: self.table.name.to_string(),
+            last_id: self.data.last_id,
+            rows,
+        }))
+    }
+
+    /// the append and a compaction it triggers run on a blocking thread, the table
+    /// stays locked until the entry is on disk. dropped while waiting, the changes
+    /// are kept as the entry is written anyway
+    pub async fn commit(mut self) -> Result<(), JournalError> {
+        let Some(entry) = self.entry()? else {
+            return Ok(());
+        };
+        let undo = std::mem::take(&mut self.undo);
+        let log = self.table.log.clone();
+        let result = tokio::task::spawn_blocking(move || log.lock().unwrap().append(entry))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        if result.is_err() {
+            self.undo = undo;
+        }
+        result
+    }
+
+    /// `commit` on the calling thread, for tables seeded while they are opened
+    pub fn commit_blocking(mut self) -> Result<(), JournalError> {
+        let Some(entry) = self.entry()? else {
+            return Ok(());
+        };
+        self.table.log.lock().unwrap().append(entry)?;
+        self.undo.clear();
+        Ok(())
+    }
+}
+
+impl<K, V> Drop for Transaction<'_, K, V>
+where
+    K: Serialize + Ord + Clone,
+    V: Serialize + Clone,
+{
+    fn drop(&mut self) {
+        for (key, before) in std::mem::take(&mut self.undo) {
+            match before {
+                Some(row) => self.data.rows.insert(key, row),
+                None => self.data.rows.remove(&key),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("todo-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn recovery_scenario() {
+        let dir = temp_dir("recovery");
+        {
+            let journal = Journal::open(&dir).unwrap();
+            let table = journal.table::<i32, String>("notes").unwrap();
+            for text in ["first", "second", "third"] {
+                let mut notes = table.write().await;
+                let id = notes.next_id();
+                notes.insert(id, text.to_string());
+                notes.commit().await.unwrap();
+            }
+            let mut notes = table.write().await;
+            notes.remove(&3);
+            notes.commit().await.unwrap();
+
+            // dropped without commit
+            let mut notes = table.write().await;
+            notes.get_mut(&1).unwrap().push_str(" changed");
+            notes.remove(&2);
+            drop(notes);
+            assert_eq!(
+                vec!["first", "second"],
+                Vec::from_iter(table.read().await.values())
+            );
+        }
+        // a crash in the middle of an append
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(JOURNAL))
+            .unwrap();
+        file.write_all(br#"{"table":"notes","last_id":4,"rows":[["4","#)
+            .unwrap();
+
+        let journal = Journal::open(&dir).unwrap();
+        let table = journal.table::<i32, String>("notes").unwrap();
+        assert_eq!(
+            vec![(1, "first"), (2, "second")],
+            Vec::from_iter(
+                table
+                    .read()
+                    .await
+                    .iter()
+                    .map(|(id, text)| (*id, text.as_str()))
+            )
+        );
+        // ids of deleted rows are not handed out again
+        let mut notes = table.write().await;
+        assert_eq!(4, notes.next_id());
+        drop(notes);
+        assert_eq!(0, fs::metadata(dir.join(JOURNAL)).unwrap().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_journal_test() {
+        let dir = temp_dir("corrupt");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(JOURNAL),
+            "not an entry\n{\"table\":\"notes\",\"last_id\":1,\"rows\":[]}\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            Journal::open(&dir),
+            Err(JournalError::Corrupt { line: 1, .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lock_test() {
+        let dir = temp_dir("lock");
+        let journal = Journal::open(&dir).unwrap();
+        assert!(matches!(
+            Journal::open(&dir),
+            Err(JournalError::Locked { .. })
+        ));
+        drop(journal);
+        Journal::open(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shared_table_test() {
+        let journal = Journal::memory();
+        let table = journal.table::<i32, String>("notes").unwrap();
+        let mut notes = table.write().await;
+        notes.insert(1, "shared".to_string());
+        notes.commit().await.unwrap();
+        let other = journal.table::<i32, String>("notes").unwrap();
+        assert_eq!(Some(&"shared".to_string()), other.read().await.get(&1));
+    }
+}
@@ -0,0 +1,5 @@
+//! only what the benchmarks measure, the server is the binary
+
+pub mod entity;
+
+pub use entity::{fold_entities, Label, TodoEntity, TodoItem, TodoWithLabelFromRow};
@@ -1,89 +1,790 @@
+mod auth;
+mod config;
 mod handlers;
+mod journal;
+mod migrate;
+mod notifier;
 mod repositories;
+mod scheduler;
+mod shutdown;
+mod storage;
 
 use crate::repositories::{
-    label::LabelRepositoryforDB,
-    todo::{TodoRepository, TodoRepositoryForDB},
+    attachment::{
+        AttachmentRepository, AttachmentRepositoryForDB, AttachmentRepositoryForMemory,
+        AttachmentRepositoryForSqlite,
+    },
+    broadcast::{Broadcast, BroadcastLabelRepository, BroadcastTodoRepository},
+    cache::{Cache, CachedLabelRepository, CachedTodoRepository},
+    cleanup::CleanupTodoRepository,
+    collaborator::{
+        CollaboratorRepository, CollaboratorRepositoryForDB, CollaboratorRepositoryForMemory,
+        CollaboratorRepositoryForSqlite,
+    },
+    comment::{
+        CommentRepository, CommentRepositoryForDB, CommentRepositoryForMemory,
+        CommentRepositoryForSqlite,
+    },
+    event::TodoRepositoryForEvents,
+    health::{
+        HealthRepository, HealthRepositoryForDB, HealthRepositoryForJournal,
+        HealthRepositoryForSqlite,
+    },
+    label::{LabelRepositoryforDB, LabelRepositoryforMemory, LabelRepositoryforSqlite},
+    reminder::{
+        ReminderRepository, ReminderRepositoryForDB, ReminderRepositoryForMemory,
+        ReminderRepositoryForSqlite,
+    },
+    replica::Replica,
+    retry,
+    session::{
+        SessionRepository, SessionRepositoryForDB, SessionRepositoryForMemory,
+        SessionRepositoryForSqlite,
+    },
+    todo::{TodoRepository, TodoRepositoryForDB, TodoRepositoryForMemory, TodoRepositoryForSqlite},
+    token::{
+        PersonalTokenRepository, PersonalTokenRepositoryForDB, PersonalTokenRepositoryForMemory,
+        PersonalTokenRepositoryForSqlite,
+        Scope::{LabelsRead, LabelsWrite, TodosRead, TodosWrite},
+    },
+    user::{UserRepository, UserRepositoryForDB, UserRepositoryForMemory, UserRepositoryForSqlite},
+    workspace::{
+        WorkspaceRepository, WorkspaceRepositoryForDB, WorkspaceRepositoryForMemory,
+        WorkspaceRepositoryForSqlite,
+    },
 };
+use anyhow::Context;
+use auth::{scope, Authenticator, JwtVerifier, SessionCookie, WorkspaceResolver};
 use axum::{
     extract::Extension,
-    http::HeaderValue,
-    routing::{delete, get, post},
+    handler::Handler,
+    http::{HeaderName, HeaderValue, Method},
+    middleware,
+    routing::{delete, get, patch, post},
     Router,
 };
+use clap::Parser;
+use config::{Backend, Cli, Config, Features, JwtConfig, NotifierKind, ReminderConfig};
 use dotenv::dotenv;
 use handlers::{
-    label::{all_label, create_label, delete_label},
-    todo::{all_todo, create_todo, delete_todo, find_todo, update_todo},
+    attachment::{
+        all_attachment, create_attachment, delete_attachment, download_attachment, AttachmentLimits,
+    },
+    broadcast::events,
+    collaborator::{all_collaborator, delete_collaborator, upsert_collaborator},
+    comment::{all_comment, create_comment, delete_comment, update_comment},
+    health::{healthz, metrics, readyz, version},
+    item::{create_item, delete_item, update_item},
+    label::{all_label, create_label, delete_label, update_label},
+    reminder::{all_reminder, create_reminder, delete_reminder},
+    session::{create_session, delete_session, find_session},
+    socket::socket,
+    todo::{
+        add_blocker, all_todo, create_todo, delete_todo, find_todo, remove_blocker, update_todo,
+    },
+    token::{all_personal_token, create_personal_token, delete_personal_token},
+    user::{create_user, login},
 };
-use hyper::header::CONTENT_TYPE;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use journal::{Journal, JournalError};
+use migrate::Schema;
+use notifier::{LogNotifier, Notifier, SmtpNotifier, WebhookNotifier};
 use repositories::label::LabelRepository;
-use sqlx::PgPool;
-use std::net::SocketAddr;
-use std::{env, sync::Arc};
-use tower_http::cors::{Any, CorsLayer};
+use scheduler::ReminderScheduler;
+use shutdown::Shutdown;
+use sqlx::{
+    postgres::PgConnectOptions, sqlite::SqliteConnectOptions, ConnectOptions, Connection, PgPool,
+    SqlitePool,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use storage::{LocalStorage, Storage};
+use tokio::task::JoinHandle;
+use tower_http::cors::CorsLayer;
 
 #[tokio::main]
 async fn main() {
-    let log_level = env::var("RUST_LOG").unwrap_or("info".to_string());
-    env::set_var("RUST_LOG", log_level);
-    tracing_subscriber::fmt::init();
     dotenv().ok();
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
+    config.log.init();
+    let (jwt_verifier, notifier) = match (
+        create_jwt_verifier(&config.jwt),
+        create_notifier(&config.reminders),
+    ) {
+        (Ok(jwt_verifier), Ok(notifier)) => (jwt_verifier, notifier),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("invalid configuration: {:#}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let attachment_limits = AttachmentLimits {
+        max_size: config.attachments.max_bytes,
+        allowed_types: config.attachments.mime_types(),
+    };
+    let session_cookie = SessionCookie {
+        secure: config.session.cookie_secure,
+        ..SessionCookie::default()
+    };
+    let (trigger, shutdown) = Shutdown::new();
+    let cache = Cache::new(config.cache.ttl(), config.cache.capacity);
+    let broadcast = Broadcast::default();
+
+    // the scheme of the url picks the backend, validated by Config::load
+    let (app, scheduler, database) = match config.database.backend() {
+        Some(Backend::Postgres) => {
+            let options = config
+                .database
+                .pg_session(connect_options::<PgConnectOptions>(&config.database.url));
+            let pool = connect(&config, &cli, options).await;
+            let replica = match &config.database.replica_url {
+                Some(url) => Some(Replica::new(
+                    connect_replica(&config, url).await,
+                    Duration::from_secs(config.database.replica_lag_secs),
+                )),
+                None => None,
+            };
+            if cli.rebuild_projections {
+                let code = match TodoRepositoryForEvents::new(pool.clone()).rebuild().await {
+                    Ok(events) => {
+                        tracing::info!("replayed {} todo events", events);
+                        0
+                    }
+                    Err(e) => {
+                        eprintln!("fail rebuild projections: {:#}", e);
+                        1
+                    }
+                };
+                pool.close().await;
+                std::process::exit(code);
+            }
+            let scheduler = spawn_scheduler(
+                &config,
+                ReminderRepositoryForDB::new(pool.clone()),
+                notifier,
+                &shutdown,
+            );
+            let settings = AppSettings {
+                attachment_limits,
+                session_cookie,
+                jwt_verifier,
+                cache,
+                broadcast,
+                shutdown: shutdown.clone(),
+            };
+            let app = if config.database.event_sourcing {
+                let todo = TodoRepositoryForEvents::new(pool.clone()).with_replica(replica.clone());
+                postgres_app(&config, &pool, replica.clone(), todo, settings)
+            } else {
+                let todo = TodoRepositoryForDB::new(pool.clone()).with_replica(replica.clone());
+                postgres_app(&config, &pool, replica.clone(), todo, settings)
+            };
+            (app, scheduler, Database::Postgres(pool, replica))
+        }
+        Some(Backend::Sqlite) => {
+            let options = connect_options::<SqliteConnectOptions>(&config.database.url)
+                .create_if_missing(true);
+            let pool = connect(&config, &cli, options).await;
+            let scheduler = spawn_scheduler(
+                &config,
+                ReminderRepositoryForSqlite::new(pool.clone()),
+                notifier,
+                &shutdown,
+            );
+            let app = create_app(
+                CachedTodoRepository::new(
+                    BroadcastTodoRepository::new(
+                        CleanupTodoRepository::new(
+                            TodoRepositoryForSqlite::new(pool.clone()),
+                            LocalStorage::new(&config.attachments.dir),
+                        ),
+                        broadcast.clone(),
+                    ),
+                    cache.clone(),
+                ),
+                CachedLabelRepository::new(
+                    BroadcastLabelRepository::new(
+                        LabelRepositoryforSqlite::new(pool.clone()),
+                        broadcast.clone(),
+                    ),
+                    cache.clone(),
+                ),
+                CommentRepositoryForSqlite::new(pool.clone()),
+                AttachmentRepositoryForSqlite::new(pool.clone()),
+                LocalStorage::new(&config.attachments.dir),
+                attachment_limits,
+                ReminderRepositoryForSqlite::new(pool.clone()),
+                UserRepositoryForSqlite::new(pool.clone()),
+                PersonalTokenRepositoryForSqlite::new(pool.clone()),
+                CollaboratorRepositoryForSqlite::new(pool.clone()),
+                SessionRepositoryForSqlite::new(pool.clone()),
+                session_cookie,
+                config.cors.origins(),
+                WorkspaceResolver::new(
+                    WorkspaceRepositoryForSqlite::new(pool.clone()),
+                    config.workspace_domain.clone(),
+                ),
+                jwt_verifier,
+                config.features,
+                HealthRepositoryForSqlite::new(pool.clone()),
+                cache,
+                broadcast,
+                shutdown.clone(),
+            );
+            (app, scheduler, Database::Sqlite(pool))
+        }
+        Some(Backend::Journal) => {
+            let dir = config.database.url.trim_start_matches("journal:");
+            let journal = match Journal::open(dir) {
+                Ok(journal) => journal,
+                Err(e) => {
+                    eprintln!("fail open journal: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            // the journal has no migrations, opening it is all there is to do
+            if cli.migrate_only {
+                return;
+            }
+            let repositories = (|| {
+                Ok::<_, JournalError>((
+                    TodoRepositoryForMemory::open(&journal)?,
+                    LabelRepositoryforMemory::open(&journal)?,
+                    CommentRepositoryForMemory::open(&journal)?,
+                    AttachmentRepositoryForMemory::open(&journal)?,
+                    ReminderRepositoryForMemory::open(&journal)?,
+                    UserRepositoryForMemory::open(&journal)?,
+                    PersonalTokenRepositoryForMemory::open(&journal)?,
+                    CollaboratorRepositoryForMemory::open(&journal)?,
+                    SessionRepositoryForMemory::open(&journal)?,
+                    WorkspaceRepositoryForMemory::open(&journal)?,
+                ))
+            })();
+            let (
+                todo,
+                label,
+                comment,
+                attachment,
+                reminder,
+                user,
+                token,
+                collaborator,
+                session,
+                workspace,
+            ) = match repositories {
+                Ok(repositories) => repositories,
+                Err(e) => {
+                    eprintln!("fail open journal: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let scheduler = spawn_scheduler(&config, reminder.clone(), notifier, &shutdown);
+            let app = create_app(
+                CachedTodoRepository::new(
+                    BroadcastTodoRepository::new(
+                        CleanupTodoRepository::new(
+                            todo,
+                            LocalStorage::new(&config.attachments.dir),
+                        ),
+                        broadcast.clone(),
+                    ),
+                    cache.clone(),
+                ),
+                CachedLabelRepository::new(
+                    BroadcastLabelRepository::new(label, broadcast.clone()),
+                    cache.clone(),
+                ),
+                comment,
+                attachment,
+                LocalStorage::new(&config.attachments.dir),
+                attachment_limits,
+                reminder,
+                user,
+                token,
+                collaborator,
+                session,
+                session_cookie,
+                config.cors.origins(),
+                WorkspaceResolver::new(workspace, config.workspace_domain.clone()),
+                jwt_verifier,
+                config.features,
+                HealthRepositoryForJournal::new(journal),
+                cache,
+                broadcast,
+                shutdown.clone(),
+            );
+            (app, scheduler, Database::Journal)
+        }
+        None => unreachable!("database.url is validated"),
+    };
+    let addr = config.listen;
+    let server = match axum::Server::try_bind(&addr) {
+        Ok(builder) => builder
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(shutdown.wait()),
+        Err(e) => {
+            eprintln!("fail bind {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("listening on {}", addr);
+
+    // on a signal the server stops accepting and in-flight requests get until
+    // the deadline, connections still open after it are dropped
+    let mut server = tokio::spawn(server);
+    let result = tokio::select! {
+        result = &mut server => result,
+        _ = shutdown::signal() => {
+            let deadline = Duration::from_secs(config.shutdown_timeout_secs);
+            tracing::info!("shutting down, draining requests for up to {:?}", deadline);
+            let _ = trigger.send(true);
+            match tokio::time::timeout(deadline, &mut server).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::warn!("requests still running after {:?}, dropping them", deadline);
+                    server.abort();
+                    Ok(Ok(()))
+                }
+            }
+        }
+    };
+    let _ = trigger.send(true);
+    if let Some(scheduler) = scheduler {
+        let _ = scheduler.await;
+    }
+    database.close().await;
+    tracing::info!("shutdown complete");
 
-    let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
-    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            eprintln!("server error: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("server task failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// whichever backend the server runs on
+enum Database {
+    Postgres(PgPool, Option<Replica>),
+    Sqlite(SqlitePool),
+    /// every commit is on disk already, there is nothing to close
+    Journal,
+}
+
+impl Database {
+    async fn close(&self) {
+        match self {
+            Database::Postgres(pool, replica) => {
+                pool.close().await;
+                if let Some(replica) = replica {
+                    replica.pool().close().await;
+                }
+            }
+            Database::Sqlite(pool) => pool.close().await,
+            Database::Journal => {}
+        }
+    }
+}
+
+fn connect_options<O: ConnectOptions>(url: &str) -> O {
+    match url.parse() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("invalid database.url: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// what every backend passes to `create_app` alike
+struct AppSettings {
+    attachment_limits: AttachmentLimits,
+    session_cookie: SessionCookie,
+    jwt_verifier: Option<JwtVerifier>,
+    cache: Cache,
+    broadcast: Broadcast,
+    shutdown: Shutdown,
+}
+
+/// the app on postgres, with todos kept in tables or as events
+fn postgres_app<T: TodoRepository>(
+    config: &Config,
+    pool: &PgPool,
+    replica: Option<Replica>,
+    todo: T,
+    settings: AppSettings,
+) -> Router {
+    create_app(
+        CachedTodoRepository::new(
+            BroadcastTodoRepository::new(
+                CleanupTodoRepository::new(todo, LocalStorage::new(&config.attachments.dir)),
+                settings.broadcast.clone(),
+            ),
+            settings.cache.clone(),
+        ),
+        CachedLabelRepository::new(
+            BroadcastLabelRepository::new(
+                LabelRepositoryforDB::new(pool.clone()).with_replica(replica),
+                settings.broadcast.clone(),
+            ),
+            settings.cache.clone(),
+        ),
+        CommentRepositoryForDB::new(pool.clone()),
+        AttachmentRepositoryForDB::new(pool.clone()),
+        LocalStorage::new(&config.attachments.dir),
+        settings.attachment_limits,
+        ReminderRepositoryForDB::new(pool.clone()),
+        UserRepositoryForDB::new(pool.clone()),
+        PersonalTokenRepositoryForDB::new(pool.clone()),
+        CollaboratorRepositoryForDB::new(pool.clone()),
+        SessionRepositoryForDB::new(pool.clone()),
+        settings.session_cookie,
+        config.cors.origins(),
+        WorkspaceResolver::new(
+            WorkspaceRepositoryForDB::new(pool.clone()),
+            config.workspace_domain.clone(),
+        ),
+        settings.jwt_verifier,
+        config.features,
+        HealthRepositoryForDB::new(pool.clone()),
+        settings.cache,
+        settings.broadcast,
+        settings.shutdown,
+    )
+}
+
+/// the replica is migrated through the primary, so it only gets connected
+async fn connect_replica(config: &Config, url: &str) -> PgPool {
+    let options = match url.parse::<PgConnectOptions>() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("invalid database.replica_url: {}", e);
+            std::process::exit(2);
+        }
+    };
+    match connect_with_retry(config, config.database.pg_session(options)).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("fail connect database replica: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// under docker compose the database may still be starting, failed connects are
+/// retried with exponential backoff for up to `database.connect_retry_secs`
+async fn connect_with_retry<DB: sqlx::Database>(
+    config: &Config,
+    options: <DB::Connection as Connection>::Options,
+) -> Result<sqlx::Pool<DB>, sqlx::Error> {
+    let deadline = Instant::now() + Duration::from_secs(config.database.connect_retry_secs);
+    let mut backoff = Duration::from_millis(250);
+    loop {
+        match config
+            .database
+            .pool_options()
+            .connect_with(options.clone())
+            .await
+        {
+            Err(e) if retry::transient(&e) && Instant::now() + backoff < deadline => {
+                tracing::warn!("database is not up yet, retrying in {:?}: {}", backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(10));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// connects and checks the schema, exits when either fails or after the
+/// migrations with --migrate-only
+async fn connect<DB>(
+    config: &Config,
+    cli: &Cli,
+    options: <DB::Connection as Connection>::Options,
+) -> sqlx::Pool<DB>
+where
+    DB: sqlx::Database,
+    sqlx::Pool<DB>: Schema,
+{
     tracing::debug!("start connect database...");
-    let pool = PgPool::connect(database_url)
-        .await
-        .expect(&format!("fail connect database, url is {}", database_url));
-    let repository = TodoRepositoryForDB::new(pool.clone());
-    let app = create_app(
-        TodoRepositoryForDB::new(pool.clone()),
-        LabelRepositoryforDB::new(pool.clone()),
-    );
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-    tracing::debug!("listening on {}", addr);
-
-    let graceful = axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(async {
-            rx.await.ok();
-        });
-    // close resource connection
-    if let Err(e) = graceful.await {
-        eprintln!("server error: {e}");
+    let pool = match connect_with_retry(config, options).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("fail connect database: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = migrate::prepare(&pool, config.database.migrate).await {
+        eprintln!("database schema: {}", e);
+        pool.close().await;
+        std::process::exit(1);
+    }
+    if cli.migrate_only {
         pool.close().await;
+        std::process::exit(0);
+    }
+    pool
+}
+
+fn spawn_scheduler<R: ReminderRepository>(
+    config: &Config,
+    repository: R,
+    notifier: Box<dyn Notifier>,
+    shutdown: &Shutdown,
+) -> Option<JoinHandle<()>> {
+    config.features.reminders.then(|| {
+        let scheduler = ReminderScheduler::new(
+            repository,
+            notifier,
+            Duration::from_secs(config.reminders.interval_secs),
+        );
+        tokio::spawn(scheduler.run(shutdown.clone().wait()))
+    })
+}
+
+/// JWTs are accepted next to login tokens when `jwt.hs256_secret` or
+/// `jwt.rs256_public_key` (path of a PEM file) is set
+fn create_jwt_verifier(config: &JwtConfig) -> anyhow::Result<Option<JwtVerifier>> {
+    match (&config.hs256_secret, &config.rs256_public_key) {
+        (Some(secret), _) => Ok(Some(JwtVerifier::hs256(secret.as_bytes()))),
+        (_, Some(path)) => JwtVerifier::rs256(path)
+            .map(Some)
+            .with_context(|| format!("invalid jwt.rs256_public_key {}", path.display())),
+        _ => Ok(None),
     }
+}
 
-    let _ = tx.send(());
+/// notifier for reminders chosen by `reminders.notifier`, checked by `Config::validate`
+fn create_notifier(config: &ReminderConfig) -> anyhow::Result<Box<dyn Notifier>> {
+    let smtp = &config.smtp;
+    Ok(match config.notifier {
+        NotifierKind::Smtp => Box::new(SmtpNotifier::new(
+            smtp.addr.clone(),
+            smtp.from.clone().context("undefined reminders.smtp.from")?,
+            smtp.to.clone().context("undefined reminders.smtp.to")?,
+        )),
+        NotifierKind::Webhook => Box::new(WebhookNotifier::new(
+            config
+                .webhook_url
+                .as_deref()
+                .context("undefined reminders.webhook_url")?
+                .parse()
+                .context("reminders.webhook_url is not an url")?,
+        )),
+        NotifierKind::Log => Box::new(LogNotifier),
+    })
 }
 
-fn create_app<Todo: TodoRepository, Label: LabelRepository>(
+#[allow(clippy::too_many_arguments)]
+fn create_app<
+    Todo: TodoRepository,
+    Label: LabelRepository,
+    Comment: CommentRepository,
+    Attachment: AttachmentRepository,
+    Blob: Storage,
+    Reminder: ReminderRepository,
+    User: UserRepository,
+    Token: PersonalTokenRepository,
+    Collaborator: CollaboratorRepository,
+    Session: SessionRepository,
+    Workspace: WorkspaceRepository,
+    Health: HealthRepository,
+>(
     todo_repository: Todo,
     label_repository: Label,
+    comment_repository: Comment,
+    attachment_repository: Attachment,
+    storage: Blob,
+    attachment_limits: AttachmentLimits,
+    reminder_repository: Reminder,
+    user_repository: User,
+    token_repository: Token,
+    collaborator_repository: Collaborator,
+    session_repository: Session,
+    session_cookie: SessionCookie,
+    allowed_origins: Vec<HeaderValue>,
+    workspace_resolver: WorkspaceResolver<Workspace>,
+    jwt_verifier: Option<JwtVerifier>,
+    features: Features,
+    health_repository: Health,
+    cache: Cache,
+    broadcast: Broadcast,
+    shutdown: Shutdown,
 ) -> Router {
-    Router::new()
+    let authenticator = Authenticator::new(
+        user_repository.clone(),
+        token_repository.clone(),
+        session_repository.clone(),
+        jwt_verifier,
+    );
+    let mut router = Router::new()
         .route("/", get(root))
-        .route("/todos", post(create_todo::<Todo>).get(all_todo::<Todo>))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz::<Health>))
+        .route("/version", get(version))
+        .route("/metrics", get(metrics));
+    if features.registration {
+        router = router.route("/users", post(create_user::<User>));
+    }
+    // every data route names the scope a personal access token needs for it
+    router
+        .route("/login", post(login::<User>))
+        .route(
+            "/sessions",
+            post(create_session::<User, Session>)
+                .get(find_session)
+                .delete(delete_session::<Session>),
+        )
+        .route(
+            "/tokens",
+            post(create_personal_token::<Token>).get(all_personal_token::<Token>),
+        )
+        .route("/tokens/:id", delete(delete_personal_token::<Token>))
+        .route(
+            "/collaborators",
+            post(upsert_collaborator::<Collaborator>.layer(scope(TodosWrite)))
+                .get(all_collaborator::<Collaborator>.layer(scope(TodosRead))),
+        )
+        .route(
+            "/collaborators/:user_id",
+            delete(delete_collaborator::<Collaborator>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos",
+            post(create_todo::<Todo>.layer(scope(TodosWrite)))
+                .get(all_todo::<Todo, Comment>.layer(scope(TodosRead))),
+        )
         .route(
             "/todos/:id",
-            get(find_todo::<Todo>)
-                .delete(delete_todo::<Todo>)
-                .patch(update_todo::<Todo>),
+            get(find_todo::<Todo>.layer(scope(TodosRead)))
+                .delete(delete_todo::<Todo>.layer(scope(TodosWrite)))
+                .patch(update_todo::<Todo>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos/:id/blockers",
+            post(add_blocker::<Todo>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos/:id/blockers/:blocker_id",
+            delete(remove_blocker::<Todo>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos/:id/items",
+            post(create_item::<Todo>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos/:id/items/:item_id",
+            patch(update_item::<Todo>.layer(scope(TodosWrite)))
+                .delete(delete_item::<Todo>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos/:id/comments",
+            post(create_comment::<Todo, Comment>.layer(scope(TodosWrite)))
+                .get(all_comment::<Todo, Comment>.layer(scope(TodosRead))),
+        )
+        .route(
+            "/todos/:id/comments/:comment_id",
+            patch(update_comment::<Todo, Comment>.layer(scope(TodosWrite)))
+                .delete(delete_comment::<Todo, Comment>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos/:id/reminders",
+            post(create_reminder::<Todo, Reminder>.layer(scope(TodosWrite)))
+                .get(all_reminder::<Todo, Reminder>.layer(scope(TodosRead))),
+        )
+        .route(
+            "/todos/:id/reminders/:reminder_id",
+            delete(delete_reminder::<Todo, Reminder>.layer(scope(TodosWrite))),
+        )
+        .route(
+            "/todos/:id/attachments",
+            post(create_attachment::<Todo, Attachment, Blob>.layer(scope(TodosWrite)))
+                .get(all_attachment::<Todo, Attachment>.layer(scope(TodosRead))),
+        )
+        .route(
+            "/todos/:id/attachments/:attachment_id",
+            get(download_attachment::<Todo, Attachment, Blob>.layer(scope(TodosRead)))
+                .delete(delete_attachment::<Todo, Attachment, Blob>.layer(scope(TodosWrite))),
         )
         //add new router path is "/labels", and use post method to create label and get method to get all labels
         .route(
             "/labels",
-            post(create_label::<Label>).get(all_label::<Label>),
+            post(create_label::<Label>.layer(scope(LabelsWrite)))
+                .get(all_label::<Label>.layer(scope(LabelsRead))),
+        )
+        .route(
+            "/labels/:id",
+            patch(update_label::<Label>.layer(scope(LabelsWrite)))
+                .delete(delete_label::<Label>.layer(scope(LabelsWrite))),
+        )
+        .route(
+            "/events",
+            get(events::<Collaborator>.layer(scope(TodosRead))),
+        )
+        .route(
+            "/ws",
+            get(socket::<Todo, Collaborator>.layer(scope(TodosRead))),
         )
-        .route("/labels/:id", delete(delete_label::<Label>))
         .layer(Extension(Arc::new(todo_repository)))
         .layer(Extension(Arc::new(label_repository)))
+        .layer(Extension(Arc::new(comment_repository)))
+        .layer(Extension(Arc::new(attachment_repository)))
+        .layer(Extension(Arc::new(storage)))
+        .layer(Extension(Arc::new(attachment_limits)))
+        .layer(Extension(Arc::new(reminder_repository)))
+        .layer(Extension(Arc::new(health_repository)))
+        .layer(Extension(shutdown))
+        .layer(Extension(cache))
+        .layer(Extension(broadcast))
+        .layer(middleware::from_fn({
+            let collaborator_repository = collaborator_repository.clone();
+            move |req, next| auth::resolve_collection(req, next, collaborator_repository.clone())
+        }))
+        .layer(middleware::from_fn(move |req, next| {
+            auth::resolve_workspace(req, next, workspace_resolver.clone())
+        }))
+        .layer(middleware::from_fn(move |req, next| {
+            auth::authenticate(req, next, authenticator.clone())
+        }))
+        .layer(Extension(Arc::new(user_repository)))
+        .layer(Extension(Arc::new(token_repository)))
+        .layer(Extension(Arc::new(collaborator_repository)))
+        .layer(Extension(Arc::new(session_repository)))
+        .layer(Extension(Arc::new(session_cookie)))
         .layer(
+            // credentials rule out wildcards, methods and headers are listed
             CorsLayer::new()
-                .allow_origin("http://localhost:3001".parse::<HeaderValue>().unwrap())
-                .allow_methods(Any)
-                .allow_headers(vec![CONTENT_TYPE]),
+                .allow_origin(allowed_origins)
+                .allow_credentials(true)
+                .allow_methods(vec![
+                    Method::GET,
+                    Method::POST,
+                    Method::PUT,
+                    Method::PATCH,
+                    Method::DELETE,
+                ])
+                .allow_headers(vec![
+                    CONTENT_TYPE,
+                    AUTHORIZATION,
+                    HeaderName::from_static(auth::COLLECTION_HEADER),
+                    HeaderName::from_static(auth::WORKSPACE_HEADER),
+                    HeaderName::from_static(auth::CSRF_HEADER),
+                ]),
         )
 }
 
@@ -94,11 +795,22 @@ async fn root() -> &'static str {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::handlers::label;
-    use crate::repositories::label::test_utils::LabelRepositoryforMemory;
+    use crate::auth::AuthUser;
+    use crate::repositories::attachment::AttachmentRepositoryForMemory;
+    use crate::repositories::collaborator::{
+        CollaboratorRepositoryForMemory, Role, UpsertCollaborator,
+    };
+    use crate::repositories::comment::CommentRepositoryForMemory;
+    use crate::repositories::health::test_utils::HealthRepositoryForMemory;
     use crate::repositories::label::Label;
-    use crate::repositories::todo::{test_utils::TodoRepositoryForMemory, CreateTodo, TodoEntity};
-    use axum::body::{self, Bytes};
+    use crate::repositories::label::LabelRepositoryforMemory;
+    use crate::repositories::reminder::ReminderRepositoryForMemory;
+    use crate::repositories::session::SessionRepositoryForMemory;
+    use crate::repositories::todo::{CreateTodo, TodoEntity, TodoRepositoryForMemory};
+    use crate::repositories::token::PersonalTokenRepositoryForMemory;
+    use crate::repositories::user::UserRepositoryForMemory;
+    use crate::repositories::workspace::WorkspaceRepositoryForMemory;
+    use crate::storage::test_utils::StorageForMemory;
     use axum::response::Response;
     use axum::{
         body::Body,
@@ -106,10 +818,43 @@ mod test {
     };
     use tower::ServiceExt;
 
+    fn create_test_app(
+        todo_repository: TodoRepositoryForMemory,
+        label_repository: LabelRepositoryforMemory,
+    ) -> Router {
+        create_app(
+            todo_repository,
+            label_repository,
+            CommentRepositoryForMemory::new(),
+            AttachmentRepositoryForMemory::new(),
+            StorageForMemory::new(),
+            AttachmentLimits::default(),
+            ReminderRepositoryForMemory::new(),
+            UserRepositoryForMemory::new(),
+            PersonalTokenRepositoryForMemory::new(),
+            CollaboratorRepositoryForMemory::new(),
+            SessionRepositoryForMemory::new(),
+            SessionCookie::default(),
+            vec![HeaderValue::from_static("http://localhost:3001")],
+            WorkspaceResolver::new(WorkspaceRepositoryForMemory::new(), None),
+            None,
+            Features::default(),
+            HealthRepositoryForMemory::new(),
+            Cache::default(),
+            Broadcast::default(),
+            Shutdown::new().1,
+        )
+    }
+
+    /// requests are sent as the user with id 1 of the default workspace
     fn build_todo_req_with_json(path: &str, method: Method, json_body: String) -> Request<Body> {
         Request::builder()
             .uri(path)
             .method(method)
+            .extension(AuthUser {
+                id: 1,
+                workspace_id: 1,
+            })
             .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
             .body(Body::from(json_body))
             .unwrap()
@@ -119,6 +864,10 @@ mod test {
         Request::builder()
             .uri(path)
             .method(method)
+            .extension(AuthUser {
+                id: 1,
+                workspace_id: 1,
+            })
             .body(Body::empty())
             .unwrap()
     }
@@ -127,7 +876,7 @@ mod test {
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
         let body: String = String::from_utf8(bytes.to_vec()).unwrap();
         let todo: TodoEntity = serde_json::from_str(&body)
-            .expect(&format!("cannot convert Todo instance. body: {}", body));
+            .unwrap_or_else(|_| panic!("cannot convert Todo instance. body: {}", body));
         todo
     }
 
@@ -154,7 +903,7 @@ mod test {
             Method::POST,
             r#"{ "text": "should_return_created_todo", "labels": [999]}"#.to_string(),
         );
-        let res = create_app(todo_repository, label_repository)
+        let res = create_test_app(todo_repository, label_repository)
             .oneshot(req)
             .await
             .unwrap();
@@ -169,11 +918,15 @@ mod test {
         let todo_repository = TodoRepositoryForMemory::new(labels.clone());
         let label_repository = LabelRepositoryforMemory::new();
         todo_repository
-            .create(CreateTodo::new("should_find_todo".to_string(), label_ids))
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_find_todo".to_string(), label_ids),
+            )
             .await
             .expect("failed create todo");
         let req = build_todo_req_with_empty(Method::GET, "/todos/1");
-        let res = create_app(todo_repository, label_repository)
+        let res = create_test_app(todo_repository, label_repository)
             .oneshot(req)
             .await
             .unwrap();
@@ -181,6 +934,27 @@ mod test {
         assert_eq!(expected, todo);
     }
 
+    #[tokio::test]
+    async fn should_reject_as_of_without_history() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_reject_as_of".to_string(), vec![]),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_test_app(todo_repository, LabelRepositoryforMemory::new());
+
+        let req = build_todo_req_with_empty(Method::GET, "/todos/1?as_of=2023-06-16T09:00:00Z");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+        let req = build_todo_req_with_empty(Method::GET, "/todos/1?as_of=yesterday");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
     #[tokio::test]
     async fn should_find_all_todo() {
         let (labels, label_ids) = label_fixture();
@@ -188,18 +962,22 @@ mod test {
         let todo_repository = TodoRepositoryForMemory::new(labels.clone());
         let label_repository = LabelRepositoryforMemory::new();
         todo_repository
-            .create(CreateTodo::new("should_find_all_todos".to_string(), label_ids))
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_find_all_todos".to_string(), label_ids),
+            )
             .await
             .expect("failed create todo");
         let req = build_todo_req_with_empty(Method::GET, "/todos");
-        let res = create_app(todo_repository, label_repository)
+        let res = create_test_app(todo_repository, label_repository)
             .oneshot(req)
             .await
             .unwrap();
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
         let body: String = String::from_utf8(bytes.to_vec()).unwrap();
         let todos: Vec<TodoEntity> = serde_json::from_str(&body)
-            .expect(&format!("cannot convert Todo instance. body: {}", body));
+            .unwrap_or_else(|_| panic!("cannot convert Todo instance. body: {}", body));
         assert_eq!(vec![expected], todos);
     }
 
@@ -210,7 +988,11 @@ mod test {
         let todo_repository = TodoRepositoryForMemory::new(labels.clone());
         let label_repository = LabelRepositoryforMemory::new();
         todo_repository
-            .create(CreateTodo::new("should_find_todo".to_string(), label_ids))
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_find_todo".to_string(), label_ids),
+            )
             .await
             .expect("failed create todo");
         let req = build_todo_req_with_json(
@@ -223,7 +1005,7 @@ mod test {
             }"#
             .to_string(),
         );
-        let res = create_app(todo_repository, label_repository)
+        let res = create_test_app(todo_repository, label_repository)
             .oneshot(req)
             .await
             .unwrap();
@@ -237,15 +1019,1158 @@ mod test {
         let todo_repository = TodoRepositoryForMemory::new(labels.clone());
         let label_repository = LabelRepositoryforMemory::new();
         todo_repository
-            .create(CreateTodo::new("should_find_todo".to_string(), label_ids))
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_find_todo".to_string(), label_ids),
+            )
             .await
             .expect("failed create todo");
         let req = build_todo_req_with_empty(Method::DELETE, "/todos/1");
 
-        let res = create_app(todo_repository, label_repository)
+        let res = create_test_app(todo_repository, label_repository)
             .oneshot(req)
             .await
             .unwrap();
         assert_eq!(StatusCode::NO_CONTENT, res.status());
     }
+
+    #[tokio::test]
+    async fn should_reject_blocker_cycle() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for text in ["blocked", "blocker"] {
+            todo_repository
+                .create(1, 1, CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_test_app(todo_repository, LabelRepositoryforMemory::new());
+
+        let req = build_todo_req_with_json(
+            "/todos/1/blockers",
+            Method::POST,
+            r#"{ "blocker_id": 2 }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let todo = res_to_todo(res).await;
+        assert!(todo.blocked);
+        assert_eq!(vec![2], todo.blocked_by);
+
+        let req = build_todo_req_with_json(
+            "/todos/2/blockers",
+            Method::POST,
+            r#"{ "blocker_id": 1 }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CONFLICT, res.status());
+
+        let req = build_todo_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "completed": true }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CONFLICT, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_created_reminder() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_created_reminder".to_string(), vec![]),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_test_app(todo_repository, LabelRepositoryforMemory::new());
+
+        let req = build_todo_req_with_json(
+            "/todos/1/reminders",
+            Method::POST,
+            r#"{ "remind_at": "2030-01-01T09:00:00Z" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let req = build_todo_req_with_json(
+            "/todos/2/reminders",
+            Method::POST,
+            r#"{ "remind_at": "2030-01-01T09:00:00Z" }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_created_item() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryforMemory::new();
+        todo_repository
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_created_item".to_string(), label_ids),
+            )
+            .await
+            .expect("failed create todo");
+        let req = build_todo_req_with_json(
+            "/todos/1/items",
+            Method::POST,
+            r#"{ "text": "should_created_item" }"#.to_string(),
+        );
+        let app = create_test_app(todo_repository, label_repository);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let req = build_todo_req_with_empty(Method::GET, "/todos/1");
+        let res = app.oneshot(req).await.unwrap();
+        let todo = res_to_todo(res).await;
+        assert_eq!(1, todo.items.len());
+        assert_eq!("should_created_item", todo.items[0].text);
+    }
+
+    #[tokio::test]
+    async fn should_count_comments_of_todo() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryforMemory::new();
+        todo_repository
+            .create(
+                1,
+                1,
+                CreateTodo::new("should_count_comments_of_todo".to_string(), label_ids),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_test_app(todo_repository, label_repository);
+        for body in ["first", "second"] {
+            let req = build_todo_req_with_json(
+                "/todos/1/comments",
+                Method::POST,
+                format!(r#"{{ "body": "{}" }}"#, body),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::CREATED, res.status());
+        }
+
+        let req = build_todo_req_with_json(
+            "/todos/2/comments",
+            Method::POST,
+            r#"{ "body": "missing todo" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let req = build_todo_req_with_empty(Method::GET, "/todos");
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let todos: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(2, todos[0]["comment_count"]);
+    }
+
+    fn build_multipart_req(path: &str, content_type: &str, data: &str) -> Request<Body> {
+        let boundary = "todo-boundary";
+        let body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"log.txt\"\r\nContent-Type: {content_type}\r\n\r\n{data}\r\n--{boundary}--\r\n"
+        );
+        Request::builder()
+            .uri(path)
+            .method(Method::POST)
+            .extension(AuthUser {
+                id: 1,
+                workspace_id: 1,
+            })
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_upload_and_download_attachment() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        todo_repository
+            .create(
+                1,
+                1,
+                CreateTodo::new(
+                    "should_upload_and_download_attachment".to_string(),
+                    label_ids,
+                ),
+            )
+            .await
+            .expect("failed create todo");
+        let storage = StorageForMemory::new();
+        let app = create_app(
+            CleanupTodoRepository::new(todo_repository, storage.clone()),
+            LabelRepositoryforMemory::new(),
+            CommentRepositoryForMemory::new(),
+            AttachmentRepositoryForMemory::new(),
+            storage.clone(),
+            AttachmentLimits {
+                max_size: 16,
+                ..AttachmentLimits::default()
+            },
+            ReminderRepositoryForMemory::new(),
+            UserRepositoryForMemory::new(),
+            PersonalTokenRepositoryForMemory::new(),
+            CollaboratorRepositoryForMemory::new(),
+            SessionRepositoryForMemory::new(),
+            SessionCookie::default(),
+            vec![HeaderValue::from_static("http://localhost:3001")],
+            WorkspaceResolver::new(WorkspaceRepositoryForMemory::new(), None),
+            None,
+            Features::default(),
+            HealthRepositoryForMemory::new(),
+            Cache::default(),
+            Broadcast::default(),
+            Shutdown::new().1,
+        );
+
+        let req = build_multipart_req("/todos/1/attachments", "text/plain", "some log");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert!(storage.contains("todos/1/1"));
+
+        let req = build_todo_req_with_empty(Method::GET, "/todos/1/attachments/1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!("text/plain", res.headers()[header::CONTENT_TYPE]);
+        assert_eq!("nosniff", res.headers()[header::X_CONTENT_TYPE_OPTIONS]);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&b"some log"[..], &bytes[..]);
+
+        let req = build_multipart_req("/todos/1/attachments", "text/plain", "too large log file");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, res.status());
+
+        let req = build_multipart_req("/todos/1/attachments", "application/x-sh", "rm -rf");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, res.status());
+        let req = build_multipart_req("/todos/1/attachments", "text/html", "<script>");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, res.status());
+
+        let req = build_todo_req_with_empty(Method::DELETE, "/todos/1");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+        assert!(!storage.contains("todos/1/1"));
+    }
+
+    #[tokio::test]
+    async fn should_reject_without_user() {
+        let app = create_test_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryforMemory::new(),
+        );
+        let req = Request::builder()
+            .uri("/todos")
+            .method(Method::GET)
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        assert_eq!(
+            "Bearer realm=\"todo\"",
+            res.headers()[header::WWW_AUTHENTICATE]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_accept_jwt() {
+        let workspace_repository = WorkspaceRepositoryForMemory::new();
+        let acme = workspace_repository.insert("acme").await;
+        let user_repository = UserRepositoryForMemory::new();
+        let user = user_repository
+            .create(acme.id, "jwt user".to_string(), String::new())
+            .await
+            .expect("failed create user");
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(
+                acme.id,
+                user.id,
+                CreateTodo::new("should_accept_jwt".to_string(), vec![]),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryforMemory::new(),
+            CommentRepositoryForMemory::new(),
+            AttachmentRepositoryForMemory::new(),
+            StorageForMemory::new(),
+            AttachmentLimits::default(),
+            ReminderRepositoryForMemory::new(),
+            user_repository,
+            PersonalTokenRepositoryForMemory::new(),
+            CollaboratorRepositoryForMemory::new(),
+            SessionRepositoryForMemory::new(),
+            SessionCookie::default(),
+            vec![HeaderValue::from_static("http://localhost:3001")],
+            WorkspaceResolver::new(workspace_repository, None),
+            Some(JwtVerifier::hs256(b"secret")),
+            Features::default(),
+            HealthRepositoryForMemory::new(),
+            Cache::default(),
+            Broadcast::default(),
+            Shutdown::new().1,
+        );
+        let mut claims = auth::Claims {
+            sub: user.id.to_string(),
+            exp: (chrono::Utc::now().timestamp() + 60) as usize,
+            workspace: Some("acme".to_string()),
+        };
+        let req = |claims: &auth::Claims, secret: &[u8]| {
+            let token = jsonwebtoken::encode(
+                &jsonwebtoken::Header::default(),
+                claims,
+                &jsonwebtoken::EncodingKey::from_secret(secret),
+            )
+            .unwrap();
+            Request::builder()
+                .uri("/todos/1")
+                .method(Method::GET)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let res = app.clone().oneshot(req(&claims, b"secret")).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let res = app.clone().oneshot(req(&claims, b"guessed")).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        assert_eq!(
+            "Bearer realm=\"todo\", error=\"invalid_token\"",
+            res.headers()[header::WWW_AUTHENTICATE]
+        );
+
+        // without the claim the request lands in the default workspace
+        claims.workspace = None;
+        let res = app.oneshot(req(&claims, b"secret")).await.unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_hide_todos_of_other_users() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(1, 2, CreateTodo::new("other user".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_test_app(todo_repository, LabelRepositoryforMemory::new());
+
+        let req = build_todo_req_with_empty(Method::GET, "/todos");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let todos: Vec<TodoEntity> = serde_json::from_slice(&bytes).unwrap();
+        assert!(todos.is_empty());
+
+        for (method, path) in [
+            (Method::GET, "/todos/1"),
+            (Method::DELETE, "/todos/1"),
+            (Method::GET, "/todos/1/comments"),
+        ] {
+            let req = build_todo_req_with_empty(method, path);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::NOT_FOUND, res.status());
+        }
+    }
+
+    #[tokio::test]
+    async fn should_register_and_login() {
+        let app = create_test_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryforMemory::new(),
+        );
+        let credentials = r#"{ "name": "alice", "password": "correct horse" }"#;
+        let req = Request::builder()
+            .uri("/users")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(credentials))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let login = |body: &'static str| {
+            Request::builder()
+                .uri("/login")
+                .method(Method::POST)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let res = app
+            .clone()
+            .oneshot(login(r#"{ "name": "alice", "password": "wrong horse" }"#))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let res = app.clone().oneshot(login(credentials)).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let token = body["token"].as_str().unwrap();
+
+        let todos = |token: &str| {
+            Request::builder()
+                .uri("/todos")
+                .method(Method::GET)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let res = app.clone().oneshot(todos(token)).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let res = app.oneshot(todos("forged")).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_enforce_token_scopes() {
+        let app = create_test_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryforMemory::new(),
+        );
+        // the token owner, user 1
+        let req = build_todo_req_with_json(
+            "/users",
+            Method::POST,
+            r#"{ "name": "ci", "password": "correct horse" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let req = build_todo_req_with_json(
+            "/tokens",
+            Method::POST,
+            r#"{ "name": "ci", "scopes": ["todos:read"] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let token = body["token"].as_str().unwrap().to_string();
+        assert!(token.starts_with("pat_"));
+
+        let with_token = |method: Method, path: &str| {
+            Request::builder()
+                .uri(path)
+                .method(method)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(r#"{ "text": "from ci", "labels": [] }"#))
+                .unwrap()
+        };
+        let res = app
+            .clone()
+            .oneshot(with_token(Method::GET, "/todos"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let res = app
+            .clone()
+            .oneshot(with_token(Method::POST, "/todos"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+        assert_eq!(
+            "Bearer realm=\"todo\", error=\"insufficient_scope\", scope=\"todos:write\"",
+            res.headers()[header::WWW_AUTHENTICATE]
+        );
+        let res = app
+            .clone()
+            .oneshot(with_token(Method::GET, "/tokens"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+
+        // revoked tokens stop working
+        let req = build_todo_req_with_empty(Method::DELETE, "/tokens/1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+        let res = app
+            .oneshot(with_token(Method::GET, "/todos"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_enforce_collaborator_roles() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(1, 2, CreateTodo::new("shared".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let collaborator_repository = CollaboratorRepositoryForMemory::new();
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryforMemory::new(),
+            CommentRepositoryForMemory::new(),
+            AttachmentRepositoryForMemory::new(),
+            StorageForMemory::new(),
+            AttachmentLimits::default(),
+            ReminderRepositoryForMemory::new(),
+            UserRepositoryForMemory::new(),
+            PersonalTokenRepositoryForMemory::new(),
+            collaborator_repository.clone(),
+            SessionRepositoryForMemory::new(),
+            SessionCookie::default(),
+            vec![HeaderValue::from_static("http://localhost:3001")],
+            WorkspaceResolver::new(WorkspaceRepositoryForMemory::new(), None),
+            None,
+            Features::default(),
+            HealthRepositoryForMemory::new(),
+            Cache::default(),
+            Broadcast::default(),
+            Shutdown::new().1,
+        );
+        let in_collection = |method: Method| {
+            let mut req = build_todo_req_with_json(
+                "/todos",
+                method,
+                r#"{ "text": "from collaborator", "labels": [] }"#.to_string(),
+            );
+            req.headers_mut()
+                .insert(auth::COLLECTION_HEADER, HeaderValue::from_static("2"));
+            req
+        };
+
+        let res = app
+            .clone()
+            .oneshot(in_collection(Method::GET))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+
+        collaborator_repository
+            .upsert(
+                2,
+                UpsertCollaborator {
+                    user_id: 1,
+                    role: Role::Viewer,
+                },
+            )
+            .await
+            .expect("failed share collection");
+        let res = app
+            .clone()
+            .oneshot(in_collection(Method::GET))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let todos: Vec<TodoEntity> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(1, todos.len());
+        let res = app
+            .clone()
+            .oneshot(in_collection(Method::POST))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+
+        collaborator_repository
+            .upsert(
+                2,
+                UpsertCollaborator {
+                    user_id: 1,
+                    role: Role::Editor,
+                },
+            )
+            .await
+            .expect("failed share collection");
+        let res = app
+            .clone()
+            .oneshot(in_collection(Method::POST))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        // only the owner may share
+        let mut req = build_todo_req_with_json(
+            "/collaborators",
+            Method::POST,
+            r#"{ "user_id": 3, "role": "viewer" }"#.to_string(),
+        );
+        req.headers_mut()
+            .insert(auth::COLLECTION_HEADER, HeaderValue::from_static("2"));
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_isolate_workspaces() {
+        let workspace_repository = WorkspaceRepositoryForMemory::new();
+        workspace_repository.insert("acme").await;
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(
+                1,
+                1,
+                CreateTodo::new("default workspace".to_string(), vec![]),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryforMemory::new(),
+            CommentRepositoryForMemory::new(),
+            AttachmentRepositoryForMemory::new(),
+            StorageForMemory::new(),
+            AttachmentLimits::default(),
+            ReminderRepositoryForMemory::new(),
+            UserRepositoryForMemory::new(),
+            PersonalTokenRepositoryForMemory::new(),
+            CollaboratorRepositoryForMemory::new(),
+            SessionRepositoryForMemory::new(),
+            SessionCookie::default(),
+            vec![HeaderValue::from_static("http://localhost:3001")],
+            WorkspaceResolver::new(workspace_repository, Some("todo.test".to_string())),
+            None,
+            Features::default(),
+            HealthRepositoryForMemory::new(),
+            Cache::default(),
+            Broadcast::default(),
+            Shutdown::new().1,
+        );
+
+        // a user of the default workspace can not name another one
+        for (workspace, status) in [
+            ("acme", StatusCode::FORBIDDEN),
+            ("missing", StatusCode::NOT_FOUND),
+        ] {
+            let mut req = build_todo_req_with_empty(Method::GET, "/todos");
+            req.headers_mut()
+                .insert(auth::WORKSPACE_HEADER, HeaderValue::from_static(workspace));
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(status, res.status());
+        }
+
+        // the same name registers once per workspace
+        let credentials = r#"{ "name": "alice", "password": "correct horse" }"#;
+        let in_acme = |uri: &str, body: &'static str| {
+            Request::builder()
+                .uri(uri)
+                .method(Method::POST)
+                .header(header::HOST, "acme.todo.test:3000")
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap()
+        };
+        for req in [
+            build_todo_req_with_json("/users", Method::POST, credentials.to_string()),
+            in_acme("/users", credentials),
+        ] {
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::CREATED, res.status());
+        }
+        let res = app
+            .clone()
+            .oneshot(in_acme("/login", credentials))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let token = body["token"].as_str().unwrap().to_string();
+
+        let todos = |workspace: &'static str| {
+            Request::builder()
+                .uri("/todos")
+                .method(Method::GET)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(auth::WORKSPACE_HEADER, workspace)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let res = app.clone().oneshot(todos("acme")).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let todos_of_acme: Vec<TodoEntity> = serde_json::from_slice(&bytes).unwrap();
+        assert!(todos_of_acme.is_empty());
+        let res = app.oneshot(todos("default")).await.unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_login_with_session_cookie() {
+        let app = create_test_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryforMemory::new(),
+        );
+        let credentials = r#"{ "name": "alice", "password": "correct horse" }"#;
+        let req = |method: Method, uri: &str, cookie: Option<&str>, csrf_token: Option<&str>| {
+            let mut builder = Request::builder()
+                .uri(uri)
+                .method(method)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref());
+            if let Some(cookie) = cookie {
+                builder = builder.header(header::COOKIE, cookie);
+            }
+            if let Some(csrf_token) = csrf_token {
+                builder = builder.header(auth::CSRF_HEADER, csrf_token);
+            }
+            let body = match uri {
+                "/todos" => r#"{ "text": "from browser", "labels": [] }"#,
+                _ => credentials,
+            };
+            builder.body(Body::from(body)).unwrap()
+        };
+        let res = app
+            .clone()
+            .oneshot(req(Method::POST, "/users", None, None))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let res = app
+            .clone()
+            .oneshot(req(Method::POST, "/sessions", None, None))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let set_cookie = res.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(set_cookie.contains("HttpOnly"));
+        assert!(set_cookie.contains("SameSite=Strict"));
+        assert!(set_cookie.contains("Secure"));
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let csrf_token = body["csrf_token"].as_str().unwrap().to_string();
+
+        let res = app
+            .clone()
+            .oneshot(req(Method::GET, "/todos", Some(&cookie), None))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let res = app
+            .clone()
+            .oneshot(req(Method::POST, "/todos", Some(&cookie), None))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+        let res = app
+            .clone()
+            .oneshot(req(Method::POST, "/todos", Some(&cookie), Some("forged")))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+        let res = app
+            .clone()
+            .oneshot(req(
+                Method::POST,
+                "/todos",
+                Some(&cookie),
+                Some(&csrf_token),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let res = app
+            .clone()
+            .oneshot(req(Method::GET, "/sessions", Some(&cookie), None))
+            .await
+            .unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(csrf_token, body["csrf_token"]);
+
+        let res = app
+            .clone()
+            .oneshot(req(
+                Method::DELETE,
+                "/sessions",
+                Some(&cookie),
+                Some(&csrf_token),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+        assert!(res.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .contains("Max-Age=0"));
+        let res = app
+            .clone()
+            .oneshot(req(Method::GET, "/todos", Some(&cookie), None))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+
+        // the frontend origin may send cookies
+        let preflight = Request::builder()
+            .uri("/todos")
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, "http://localhost:3001")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(preflight).await.unwrap();
+        assert_eq!(
+            "true",
+            res.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS]
+        );
+    }
+
+    /// an app whose todo and label writes are published to watchers
+    fn create_broadcasting_app(
+        shutdown: Shutdown,
+        collaborator_repository: CollaboratorRepositoryForMemory,
+    ) -> Router {
+        let broadcast = Broadcast::default();
+        create_app(
+            BroadcastTodoRepository::new(TodoRepositoryForMemory::new(vec![]), broadcast.clone()),
+            BroadcastLabelRepository::new(LabelRepositoryforMemory::new(), broadcast.clone()),
+            CommentRepositoryForMemory::new(),
+            AttachmentRepositoryForMemory::new(),
+            StorageForMemory::new(),
+            AttachmentLimits::default(),
+            ReminderRepositoryForMemory::new(),
+            UserRepositoryForMemory::new(),
+            PersonalTokenRepositoryForMemory::new(),
+            collaborator_repository,
+            SessionRepositoryForMemory::new(),
+            SessionCookie::default(),
+            vec![HeaderValue::from_static("http://localhost:3001")],
+            WorkspaceResolver::new(WorkspaceRepositoryForMemory::new(), None),
+            None,
+            Features::default(),
+            HealthRepositoryForMemory::new(),
+            Cache::default(),
+            broadcast,
+            shutdown,
+        )
+    }
+
+    #[tokio::test]
+    async fn should_stream_changes() {
+        use hyper::body::HttpBody;
+
+        let (trigger, shutdown) = Shutdown::new();
+        let app = create_broadcasting_app(shutdown, CollaboratorRepositoryForMemory::new());
+        let res = app
+            .clone()
+            .oneshot(build_todo_req_with_empty(Method::GET, "/events"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!("text/event-stream", res.headers()[header::CONTENT_TYPE]);
+        let mut body = res.into_body();
+
+        let req = build_todo_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "should_stream_changes", "labels": [] }"#.to_string(),
+        );
+        let todo = res_to_todo(app.oneshot(req).await.unwrap()).await;
+        let chunk = body.data().await.unwrap().unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(chunk.contains("event: todo.created\n"), "{}", chunk);
+        assert!(chunk.contains("id: 1\n"), "{}", chunk);
+        assert!(chunk.contains(&format!(r#""id":{}"#, todo.id)), "{}", chunk);
+
+        // the stream ends with the server instead of holding up its shutdown
+        trigger.send(true).unwrap();
+        assert!(body.data().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn should_end_stream_once_collaborator_is_removed() {
+        use hyper::body::HttpBody;
+
+        let (_trigger, shutdown) = Shutdown::new();
+        let collaborator_repository = CollaboratorRepositoryForMemory::new();
+        collaborator_repository
+            .upsert(
+                2,
+                UpsertCollaborator {
+                    user_id: 1,
+                    role: Role::Viewer,
+                },
+            )
+            .await
+            .expect("failed share collection");
+        let app = create_broadcasting_app(shutdown, collaborator_repository.clone());
+        let mut req = build_todo_req_with_empty(Method::GET, "/events");
+        req.headers_mut()
+            .insert(auth::COLLECTION_HEADER, HeaderValue::from_static("2"));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let mut body = res.into_body();
+        // the owner writes to their own collection
+        let create = || {
+            Request::builder()
+                .uri("/todos")
+                .method(Method::POST)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .extension(AuthUser {
+                    id: 2,
+                    workspace_id: 1,
+                })
+                .body(Body::from(r#"{ "text": "shared", "labels": [] }"#))
+                .unwrap()
+        };
+
+        app.clone().oneshot(create()).await.unwrap();
+        let chunk = body.data().await.unwrap().unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(chunk.contains("event: todo.created\n"), "{}", chunk);
+
+        collaborator_repository
+            .delete(2, 1)
+            .await
+            .expect("failed unshare collection");
+        app.oneshot(create()).await.unwrap();
+        assert!(body.data().await.is_none());
+    }
+
+    type Socket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn socket_call(socket: &mut Socket, request: serde_json::Value) -> serde_json::Value {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        socket
+            .send(Message::Text(request.to_string()))
+            .await
+            .unwrap();
+        socket_receive(socket).await
+    }
+
+    async fn socket_receive(socket: &mut Socket) -> serde_json::Value {
+        use futures::StreamExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => serde_json::from_str(&text).unwrap(),
+            message => panic!("expected a text message, got {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_write_over_socket() {
+        use futures::StreamExt;
+        use serde_json::json;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let (trigger, shutdown) = Shutdown::new();
+        // the upgrade needs a real connection, so the user comes from a layer
+        let app = create_broadcasting_app(shutdown, CollaboratorRepositoryForMemory::new()).layer(
+            Extension(AuthUser {
+                id: 1,
+                workspace_id: 1,
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+            .await
+            .unwrap();
+
+        let reply = socket_call(
+            &mut socket,
+            json!({ "type": "create", "ref": 1, "todo": { "text": "", "labels": [] } }),
+        )
+        .await;
+        assert_eq!("error", reply["type"]);
+        assert_eq!(1, reply["ref"]);
+        assert_eq!(400, reply["status"]);
+        assert!(reply["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Validation error"));
+
+        let reply = socket_call(&mut socket, json!({ "type": "subscribe", "ref": "s" })).await;
+        assert_eq!(
+            json!({ "type": "subscribed", "ref": "s", "todos": [] }),
+            reply
+        );
+
+        let reply = socket_call(
+            &mut socket,
+            json!({ "type": "create", "ref": 2, "todo": { "text": "over the socket", "labels": [] } }),
+        )
+        .await;
+        assert_eq!("ack", reply["type"]);
+        assert_eq!(201, reply["status"]);
+        let id = reply["todo"]["id"].clone();
+        let event = socket_receive(&mut socket).await;
+        assert_eq!("event", event["type"]);
+        assert_eq!("todo.created", event["kind"]);
+        assert_eq!(id, event["data"]["id"]);
+
+        let reply = socket_call(&mut socket, json!({ "type": "delete", "ref": 3, "id": id })).await;
+        assert_eq!(json!({ "type": "ack", "ref": 3, "status": 204 }), reply);
+        let event = socket_receive(&mut socket).await;
+        assert_eq!("todo.deleted", event["kind"]);
+        let reply = socket_call(&mut socket, json!({ "type": "delete", "ref": 4, "id": id })).await;
+        assert_eq!("error", reply["type"]);
+        assert_eq!(404, reply["status"]);
+
+        // the socket is closed by the server instead of holding up its shutdown
+        trigger.send(true).unwrap();
+        assert!(matches!(
+            socket.next().await,
+            Some(Ok(Message::Close(_))) | None
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_recheck_collaborator_role_over_socket() {
+        use serde_json::json;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let (trigger, shutdown) = Shutdown::new();
+        let collaborator_repository = CollaboratorRepositoryForMemory::new();
+        let share = |role: Role| {
+            let collaborator_repository = collaborator_repository.clone();
+            async move {
+                collaborator_repository
+                    .upsert(2, UpsertCollaborator { user_id: 1, role })
+                    .await
+                    .expect("failed share collection");
+            }
+        };
+        share(Role::Editor).await;
+        let app = create_broadcasting_app(shutdown, collaborator_repository.clone()).layer(
+            Extension(AuthUser {
+                id: 1,
+                workspace_id: 1,
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert(auth::COLLECTION_HEADER, HeaderValue::from_static("2"));
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let create = |reference: i32| json!({ "type": "create", "ref": reference, "todo": { "text": "shared", "labels": [] } });
+
+        let reply = socket_call(&mut socket, create(1)).await;
+        assert_eq!(201, reply["status"]);
+
+        // the role taken at connect does not outlive a downgrade
+        share(Role::Viewer).await;
+        let reply = socket_call(&mut socket, create(2)).await;
+        assert_eq!("error", reply["type"]);
+        assert_eq!(403, reply["status"]);
+        let reply = socket_call(&mut socket, json!({ "type": "subscribe", "ref": 3 })).await;
+        assert_eq!("subscribed", reply["type"]);
+        assert_eq!(1, reply["todos"].as_array().unwrap().len());
+
+        collaborator_repository
+            .delete(2, 1)
+            .await
+            .expect("failed unshare collection");
+        let reply = socket_call(&mut socket, json!({ "type": "subscribe", "ref": 4 })).await;
+        assert_eq!("error", reply["type"]);
+        assert_eq!(403, reply["status"]);
+
+        trigger.send(true).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_report_health() {
+        let health_repository = HealthRepositoryForMemory::new();
+        let (trigger, shutdown) = Shutdown::new();
+        let cache = Cache::new(Duration::from_secs(60), 10);
+        let app = create_app(
+            CachedTodoRepository::new(TodoRepositoryForMemory::new(vec![]), cache.clone()),
+            CachedLabelRepository::new(LabelRepositoryforMemory::new(), cache.clone()),
+            CommentRepositoryForMemory::new(),
+            AttachmentRepositoryForMemory::new(),
+            StorageForMemory::new(),
+            AttachmentLimits::default(),
+            ReminderRepositoryForMemory::new(),
+            UserRepositoryForMemory::new(),
+            PersonalTokenRepositoryForMemory::new(),
+            CollaboratorRepositoryForMemory::new(),
+            SessionRepositoryForMemory::new(),
+            SessionCookie::default(),
+            vec![HeaderValue::from_static("http://localhost:3001")],
+            WorkspaceResolver::new(WorkspaceRepositoryForMemory::new(), None),
+            None,
+            Features::default(),
+            health_repository.clone(),
+            cache,
+            Broadcast::default(),
+            shutdown,
+        );
+        let get = |path: &str| {
+            Request::builder()
+                .uri(path)
+                .method(Method::GET)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let body = |res: Response| async {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+
+        let res = app.clone().oneshot(get("/healthz")).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let res = app.clone().oneshot(get("/version")).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(env!("CARGO_PKG_VERSION"), body(res).await["version"]);
+
+        let res = app.clone().oneshot(get("/readyz")).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(1, body(res).await["migrations"]["applied"]);
+        health_repository.fail();
+        let res = app.clone().oneshot(get("/readyz")).await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+
+        for _ in 0..2 {
+            let res = app
+                .clone()
+                .oneshot(build_todo_req_with_empty(Method::GET, "/todos"))
+                .await
+                .unwrap();
+            assert_eq!(StatusCode::OK, res.status());
+        }
+        let res = app.clone().oneshot(get("/metrics")).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let metrics = String::from_utf8(bytes.to_vec()).unwrap();
+        for line in [
+            r#"todo_cache_requests_total{repository="todos",result="hit"} 1"#,
+            r#"todo_cache_requests_total{repository="todos",result="miss"} 1"#,
+            r#"todo_cache_hit_ratio{repository="todos"} 0.5"#,
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{}", metrics);
+        }
+
+        // still alive, but no longer taking traffic while shutting down
+        trigger.send(true).unwrap();
+        let res = app.clone().oneshot(get("/readyz")).await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        assert_eq!("shutting down", body(res).await["status"]);
+        let res = app.oneshot(get("/healthz")).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+    }
 }
@@ -0,0 +1,160 @@
+use axum::async_trait;
+use sqlx::{migrate::Migrator, PgPool, SqlitePool};
+use thiserror::Error;
+
+/// the files of `migrations/`, embedded at build time
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+/// the files of `migrations/sqlite/`, sqlx skips the subdirectory for `MIGRATOR`
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("the database has migration {0} which this build does not know, upgrade the server")]
+    Unknown(i64),
+    #[error("migration {0} failed halfway and has to be fixed by hand")]
+    Dirty(i64),
+    #[error(transparent)]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// a database with its own set of embedded migrations
+#[async_trait]
+pub trait Schema: Send + Sync {
+    fn migrator(&self) -> &'static Migrator;
+    /// versions in the tracking table of sqlx with whether they succeeded
+    async fn applied(&self) -> Result<Vec<(i64, bool)>, sqlx::Error>;
+    async fn run(&self) -> Result<(), sqlx::migrate::MigrateError>;
+}
+
+#[async_trait]
+impl Schema for PgPool {
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+
+    async fn applied(&self) -> Result<Vec<(i64, bool)>, sqlx::Error> {
+        // sqlx creates the table with the first migration it runs
+        let tracked: bool =
+            sqlx::query_scalar("select to_regclass('_sqlx_migrations') is not null")
+                .fetch_one(self)
+                .await?;
+        if !tracked {
+            return Ok(vec![]);
+        }
+        sqlx::query_as("select version, success from _sqlx_migrations order by version")
+            .fetch_all(self)
+            .await
+    }
+
+    async fn run(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        MIGRATOR.run(self).await
+    }
+}
+
+#[async_trait]
+impl Schema for SqlitePool {
+    fn migrator(&self) -> &'static Migrator {
+        &SQLITE_MIGRATOR
+    }
+
+    async fn applied(&self) -> Result<Vec<(i64, bool)>, sqlx::Error> {
+        let tracked: bool = sqlx::query_scalar(
+            "select exists(select 1 from sqlite_master where type = 'table' and name = '_sqlx_migrations')",
+        )
+        .fetch_one(self)
+        .await?;
+        if !tracked {
+            return Ok(vec![]);
+        }
+        sqlx::query_as("select version, success from _sqlx_migrations order by version")
+            .fetch_all(self)
+            .await
+    }
+
+    async fn run(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        SQLITE_MIGRATOR.run(self).await
+    }
+}
+
+/// embedded migrations the database has not applied yet
+pub fn pending(migrator: &Migrator, applied: &[(i64, bool)]) -> usize {
+    migrator
+        .iter()
+        .filter(|migration| {
+            !applied
+                .iter()
+                .any(|(version, _)| *version == migration.version)
+        })
+        .count()
+}
+
+fn check(migrator: &Migrator, applied: &[(i64, bool)]) -> Result<(), MigrationError> {
+    for &(version, success) in applied {
+        if !migrator
+            .iter()
+            .any(|migration| migration.version == version)
+        {
+            return Err(MigrationError::Unknown(version));
+        }
+        if !success {
+            return Err(MigrationError::Dirty(version));
+        }
+    }
+    Ok(())
+}
+
+/// refuses a schema newer than this build, then applies the pending
+/// migrations when `run` is set and only warns about them otherwise
+pub async fn prepare(pool: &impl Schema, run: bool) -> Result<(), MigrationError> {
+    let applied = pool.applied().await?;
+    check(pool.migrator(), &applied)?;
+    let pending = pending(pool.migrator(), &applied);
+    if pending == 0 {
+        tracing::debug!("database schema is up to date");
+    } else if run {
+        tracing::info!("applying {} migrations", pending);
+        pool.run().await?;
+    } else {
+        tracing::warn!(
+            "{} migrations are pending, start with --migrate to apply them",
+            pending
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_test() {
+        let known: Vec<_> = MIGRATOR.iter().map(|m| (m.version, true)).collect();
+        assert!(check(&MIGRATOR, &known).is_ok());
+        assert_eq!(0, pending(&MIGRATOR, &known));
+        assert_eq!(1, pending(&MIGRATOR, &known[1..]));
+        assert_eq!(
+            SQLITE_MIGRATOR.iter().count(),
+            pending(&SQLITE_MIGRATOR, &[])
+        );
+        assert!(matches!(
+            check(&SQLITE_MIGRATOR, &known),
+            Err(MigrationError::Unknown(_))
+        ));
+
+        let mut newer = known.clone();
+        newer.push((i64::MAX, true));
+        assert!(matches!(
+            check(&MIGRATOR, &newer),
+            Err(MigrationError::Unknown(i64::MAX))
+        ));
+        let mut dirty = known;
+        dirty.last_mut().unwrap().1 = false;
+        assert!(matches!(
+            check(&MIGRATOR, &dirty),
+            Err(MigrationError::Dirty(_))
+        ));
+    }
+}
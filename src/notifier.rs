@@ -0,0 +1,283 @@
+use anyhow::{bail, Context};
+use axum::async_trait;
+use hyper::{client::HttpConnector, Body, Client, Method, Request, Uri};
+use hyper_rustls::HttpsConnector;
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use crate::repositories::reminder::DueReminder;
+
+/// delivers a due reminder somewhere, an error lets the scheduler retry later
+#[async_trait]
+pub trait Notifier: std::marker::Send + std::marker::Sync + 'static {
+    async fn notify(&self, reminder: &DueReminder) -> anyhow::Result<()>;
+}
+
+/// writes reminders to the log
+#[derive(Debug, Clone, Default)]
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, reminder: &DueReminder) -> anyhow::Result<()> {
+        tracing::info!(
+            "reminder {} for todo {}: {}",
+            reminder.id,
+            reminder.todo_id,
+            reminder.text
+        );
+        Ok(())
+    }
+}
+
+/// sends a plain text mail through an SMTP relay without authentication
+#[derive(Debug, Clone)]
+pub struct SmtpNotifier {
+    addr: String,
+    from: String,
+    to: String,
+}
+
+impl SmtpNotifier {
+    pub fn new(addr: String, from: String, to: String) -> Self {
+        Self { addr, from, to }
+    }
+
+    fn message(&self, reminder: &DueReminder) -> String {
+        let body = format!(
+            "Reminder for todo #{}:\r\n{}\r\n",
+            reminder.todo_id, reminder.text
+        );
+        // dot-stuffing, a line of a single "." would end DATA early
+        let body = body
+            .split("\r\n")
+            .map(|line| match line.strip_prefix('.') {
+                Some(_) => format!(".{}", line),
+                None => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        format!(
+            "From: <{}>\r\nTo: <{}>\r\nSubject: Reminder: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            self.from,
+            self.to,
+            reminder.text.replace(['\r', '\n'], " "),
+            body
+        )
+    }
+}
+
+/// reads one (possibly multiline) reply and checks its code
+async fn expect_reply<R: AsyncBufReadExt + Unpin>(
+    reader: &mut R,
+    code: &str,
+) -> anyhow::Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            bail!("smtp connection closed, expected {}", code);
+        }
+        if !line.starts_with(code) {
+            bail!("smtp unexpected reply: {}", line.trim_end());
+        }
+        // "250-..." continues, "250 ..." is the last line
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    async fn notify(&self, reminder: &DueReminder) -> anyhow::Result<()> {
+        let stream = TcpStream::connect(&self.addr)
+            .await
+            .with_context(|| format!("smtp connect to {}", self.addr))?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        expect_reply(&mut reader, "220").await?;
+        let commands = [
+            ("HELO localhost\r\n".to_string(), "250"),
+            (format!("MAIL FROM:<{}>\r\n", self.from), "250"),
+            (format!("RCPT TO:<{}>\r\n", self.to), "25"),
+            ("DATA\r\n".to_string(), "354"),
+            (format!("{}\r\n.\r\n", self.message(reminder)), "250"),
+            ("QUIT\r\n".to_string(), "221"),
+        ];
+        for (command, code) in commands {
+            writer.write_all(command.as_bytes()).await?;
+            expect_reply(&mut reader, code).await?;
+        }
+        Ok(())
+    }
+}
+
+/// posts reminders as JSON to an URL
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    url: Uri,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: Uri) -> Self {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Self {
+            url,
+            client: Client::builder().build(connector),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, reminder: &DueReminder) -> anyhow::Result<()> {
+        let body = json!({
+            "reminder_id": reminder.id,
+            "todo_id": reminder.todo_id,
+            "text": reminder.text,
+            "remind_at": reminder.remind_at,
+        });
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(hyper::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(body.to_string()))?;
+        let res = self.client.request(req).await?;
+        if !res.status().is_success() {
+            bail!("webhook responded {}", res.status());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+    use tokio::net::TcpListener;
+
+    fn reminder() -> DueReminder {
+        DueReminder {
+            id: 1,
+            todo_id: 2,
+            text: "call back".to_string(),
+            remind_at: Utc::now(),
+            attempts: 0,
+        }
+    }
+
+    /// local stand-in for an SMTP relay, returns everything sent after DATA
+    async fn smtp_stand_in() -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            writer.write_all(b"220 stand-in\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        writer.write_all(b"250 queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match &line[..4] {
+                    "DATA" => {
+                        in_data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => {
+                        writer.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    "HELO" => b"250-stand-in\r\n250 ok\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            data
+        });
+        (addr, handle)
+    }
+
+    #[tokio::test]
+    async fn smtp_notifier_test() {
+        let (addr, handle) = smtp_stand_in().await;
+        let notifier = SmtpNotifier::new(
+            addr,
+            "todo@localhost".to_string(),
+            "me@localhost".to_string(),
+        );
+        notifier.notify(&reminder()).await.expect("failed notify");
+        let data = handle.await.unwrap();
+        assert!(data.contains("Subject: Reminder: call back"));
+        assert!(data.contains("Reminder for todo #2"));
+    }
+
+    #[tokio::test]
+    async fn smtp_notifier_fails_without_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let notifier =
+            SmtpNotifier::new(addr, "a@localhost".to_string(), "b@localhost".to_string());
+        assert!(notifier.notify(&reminder()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn webhook_notifier_test() {
+        let bodies: Arc<Mutex<Vec<serde_json::Value>>> = Arc::default();
+        let received = bodies.clone();
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let received = received.clone();
+            async move {
+                Ok::<_, Infallible>(hyper::service::service_fn(move |req: Request<Body>| {
+                    let received = received.clone();
+                    async move {
+                        let bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        received
+                            .lock()
+                            .unwrap()
+                            .push(serde_json::from_slice(&bytes).unwrap());
+                        Ok::<_, Infallible>(hyper::Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server =
+            hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+
+        let notifier = WebhookNotifier::new(url.parse().unwrap());
+        notifier.notify(&reminder()).await.expect("failed notify");
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(1, bodies.len());
+        assert_eq!("call back", bodies[0]["text"]);
+        assert_eq!(2, bodies[0]["todo_id"]);
+    }
+}
@@ -1,14 +1,105 @@
+pub mod attachment;
+pub mod broadcast;
+pub mod cache;
+pub mod cleanup;
+pub mod collaborator;
+pub mod comment;
+#[cfg(test)]
+mod conformance;
+pub mod event;
+pub mod health;
+pub mod item;
 pub mod label;
+pub mod reminder;
+pub mod replica;
+pub mod retry;
+pub mod session;
 pub mod todo;
+pub mod token;
+pub mod user;
+pub mod workspace;
 
 use thiserror::Error;
 
-#[derive(Debug, Error)]
-enum RepositoryError {
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RepositoryError {
     #[error("Unexpected Error: [{0}]")]
     Unexpected(String),
     #[error("NotFound, id is {0}")]
     NotFound(i32),
     #[error("Duplicate data, id is {0}")]
     Duplicate(i32),
+    #[error("Dependency cycle, id is {0}")]
+    Cycle(i32),
+    #[error("Blocked by open todos, id is {0}")]
+    Blocked(i32),
+    #[error("History is not recorded, id is {0}")]
+    NoHistory(i32),
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use crate::migrate;
+    use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// a fresh sqlite database in a temp file with the migrations applied,
+    /// every test gets its own so they can run in parallel
+    pub async fn prepare_sqlite_pool() -> SqlitePool {
+        static SEQ: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "todo-test-{}-{}.db",
+            std::process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        // a reused pid may have left a database behind, along with its wal
+        for suffix in ["", "-wal", "-shm"] {
+            let mut stale = path.clone().into_os_string();
+            stale.push(suffix);
+            let _ = std::fs::remove_file(stale);
+        }
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .expect("fail connect sqlite database");
+        migrate::prepare(&pool, true)
+            .await
+            .expect("fail migrate sqlite database");
+        pool
+    }
+
+    pub async fn prepare_sqlite_test_workspace(pool: &SqlitePool, slug: &str) -> i32 {
+        let (workspace_id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            insert into workspaces (slug, name)
+            values ($1, $1)
+            on conflict (slug) do update set name = excluded.name
+            returning id
+            "#,
+        )
+        .bind(slug)
+        .fetch_one(pool)
+        .await
+        .expect("Failed to prepare workspace data.");
+        workspace_id
+    }
+
+    pub async fn prepare_sqlite_test_user(pool: &SqlitePool, workspace_id: i32, name: &str) -> i32 {
+        let (user_id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            insert into users (workspace_id, name, password_hash)
+            values ($1, $2, '')
+            on conflict (workspace_id, name) do update set name = excluded.name
+            returning id
+            "#,
+        )
+        .bind(workspace_id)
+        .bind(name)
+        .fetch_one(pool)
+        .await
+        .expect("Failed to prepare user data.");
+        user_id
+    }
 }
@@ -0,0 +1,357 @@
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+
+/// operation to metadata of files attached to a TODO, blobs live in a `Storage`
+/// create: POST -- register an uploaded file
+/// find: GET -- a file of a TODO
+/// all: GET -- files of a TODO
+/// delete: DELETE -- unregister a file
+#[async_trait]
+pub trait AttachmentRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, todo_id: i32, payload: CreateAttachment) -> anyhow::Result<Attachment>;
+    async fn find(&self, todo_id: i32, id: i32) -> anyhow::Result<Attachment>;
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Attachment>>;
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct Attachment {
+    pub id: i32,
+    pub todo_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Attachment {
+    /// key of the blob in the storage
+    pub fn storage_key(&self) -> String {
+        format!("{}/{}", Self::storage_prefix(self.todo_id), self.id)
+    }
+
+    /// what the keys of the blobs of a todo start with
+    pub fn storage_prefix(todo_id: i32) -> String {
+        format!("todos/{}", todo_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateAttachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttachmentRepositoryForDB {
+    pool: PgPool,
+}
+
+impl AttachmentRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AttachmentRepository for AttachmentRepositoryForDB {
+    async fn create(&self, todo_id: i32, payload: CreateAttachment) -> anyhow::Result<Attachment> {
+        let attachment = sqlx::query_as::<_, Attachment>(
+            r#"
+            insert into attachments (todo_id, file_name, content_type, size)
+            values ($1, $2, $3, $4)
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.file_name)
+        .bind(payload.content_type)
+        .bind(payload.size)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(attachment)
+    }
+
+    async fn find(&self, todo_id: i32, id: i32) -> anyhow::Result<Attachment> {
+        retry::read(|| async move {
+            let attachment = sqlx::query_as::<_, Attachment>(
+                r#"
+                select * from attachments where todo_id = $1 and id = $2
+                "#,
+            )
+            .bind(todo_id)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(id))?;
+
+            Ok(attachment)
+        })
+        .await
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Attachment>> {
+        retry::read(|| async move {
+            let attachments = sqlx::query_as::<_, Attachment>(
+                r#"
+                select * from attachments
+                where todo_id = $1
+                order by attachments.id asc
+                "#,
+            )
+            .bind(todo_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(attachments)
+        })
+        .await
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from attachments where todo_id = $1 and id = $2
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttachmentRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl AttachmentRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AttachmentRepository for AttachmentRepositoryForSqlite {
+    async fn create(&self, todo_id: i32, payload: CreateAttachment) -> anyhow::Result<Attachment> {
+        let attachment = sqlx::query_as::<_, Attachment>(
+            r#"
+            insert into attachments (todo_id, file_name, content_type, size, created_at)
+            values ($1, $2, $3, $4, $5)
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.file_name)
+        .bind(payload.content_type)
+        .bind(payload.size)
+        .bind(Utc::now())
+        // read to the end, a statement left unfinished keeps the write uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or_else(|| RepositoryError::Unexpected("no attachment returned".to_string()))?;
+
+        Ok(attachment)
+    }
+
+    async fn find(&self, todo_id: i32, id: i32) -> anyhow::Result<Attachment> {
+        let attachment = sqlx::query_as::<_, Attachment>(
+            r#"
+            select * from attachments where todo_id = $1 and id = $2
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(attachment)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Attachment>> {
+        let attachments = sqlx::query_as::<_, Attachment>(
+            r#"
+            select * from attachments
+            where todo_id = $1
+            order by attachments.id asc
+            "#,
+        )
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(attachments)
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from attachments where todo_id = $1 and id = $2
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+}
+
+/// keeps attachments in a table of a `Journal`
+#[derive(Debug, Clone)]
+pub struct AttachmentRepositoryForMemory {
+    store: Table<i32, Attachment>,
+}
+
+impl AttachmentRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("attachments")?,
+        })
+    }
+}
+
+#[async_trait]
+impl AttachmentRepository for AttachmentRepositoryForMemory {
+    async fn create(&self, todo_id: i32, payload: CreateAttachment) -> anyhow::Result<Attachment> {
+        let mut store = self.store.write().await;
+        let id = store.next_id();
+        let attachment = Attachment {
+            id,
+            todo_id,
+            file_name: payload.file_name,
+            content_type: payload.content_type,
+            size: payload.size,
+            created_at: Utc::now(),
+        };
+        store.insert(id, attachment.clone());
+        store.commit().await?;
+        Ok(attachment)
+    }
+
+    async fn find(&self, todo_id: i32, id: i32) -> anyhow::Result<Attachment> {
+        let store = self.store.read().await;
+        let attachment = store
+            .get(&id)
+            .filter(|attachment| attachment.todo_id == todo_id)
+            .cloned()
+            .context(RepositoryError::NotFound(id))?;
+        Ok(attachment)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Attachment>> {
+        let store = self.store.read().await;
+        Ok(store
+            .values()
+            .filter(|attachment| attachment.todo_id == todo_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        store
+            .get(&id)
+            .filter(|attachment| attachment.todo_id == todo_id)
+            .context(RepositoryError::NotFound(id))?;
+        store.remove(&id);
+        store.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::todo::{CreateTodo, TodoRepository, TodoRepositoryForDB};
+    use crate::repositories::user::test_utils::prepare_test_user;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+
+        let user_id = prepare_test_user(&pool).await;
+        let todo_repository = TodoRepositoryForDB::new(pool.clone());
+        let todo = todo_repository
+            .create(
+                1,
+                user_id,
+                CreateTodo::new("[attachment crud_scenario] todo".to_string(), vec![]),
+            )
+            .await
+            .expect("[create todo] returned Err");
+
+        let repository = AttachmentRepositoryForDB::new(pool.clone());
+
+        // create
+        let attachment = repository
+            .create(
+                todo.id,
+                CreateAttachment {
+                    file_name: "screenshot.png".to_string(),
+                    content_type: "image/png".to_string(),
+                    size: 42,
+                },
+            )
+            .await
+            .expect("[create] returned Err");
+        assert_eq!(attachment.file_name, "screenshot.png");
+
+        // find
+        let found = repository
+            .find(todo.id, attachment.id)
+            .await
+            .expect("[find] returned Err");
+        assert_eq!(attachment, found);
+
+        // all
+        let attachments = repository.all(todo.id).await.expect("[all] returned Err");
+        assert_eq!(vec![attachment.clone()], attachments);
+
+        // rows follow the todo
+        todo_repository
+            .delete(1, user_id, todo.id)
+            .await
+            .expect("[delete todo] returned Err");
+        assert!(repository.all(todo.id).await.unwrap().is_empty());
+        assert!(repository.delete(todo.id, attachment.id).await.is_err());
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    impl AttachmentRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! todo and label writes published to everyone watching the collection, recent
+//! events stay in a bounded buffer so a watcher can resume after reconnecting
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::{
+    item::{CreateItem, TodoItem, UpdateItem},
+    label::{Label, LabelRepository},
+    todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+};
+
+/// events kept for resuming, a watcher further behind has to reload
+pub const BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// counts up from 1 in every process
+    pub id: u64,
+    pub workspace_id: i32,
+    pub owner_id: i32,
+    /// `todo.created`, `todo.updated`, `todo.deleted`, `label.created`, `label.updated`
+    /// or `label.deleted`
+    pub kind: &'static str,
+    /// the todo or label as json, `{"id":..}` once it is deleted
+    pub data: String,
+}
+
+/// what a watcher receives, `Reset` when events were lost on the way and the
+/// collection has to be reloaded, with the id to resume from afterwards if known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Event(Event),
+    Reset(Option<u64>),
+}
+
+#[derive(Debug)]
+struct Buffer {
+    last_id: u64,
+    events: VecDeque<Event>,
+    capacity: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Broadcast {
+    sender: broadcast::Sender<Event>,
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+impl Default for Broadcast {
+    fn default() -> Self {
+        Self::new(BUFFER)
+    }
+}
+
+impl Broadcast {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            buffer: Arc::new(Mutex::new(Buffer {
+                last_id: 0,
+                events: VecDeque::with_capacity(capacity),
+                capacity,
+            })),
+        }
+    }
+
+    fn publish(&self, workspace_id: i32, owner_id: i32, kind: &'static str, data: impl Serialize) {
+        let data = match serde_json::to_string(&data) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::error!("failed to serialize {} event: {}", kind, e);
+                return;
+            }
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.last_id += 1;
+        let event = Event {
+            id: buffer.last_id,
+            workspace_id,
+            owner_id,
+            kind,
+            data,
+        };
+        if buffer.events.len() == buffer.capacity {
+            buffer.events.pop_front();
+        }
+        buffer.events.push_back(event.clone());
+        // sent under the lock, so a new subscription sees every event exactly once;
+        // nobody listening is not an error
+        let _ = self.sender.send(event);
+    }
+
+    /// events of the collection from now on, preceded by the buffered ones after
+    /// `last_event_id` when resuming
+    pub fn subscribe(
+        &self,
+        workspace_id: i32,
+        owner_id: i32,
+        last_event_id: Option<u64>,
+    ) -> Subscription {
+        let buffer = self.buffer.lock().unwrap();
+        let mut backlog = VecDeque::new();
+        if let Some(last_event_id) = last_event_id {
+            let oldest = buffer
+                .events
+                .front()
+                .map_or(buffer.last_id + 1, |event| event.id);
+            // ids of an earlier process, or events which fell out of the buffer
+            if last_event_id > buffer.last_id || last_event_id + 1 < oldest {
+                backlog.push_back(Message::Reset(Some(buffer.last_id)));
+            } else {
+                backlog.extend(
+                    buffer
+                        .events
+                        .iter()
+                        .filter(|event| event.id > last_event_id)
+                        .filter(|event| {
+                            event.workspace_id == workspace_id && event.owner_id == owner_id
+                        })
+                        .cloned()
+                        .map(Message::Event),
+                );
+            }
+        }
+        Subscription {
+            workspace_id,
+            owner_id,
+            backlog,
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Subscription {
+    workspace_id: i32,
+    owner_id: i32,
+    backlog: VecDeque<Message>,
+    receiver: broadcast::Receiver<Event>,
+}
+
+impl Subscription {
+    /// waits for the next message of the collection
+    pub async fn next(&mut self) -> Option<Message> {
+        if let Some(message) = self.backlog.pop_front() {
+            return Some(message);
+        }
+        loop {
+            match self.receiver.recv().await {
+                Ok(event)
+                    if event.workspace_id == self.workspace_id
+                        && event.owner_id == self.owner_id =>
+                {
+                    return Some(Message::Event(event))
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => return Some(Message::Reset(None)),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// publishes every successful write, checklist and blocker changes as `todo.updated`
+#[derive(Debug, Clone)]
+pub struct BroadcastTodoRepository<R> {
+    inner: R,
+    broadcast: Broadcast,
+}
+
+impl<R: TodoRepository> BroadcastTodoRepository<R> {
+    pub fn new(inner: R, broadcast: Broadcast) -> Self {
+        Self { inner, broadcast }
+    }
+
+    fn updated(&self, workspace_id: i32, user_id: i32, todo: &TodoEntity) {
+        self.broadcast
+            .publish(workspace_id, user_id, "todo.updated", todo);
+    }
+
+    /// the write of a part of the todo went through, watchers get the whole todo
+    async fn changed<T>(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        result: anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        if result.is_ok() {
+            match self.inner.find(workspace_id, user_id, todo_id).await {
+                Ok(todo) => self.updated(workspace_id, user_id, &todo),
+                Err(e) => tracing::warn!("changed todo {} is gone: {}", todo_id, e),
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepository> TodoRepository for BroadcastTodoRepository<R> {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let todo = self.inner.create(workspace_id, user_id, payload).await?;
+        self.broadcast
+            .publish(workspace_id, user_id, "todo.created", &todo);
+        Ok(todo)
+    }
+
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity> {
+        self.inner.find(workspace_id, user_id, id).await
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        self.inner.all(workspace_id, user_id).await
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let todo = self
+            .inner
+            .update(workspace_id, user_id, id, payload)
+            .await?;
+        self.updated(workspace_id, user_id, &todo);
+        Ok(todo)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        self.inner.delete(workspace_id, user_id, id).await?;
+        self.broadcast
+            .publish(workspace_id, user_id, "todo.deleted", json!({ "id": id }));
+        Ok(())
+    }
+
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let result = self
+            .inner
+            .create_item(workspace_id, user_id, todo_id, payload)
+            .await;
+        self.changed(workspace_id, user_id, todo_id, result).await
+    }
+
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let result = self
+            .inner
+            .update_item(workspace_id, user_id, todo_id, item_id, payload)
+            .await;
+        self.changed(workspace_id, user_id, todo_id, result).await
+    }
+
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()> {
+        let result = self
+            .inner
+            .delete_item(workspace_id, user_id, todo_id, item_id)
+            .await;
+        self.changed(workspace_id, user_id, todo_id, result).await
+    }
+
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        let todo = self
+            .inner
+            .add_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await?;
+        self.updated(workspace_id, user_id, &todo);
+        Ok(todo)
+    }
+
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()> {
+        let result = self
+            .inner
+            .remove_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await;
+        self.changed(workspace_id, user_id, todo_id, result).await
+    }
+
+    async fn find_as_of(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<TodoEntity> {
+        self.inner.find_as_of(workspace_id, user_id, id, at).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BroadcastLabelRepository<R> {
+    inner: R,
+    broadcast: Broadcast,
+}
+
+impl<R: LabelRepository> BroadcastLabelRepository<R> {
+    pub fn new(inner: R, broadcast: Broadcast) -> Self {
+        Self { inner, broadcast }
+    }
+}
+
+#[async_trait]
+impl<R: LabelRepository> LabelRepository for BroadcastLabelRepository<R> {
+    async fn create(&self, workspace_id: i32, user_id: i32, name: String) -> anyhow::Result<Label> {
+        let label = self.inner.create(workspace_id, user_id, name).await?;
+        self.broadcast
+            .publish(workspace_id, user_id, "label.created", &label);
+        Ok(label)
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<Label>> {
+        self.inner.all(workspace_id, user_id).await
+    }
+
+    /// todos show the new name on their next read, watchers only get the label
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        name: String,
+    ) -> anyhow::Result<Label> {
+        let label = self.inner.update(workspace_id, user_id, id, name).await?;
+        self.broadcast
+            .publish(workspace_id, user_id, "label.updated", &label);
+        Ok(label)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        self.inner.delete(workspace_id, user_id, id).await?;
+        self.broadcast
+            .publish(workspace_id, user_id, "label.deleted", json!({ "id": id }));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        journal::Journal,
+        repositories::{
+            conformance::{self, Fixture},
+            label::LabelRepositoryforMemory,
+            todo::TodoRepositoryForMemory,
+        },
+    };
+    use std::time::Duration;
+
+    fn broadcasting(
+        broadcast: &Broadcast,
+    ) -> (
+        BroadcastTodoRepository<TodoRepositoryForMemory>,
+        BroadcastLabelRepository<LabelRepositoryforMemory>,
+    ) {
+        let journal = Journal::memory();
+        (
+            BroadcastTodoRepository::new(
+                TodoRepositoryForMemory::open(&journal).unwrap(),
+                broadcast.clone(),
+            ),
+            BroadcastLabelRepository::new(
+                LabelRepositoryforMemory::open(&journal).unwrap(),
+                broadcast.clone(),
+            ),
+        )
+    }
+
+    fn kinds(messages: &[Message]) -> Vec<&'static str> {
+        messages
+            .iter()
+            .map(|message| match message {
+                Message::Event(event) => event.kind,
+                Message::Reset(_) => "reset",
+            })
+            .collect()
+    }
+
+    async fn received(subscription: &mut Subscription) -> Vec<Message> {
+        let mut messages = vec![];
+        while let Ok(Some(message)) =
+            tokio::time::timeout(Duration::from_millis(20), subscription.next()).await
+        {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn conformance() {
+        let (todos, labels) = broadcasting(&Broadcast::default());
+        conformance::run(
+            &todos,
+            &labels,
+            Fixture {
+                user_id: 1,
+                other_user_id: 2,
+                other_workspace_id: 2,
+                stranger_id: 3,
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn publish_test() {
+        let broadcast = Broadcast::default();
+        let (todos, labels) = broadcasting(&broadcast);
+        let mut mine = broadcast.subscribe(1, 1, None);
+        let mut others = broadcast.subscribe(1, 2, None);
+
+        let label = labels.create(1, 1, "label".to_string()).await.unwrap();
+        let todo = todos
+            .create(1, 1, CreateTodo::new("todo".to_string(), vec![label.id]))
+            .await
+            .unwrap();
+        todos
+            .create_item(
+                1,
+                1,
+                todo.id,
+                CreateItem {
+                    text: "item".to_string(),
+                    position: None,
+                },
+            )
+            .await
+            .unwrap();
+        // failed writes are not published
+        assert!(todos.delete(1, 1, todo.id + 1).await.is_err());
+        todos.delete(1, 1, todo.id).await.unwrap();
+
+        let messages = received(&mut mine).await;
+        assert_eq!(
+            vec![
+                "label.created",
+                "todo.created",
+                "todo.updated",
+                "todo.deleted"
+            ],
+            kinds(&messages)
+        );
+        match &messages[2] {
+            Message::Event(event) => {
+                let updated: TodoEntity = serde_json::from_str(&event.data).unwrap();
+                assert_eq!(1, updated.items.len());
+            }
+            Message::Reset(_) => unreachable!(),
+        }
+        assert!(received(&mut others).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resume_test() {
+        let broadcast = Broadcast::new(2);
+        for id in 1..=3 {
+            broadcast.publish(1, 1, "todo.deleted", json!({ "id": id }));
+        }
+        broadcast.publish(1, 2, "todo.deleted", json!({ "id": 4 }));
+
+        // 3 and 4 are buffered, the event of user 2 is not for user 1
+        let ids = |messages: Vec<Message>| {
+            messages
+                .into_iter()
+                .map(|message| match message {
+                    Message::Event(event) => event.id,
+                    Message::Reset(_) => 0,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![3],
+            ids(received(&mut broadcast.subscribe(1, 1, Some(2))).await)
+        );
+        assert!(received(&mut broadcast.subscribe(1, 1, Some(4)))
+            .await
+            .is_empty());
+        assert!(received(&mut broadcast.subscribe(1, 1, None))
+            .await
+            .is_empty());
+        // 2 fell out of the buffer, the watcher reloads and goes on from 4
+        let mut reloading = broadcast.subscribe(1, 1, Some(1));
+        assert_eq!(Some(Message::Reset(Some(4))), reloading.next().await);
+        assert!(received(&mut reloading).await.is_empty());
+        // an id from before a restart
+        assert_eq!(
+            vec![0],
+            ids(received(&mut broadcast.subscribe(1, 1, Some(9))).await)
+        );
+
+        // a watcher which falls behind the channel is told to reload
+        let mut slow = broadcast.subscribe(1, 1, None);
+        for id in 5..=7 {
+            broadcast.publish(1, 1, "todo.deleted", json!({ "id": id }));
+        }
+        assert_eq!(vec![0, 6, 7], ids(received(&mut slow).await));
+    }
+}
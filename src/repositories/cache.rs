@@ -0,0 +1,541 @@
+//! read-through cache in front of another todo and label repository, reads of a
+//! user are kept until `ttl` passes or the same user writes anything
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{
+    item::{CreateItem, TodoItem, UpdateItem},
+    label::{Label, LabelRepository},
+    todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+};
+
+/// workspace and user the cached reads belong to, todos and labels are never
+/// shared between owners so a write of one owner only drops its own reads
+type Owner = (i32, i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Query {
+    Todos,
+    Todo(i32),
+    Labels,
+}
+
+#[derive(Default)]
+struct Slot {
+    /// value of the clock at the last write of the owner
+    generation: u64,
+    entries: HashMap<Query, (Instant, Arc<dyn Any + Send + Sync>)>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// ticks on every write, a read loaded before a write must not be stored after it
+    clock: u64,
+    slots: HashMap<Owner, Slot>,
+}
+
+impl Entries {
+    fn generation(&self, owner: Owner) -> u64 {
+        self.slots
+            .get(&owner)
+            .map_or(self.clock, |slot| slot.generation)
+    }
+}
+
+/// hits and misses of one repository
+#[derive(Debug, Default)]
+pub struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Counters {
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// share of reads answered by the cache, 0 before the first read
+    pub fn hit_ratio(&self) -> f64 {
+        let (hits, misses) = (self.hits(), self.misses());
+        if hits + misses == 0 {
+            return 0.0;
+        }
+        hits as f64 / (hits + misses) as f64
+    }
+}
+
+struct Inner {
+    ttl: Duration,
+    /// owners whose reads are kept, expired reads are dropped once there are more
+    capacity: usize,
+    entries: Mutex<Entries>,
+    todos: Counters,
+    labels: Counters,
+    invalidations: AtomicU64,
+}
+
+/// shared by the todo and label decorators of a server, a zero `ttl` turns it off
+#[derive(Clone)]
+pub struct Cache {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for Cache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cache")
+            .field("ttl", &self.inner.ttl)
+            .field("capacity", &self.inner.capacity)
+            .finish()
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(Duration::ZERO, 0)
+    }
+}
+
+impl Cache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                ttl,
+                capacity,
+                entries: Mutex::default(),
+                todos: Counters::default(),
+                labels: Counters::default(),
+                invalidations: AtomicU64::default(),
+            }),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.inner.ttl.is_zero()
+    }
+
+    pub fn todos(&self) -> &Counters {
+        &self.inner.todos
+    }
+
+    pub fn labels(&self) -> &Counters {
+        &self.inner.labels
+    }
+
+    /// writes which dropped cached reads
+    pub fn invalidations(&self) -> u64 {
+        self.inner.invalidations.load(Ordering::Relaxed)
+    }
+
+    /// the cached value or the one `load` returns, errors are not cached
+    async fn read<V, F>(
+        &self,
+        counters: &Counters,
+        owner: Owner,
+        query: Query,
+        load: F,
+    ) -> anyhow::Result<V>
+    where
+        V: Clone + Send + Sync + 'static,
+        F: std::future::Future<Output = anyhow::Result<V>>,
+    {
+        if !self.enabled() {
+            return load.await;
+        }
+        let generation = {
+            let entries = self.inner.entries.lock().unwrap();
+            let cached = entries
+                .slots
+                .get(&owner)
+                .and_then(|slot| slot.entries.get(&query))
+                .filter(|(at, _)| at.elapsed() < self.inner.ttl)
+                .and_then(|(_, value)| value.downcast_ref::<V>());
+            if let Some(value) = cached {
+                counters.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(value.clone());
+            }
+            entries.generation(owner)
+        };
+        counters.misses.fetch_add(1, Ordering::Relaxed);
+
+        let value = load.await?;
+        let mut entries = self.inner.entries.lock().unwrap();
+        if entries.generation(owner) != generation {
+            return Ok(value);
+        }
+        if !entries.slots.contains_key(&owner) && entries.slots.len() >= self.inner.capacity {
+            let ttl = self.inner.ttl;
+            entries.slots.retain(|_, slot| {
+                slot.entries.retain(|_, (at, _)| at.elapsed() < ttl);
+                !slot.entries.is_empty()
+            });
+            if entries.slots.len() >= self.inner.capacity {
+                return Ok(value);
+            }
+        }
+        let clock = entries.clock;
+        entries
+            .slots
+            .entry(owner)
+            .or_insert_with(|| Slot {
+                generation: clock,
+                ..Slot::default()
+            })
+            .entries
+            .insert(query, (Instant::now(), Arc::new(value.clone())));
+        Ok(value)
+    }
+
+    /// drops every read of `owner`, called after each of its writes whether it
+    /// succeeded or not
+    fn invalidate(&self, owner: Owner) {
+        if !self.enabled() {
+            return;
+        }
+        let mut entries = self.inner.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        // owners without a slot follow the clock, ticking it is enough for them
+        if let Some(slot) = entries.slots.get_mut(&owner) {
+            slot.generation = clock;
+            if !slot.entries.is_empty() {
+                slot.entries.clear();
+                self.inner.invalidations.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedTodoRepository<R> {
+    inner: R,
+    cache: Cache,
+}
+
+impl<R: TodoRepository> CachedTodoRepository<R> {
+    pub fn new(inner: R, cache: Cache) -> Self {
+        Self { inner, cache }
+    }
+
+    fn written<T>(&self, workspace_id: i32, user_id: i32, result: T) -> T {
+        self.cache.invalidate((workspace_id, user_id));
+        result
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepository> TodoRepository for CachedTodoRepository<R> {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let result = self.inner.create(workspace_id, user_id, payload).await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity> {
+        self.cache
+            .read(
+                self.cache.todos(),
+                (workspace_id, user_id),
+                Query::Todo(id),
+                self.inner.find(workspace_id, user_id, id),
+            )
+            .await
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        self.cache
+            .read(
+                self.cache.todos(),
+                (workspace_id, user_id),
+                Query::Todos,
+                self.inner.all(workspace_id, user_id),
+            )
+            .await
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let result = self.inner.update(workspace_id, user_id, id, payload).await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = self.inner.delete(workspace_id, user_id, id).await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let result = self
+            .inner
+            .create_item(workspace_id, user_id, todo_id, payload)
+            .await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let result = self
+            .inner
+            .update_item(workspace_id, user_id, todo_id, item_id, payload)
+            .await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()> {
+        let result = self
+            .inner
+            .delete_item(workspace_id, user_id, todo_id, item_id)
+            .await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        let result = self
+            .inner
+            .add_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()> {
+        let result = self
+            .inner
+            .remove_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await;
+        self.written(workspace_id, user_id, result)
+    }
+
+    /// the past does not change, but there is no point in keeping it either
+    async fn find_as_of(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<TodoEntity> {
+        self.inner.find_as_of(workspace_id, user_id, id, at).await
+    }
+}
+
+/// label writes drop the cached todos of the owner as well, they list the labels
+#[derive(Debug, Clone)]
+pub struct CachedLabelRepository<R> {
+    inner: R,
+    cache: Cache,
+}
+
+impl<R: LabelRepository> CachedLabelRepository<R> {
+    pub fn new(inner: R, cache: Cache) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl<R: LabelRepository> LabelRepository for CachedLabelRepository<R> {
+    async fn create(&self, workspace_id: i32, user_id: i32, name: String) -> anyhow::Result<Label> {
+        let result = self.inner.create(workspace_id, user_id, name).await;
+        self.cache.invalidate((workspace_id, user_id));
+        result
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<Label>> {
+        self.cache
+            .read(
+                self.cache.labels(),
+                (workspace_id, user_id),
+                Query::Labels,
+                self.inner.all(workspace_id, user_id),
+            )
+            .await
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        name: String,
+    ) -> anyhow::Result<Label> {
+        let result = self.inner.update(workspace_id, user_id, id, name).await;
+        self.cache.invalidate((workspace_id, user_id));
+        result
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = self.inner.delete(workspace_id, user_id, id).await;
+        self.cache.invalidate((workspace_id, user_id));
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        journal::Journal,
+        repositories::{
+            conformance::{self, Fixture},
+            label::LabelRepositoryforMemory,
+            todo::TodoRepositoryForMemory,
+        },
+    };
+
+    fn cached(cache: &Cache) -> (impl TodoRepository, impl LabelRepository) {
+        let journal = Journal::memory();
+        (
+            CachedTodoRepository::new(
+                TodoRepositoryForMemory::open(&journal).unwrap(),
+                cache.clone(),
+            ),
+            CachedLabelRepository::new(
+                LabelRepositoryforMemory::open(&journal).unwrap(),
+                cache.clone(),
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn conformance() {
+        let cache = Cache::new(Duration::from_secs(60), 100);
+        let (todos, labels) = cached(&cache);
+        conformance::run(
+            &todos,
+            &labels,
+            Fixture {
+                user_id: 1,
+                other_user_id: 2,
+                other_workspace_id: 2,
+                stranger_id: 3,
+            },
+        )
+        .await;
+        assert!(cache.todos().hits() > 0);
+        assert!(cache.invalidations() > 0);
+    }
+
+    #[tokio::test]
+    async fn invalidation_test() {
+        let cache = Cache::new(Duration::from_secs(60), 100);
+        let (todos, labels) = cached(&cache);
+        let label = labels.create(1, 1, "label".to_string()).await.unwrap();
+        let todo = todos
+            .create(1, 1, CreateTodo::new("todo".to_string(), vec![label.id]))
+            .await
+            .unwrap();
+
+        assert_eq!(vec![todo.clone()], todos.all(1, 1).await.unwrap());
+        assert_eq!(vec![todo.clone()], todos.all(1, 1).await.unwrap());
+        assert_eq!((1, 1), (cache.todos().hits(), cache.todos().misses()));
+        assert_eq!(0.5, cache.todos().hit_ratio());
+
+        // writes of another owner keep the reads
+        todos
+            .create(1, 2, CreateTodo::new("other".to_string(), vec![]))
+            .await
+            .unwrap();
+        todos.all(1, 1).await.unwrap();
+        assert_eq!(2, cache.todos().hits());
+
+        // renaming a label shows in the cached todos
+        todos.find(1, 1, todo.id).await.unwrap();
+        let renamed = labels
+            .update(1, 1, label.id, "renamed".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![renamed.clone()],
+            todos.all(1, 1).await.unwrap()[0].labels
+        );
+        assert_eq!(
+            vec![renamed],
+            todos.find(1, 1, todo.id).await.unwrap().labels
+        );
+        assert_eq!(2, cache.todos().hits());
+        assert_eq!(1, cache.invalidations());
+
+        // deleting a label drops it from the cached todos
+        labels.delete(1, 1, label.id).await.unwrap();
+        assert!(todos.all(1, 1).await.unwrap()[0].labels.is_empty());
+        assert!(todos.find(1, 1, todo.id).await.unwrap().labels.is_empty());
+        assert_eq!(2, cache.todos().hits());
+        assert_eq!(2, cache.invalidations());
+    }
+
+    #[tokio::test]
+    async fn expiry_test() {
+        let cache = Cache::new(Duration::from_millis(20), 1);
+        let (todos, _) = cached(&cache);
+        todos.all(1, 1).await.unwrap();
+        todos.all(1, 1).await.unwrap();
+        // over capacity, the read of the second owner is not kept
+        todos.all(1, 2).await.unwrap();
+        todos.all(1, 2).await.unwrap();
+        assert_eq!((1, 3), (cache.todos().hits(), cache.todos().misses()));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        todos.all(1, 2).await.unwrap();
+        todos.all(1, 2).await.unwrap();
+        assert_eq!((2, 4), (cache.todos().hits(), cache.todos().misses()));
+    }
+
+    #[tokio::test]
+    async fn disabled_test() {
+        let cache = Cache::default();
+        let (todos, _) = cached(&cache);
+        todos.all(1, 1).await.unwrap();
+        todos.all(1, 1).await.unwrap();
+        assert_eq!((0, 0), (cache.todos().hits(), cache.todos().misses()));
+    }
+}
@@ -0,0 +1,196 @@
+//! blobs of uploaded files outlive their rows, this removes them together
+//! with the todo whatever deletes it
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{
+    attachment::Attachment,
+    item::{CreateItem, TodoItem, UpdateItem},
+    todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+};
+use crate::storage::Storage;
+
+/// sweeps every blob stored for the todo once `delete` removed it, whether an
+/// attachment row still pointed at it or an upload was racing the delete; blobs
+/// which fail to go away are logged and left behind
+#[derive(Debug, Clone)]
+pub struct CleanupTodoRepository<R, S> {
+    inner: R,
+    storage: S,
+}
+
+impl<R: TodoRepository, S: Storage> CleanupTodoRepository<R, S> {
+    pub fn new(inner: R, storage: S) -> Self {
+        Self { inner, storage }
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepository, S: Storage> TodoRepository for CleanupTodoRepository<R, S> {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.inner.create(workspace_id, user_id, payload).await
+    }
+
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity> {
+        self.inner.find(workspace_id, user_id, id).await
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        self.inner.all(workspace_id, user_id).await
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.inner.update(workspace_id, user_id, id, payload).await
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        self.inner.delete(workspace_id, user_id, id).await?;
+        // the attachment rows are gone with the todo, so is anything under its prefix
+        let prefix = Attachment::storage_prefix(id);
+        if let Err(e) = self.storage.delete_all(&prefix).await {
+            tracing::warn!("failed to remove blobs {}: {}", prefix, e);
+        }
+        Ok(())
+    }
+
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem> {
+        self.inner
+            .create_item(workspace_id, user_id, todo_id, payload)
+            .await
+    }
+
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem> {
+        self.inner
+            .update_item(workspace_id, user_id, todo_id, item_id, payload)
+            .await
+    }
+
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()> {
+        self.inner
+            .delete_item(workspace_id, user_id, todo_id, item_id)
+            .await
+    }
+
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        self.inner
+            .add_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await
+    }
+
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()> {
+        self.inner
+            .remove_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await
+    }
+
+    async fn find_as_of(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<TodoEntity> {
+        self.inner.find_as_of(workspace_id, user_id, id, at).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        journal::Journal,
+        repositories::{
+            attachment::{AttachmentRepository, AttachmentRepositoryForMemory, CreateAttachment},
+            todo::TodoRepositoryForMemory,
+        },
+        storage::test_utils::StorageForMemory,
+    };
+    use hyper::body::Bytes;
+
+    #[tokio::test]
+    async fn delete_removes_blobs() {
+        let journal = Journal::memory();
+        let attachments = AttachmentRepositoryForMemory::open(&journal).unwrap();
+        let storage = StorageForMemory::new();
+        let repository = CleanupTodoRepository::new(
+            TodoRepositoryForMemory::open(&journal).unwrap(),
+            storage.clone(),
+        );
+        let todo = repository
+            .create(1, 1, CreateTodo::new("with file".to_string(), vec![]))
+            .await
+            .unwrap();
+        let attachment = attachments
+            .create(
+                todo.id,
+                CreateAttachment {
+                    file_name: "log.txt".to_string(),
+                    content_type: "text/plain".to_string(),
+                    size: 3,
+                },
+            )
+            .await
+            .unwrap();
+        storage
+            .put(&attachment.storage_key(), Bytes::from_static(b"log"))
+            .await
+            .unwrap();
+        // an upload whose row went with the todo before its blob was stored
+        let racing = format!("{}/99", Attachment::storage_prefix(todo.id));
+        storage
+            .put(&racing, Bytes::from_static(b"late"))
+            .await
+            .unwrap();
+
+        // someone else's todo keeps its blobs
+        assert!(repository.delete(1, 2, todo.id).await.is_err());
+        assert!(storage.contains(&attachment.storage_key()));
+
+        repository.delete(1, 1, todo.id).await.unwrap();
+        assert!(!storage.contains(&attachment.storage_key()));
+        assert!(!storage.contains(&racing));
+    }
+}
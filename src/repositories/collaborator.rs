@@ -0,0 +1,437 @@
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+use std::str::FromStr;
+use validator::Validate;
+
+/// operation to users a collection of TODOs is shared with
+/// upsert: PUT -- share the collection of `owner_id` with a user of the same workspace,
+/// or change the role
+/// all: GET -- collaborators of a collection
+/// delete: DELETE -- stop sharing with a user
+/// role -- role of `user_id` in the collection of `owner_id`, if shared
+#[async_trait]
+pub trait CollaboratorRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn upsert(
+        &self,
+        owner_id: i32,
+        payload: UpsertCollaborator,
+    ) -> anyhow::Result<Collaborator>;
+    async fn all(&self, owner_id: i32) -> anyhow::Result<Vec<Collaborator>>;
+    async fn delete(&self, owner_id: i32, user_id: i32) -> anyhow::Result<()>;
+    async fn role(&self, owner_id: i32, user_id: i32) -> anyhow::Result<Option<Role>>;
+}
+
+/// ordered, every role may do what the ones before it may
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = RepositoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(RepositoryError::Unexpected(format!("unknown role {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Collaborator {
+    pub owner_id: i32,
+    pub user_id: i32,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct CollaboratorFromRow {
+    owner_id: i32,
+    user_id: i32,
+    role: String,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<CollaboratorFromRow> for Collaborator {
+    type Error = RepositoryError;
+
+    fn try_from(row: CollaboratorFromRow) -> Result<Self, Self::Error> {
+        Ok(Collaborator {
+            owner_id: row.owner_id,
+            user_id: row.user_id,
+            role: row.role.parse()?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct UpsertCollaborator {
+    pub user_id: i32,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone)]
+pub struct CollaboratorRepositoryForDB {
+    pool: PgPool,
+}
+
+impl CollaboratorRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CollaboratorRepository for CollaboratorRepositoryForDB {
+    async fn upsert(
+        &self,
+        owner_id: i32,
+        payload: UpsertCollaborator,
+    ) -> anyhow::Result<Collaborator> {
+        if owner_id == payload.user_id {
+            return Err(RepositoryError::Duplicate(payload.user_id).into());
+        }
+        let row = sqlx::query_as::<_, CollaboratorFromRow>(
+            r#"
+            insert into collaborators (owner_id, user_id, role)
+            select $1, id, $3 from users
+            where id = $2 and workspace_id = (select workspace_id from users where id = $1)
+            on conflict (owner_id, user_id) do update set role = excluded.role
+            returning *
+            "#,
+        )
+        .bind(owner_id)
+        .bind(payload.user_id)
+        .bind(payload.role.as_str())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(payload.user_id))?;
+
+        Ok(row.try_into()?)
+    }
+
+    async fn all(&self, owner_id: i32) -> anyhow::Result<Vec<Collaborator>> {
+        retry::read(|| async move {
+            let rows = sqlx::query_as::<_, CollaboratorFromRow>(
+                r#"
+                select * from collaborators
+                where owner_id = $1
+                order by user_id asc
+                "#,
+            )
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let collaborators = rows
+                .into_iter()
+                .map(Collaborator::try_from)
+                .collect::<Result<_, _>>()?;
+            Ok(collaborators)
+        })
+        .await
+    }
+
+    async fn delete(&self, owner_id: i32, user_id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from collaborators where owner_id = $1 and user_id = $2
+            "#,
+        )
+        .bind(owner_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(user_id).into());
+        }
+        Ok(())
+    }
+
+    async fn role(&self, owner_id: i32, user_id: i32) -> anyhow::Result<Option<Role>> {
+        retry::read(|| async move {
+            let role = sqlx::query_as::<_, (String,)>(
+                r#"
+                select role from collaborators where owner_id = $1 and user_id = $2
+                "#,
+            )
+            .bind(owner_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+            Ok(role.map(|(role,)| role.parse()).transpose()?)
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CollaboratorRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl CollaboratorRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CollaboratorRepository for CollaboratorRepositoryForSqlite {
+    async fn upsert(
+        &self,
+        owner_id: i32,
+        payload: UpsertCollaborator,
+    ) -> anyhow::Result<Collaborator> {
+        if owner_id == payload.user_id {
+            return Err(RepositoryError::Duplicate(payload.user_id).into());
+        }
+        let row = sqlx::query_as::<_, CollaboratorFromRow>(
+            r#"
+            insert into collaborators (owner_id, user_id, role, created_at)
+            select $1, id, $3, $4 from users
+            where id = $2 and workspace_id = (select workspace_id from users where id = $1)
+            on conflict (owner_id, user_id) do update set role = excluded.role
+            returning *
+            "#,
+        )
+        .bind(owner_id)
+        .bind(payload.user_id)
+        .bind(payload.role.as_str())
+        .bind(Utc::now())
+        // read to the end, a statement left unfinished keeps the write uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::NotFound(payload.user_id))?;
+
+        Ok(row.try_into()?)
+    }
+
+    async fn all(&self, owner_id: i32) -> anyhow::Result<Vec<Collaborator>> {
+        let rows = sqlx::query_as::<_, CollaboratorFromRow>(
+            r#"
+            select * from collaborators
+            where owner_id = $1
+            order by user_id asc
+            "#,
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let collaborators = rows
+            .into_iter()
+            .map(Collaborator::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(collaborators)
+    }
+
+    async fn delete(&self, owner_id: i32, user_id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from collaborators where owner_id = $1 and user_id = $2
+            "#,
+        )
+        .bind(owner_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(user_id).into());
+        }
+        Ok(())
+    }
+
+    async fn role(&self, owner_id: i32, user_id: i32) -> anyhow::Result<Option<Role>> {
+        let role = sqlx::query_as::<_, (String,)>(
+            r#"
+            select role from collaborators where owner_id = $1 and user_id = $2
+            "#,
+        )
+        .bind(owner_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(role.map(|(role,)| role.parse()).transpose()?)
+    }
+}
+
+/// keeps collaborators in a table of a `Journal`, keyed by (owner_id, user_id)
+#[derive(Debug, Clone)]
+pub struct CollaboratorRepositoryForMemory {
+    store: Table<(i32, i32), Collaborator>,
+}
+
+impl CollaboratorRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("collaborators")?,
+        })
+    }
+}
+
+#[async_trait]
+impl CollaboratorRepository for CollaboratorRepositoryForMemory {
+    async fn upsert(
+        &self,
+        owner_id: i32,
+        payload: UpsertCollaborator,
+    ) -> anyhow::Result<Collaborator> {
+        if owner_id == payload.user_id {
+            return Err(RepositoryError::Duplicate(payload.user_id).into());
+        }
+        let mut store = self.store.write().await;
+        let collaborator = Collaborator {
+            owner_id,
+            user_id: payload.user_id,
+            role: payload.role,
+            created_at: Utc::now(),
+        };
+        store.insert((owner_id, payload.user_id), collaborator.clone());
+        store.commit().await?;
+        Ok(collaborator)
+    }
+
+    async fn all(&self, owner_id: i32) -> anyhow::Result<Vec<Collaborator>> {
+        let store = self.store.read().await;
+        Ok(store
+            .values()
+            .filter(|collaborator| collaborator.owner_id == owner_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete(&self, owner_id: i32, user_id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        store
+            .remove(&(owner_id, user_id))
+            .ok_or(RepositoryError::NotFound(user_id))?;
+        store.commit().await?;
+        Ok(())
+    }
+
+    async fn role(&self, owner_id: i32, user_id: i32) -> anyhow::Result<Option<Role>> {
+        let store = self.store.read().await;
+        Ok(store
+            .get(&(owner_id, user_id))
+            .map(|collaborator| collaborator.role))
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::user::test_utils::{prepare_named_test_user, prepare_test_user};
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+        let owner_id = prepare_test_user(&pool).await;
+        let user_id = prepare_named_test_user(&pool, "[collaborator crud_scenario] user").await;
+        let repository = CollaboratorRepositoryForDB::new(pool.clone());
+        repository.delete(owner_id, user_id).await.ok();
+
+        // upsert
+        let collaborator = repository
+            .upsert(
+                owner_id,
+                UpsertCollaborator {
+                    user_id,
+                    role: Role::Viewer,
+                },
+            )
+            .await
+            .expect("[upsert] returned Err");
+        assert_eq!(Role::Viewer, collaborator.role);
+        let collaborator = repository
+            .upsert(
+                owner_id,
+                UpsertCollaborator {
+                    user_id,
+                    role: Role::Editor,
+                },
+            )
+            .await
+            .expect("[upsert] returned Err");
+        assert_eq!(Role::Editor, collaborator.role);
+        assert!(repository
+            .upsert(
+                owner_id,
+                UpsertCollaborator {
+                    user_id: owner_id,
+                    role: Role::Editor,
+                },
+            )
+            .await
+            .is_err());
+
+        // role
+        let role = repository
+            .role(owner_id, user_id)
+            .await
+            .expect("[role] returned Err");
+        assert_eq!(Some(Role::Editor), role);
+        let role = repository
+            .role(user_id, owner_id)
+            .await
+            .expect("[role] returned Err");
+        assert_eq!(None, role);
+
+        // all
+        let collaborators = repository.all(owner_id).await.expect("[all] returned Err");
+        assert!(collaborators.contains(&collaborator));
+
+        // delete
+        repository
+            .delete(owner_id, user_id)
+            .await
+            .expect("[delete] returned Err");
+        assert!(repository.delete(owner_id, user_id).await.is_err());
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    impl CollaboratorRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+    }
+}
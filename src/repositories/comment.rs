@@ -0,0 +1,494 @@
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+use std::collections::HashMap;
+use validator::Validate;
+
+/// operation to comments on a TODO
+/// create: POST -- add a comment to a TODO
+/// all: GET -- comments of a TODO, oldest first
+/// update: PATCH -- edit a comment
+/// delete: DELETE -- remove a comment
+/// count_by_todo -- number of comments for each of the given TODOs which has any
+#[async_trait]
+pub trait CommentRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, todo_id: i32, payload: CreateComment) -> anyhow::Result<Comment>;
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Comment>>;
+    async fn update(
+        &self,
+        todo_id: i32,
+        id: i32,
+        payload: UpdateComment,
+    ) -> anyhow::Result<Comment>;
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()>;
+    async fn count_by_todo(&self, todo_ids: &[i32]) -> anyhow::Result<HashMap<i32, i64>>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct Comment {
+    pub id: i32,
+    pub todo_id: i32,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateComment {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 1000, message = "Over text length"))]
+    pub body: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct UpdateComment {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 1000, message = "Over text length"))]
+    pub body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommentRepositoryForDB {
+    pool: PgPool,
+}
+
+impl CommentRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CommentRepository for CommentRepositoryForDB {
+    async fn create(&self, todo_id: i32, payload: CreateComment) -> anyhow::Result<Comment> {
+        let comment = sqlx::query_as::<_, Comment>(
+            r#"
+            insert into comments (todo_id, body)
+            values ($1, $2)
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.body)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(comment)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Comment>> {
+        retry::read(|| async move {
+            let comments = sqlx::query_as::<_, Comment>(
+                r#"
+                select * from comments
+                where todo_id = $1
+                order by comments.id asc
+                "#,
+            )
+            .bind(todo_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(comments)
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        todo_id: i32,
+        id: i32,
+        payload: UpdateComment,
+    ) -> anyhow::Result<Comment> {
+        let comment = sqlx::query_as::<_, Comment>(
+            r#"
+            update comments set body = $3, updated_at = now()
+            where todo_id = $1 and id = $2
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .bind(payload.body)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(comment)
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from comments where todo_id = $1 and id = $2
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn count_by_todo(&self, todo_ids: &[i32]) -> anyhow::Result<HashMap<i32, i64>> {
+        retry::read(|| async move {
+            let counts = sqlx::query_as::<_, (i32, i64)>(
+                r#"
+                select todo_id, count(*) from comments
+                where todo_id = any($1)
+                group by todo_id
+                "#,
+            )
+            .bind(todo_ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(counts.into_iter().collect())
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommentRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl CommentRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CommentRepository for CommentRepositoryForSqlite {
+    async fn create(&self, todo_id: i32, payload: CreateComment) -> anyhow::Result<Comment> {
+        let comment = sqlx::query_as::<_, Comment>(
+            r#"
+            insert into comments (todo_id, body, created_at, updated_at)
+            values ($1, $2, $3, $3)
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.body)
+        .bind(Utc::now())
+        // read to the end, a statement left unfinished keeps the write uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or_else(|| RepositoryError::Unexpected("no comment returned".to_string()))?;
+
+        Ok(comment)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Comment>> {
+        let comments = sqlx::query_as::<_, Comment>(
+            r#"
+            select * from comments
+            where todo_id = $1
+            order by comments.id asc
+            "#,
+        )
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(comments)
+    }
+
+    async fn update(
+        &self,
+        todo_id: i32,
+        id: i32,
+        payload: UpdateComment,
+    ) -> anyhow::Result<Comment> {
+        let comment = sqlx::query_as::<_, Comment>(
+            r#"
+            update comments set body = $3, updated_at = $4
+            where todo_id = $1 and id = $2
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .bind(payload.body)
+        .bind(Utc::now())
+        // read to the end, see create
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(comment)
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from comments where todo_id = $1 and id = $2
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn count_by_todo(&self, todo_ids: &[i32]) -> anyhow::Result<HashMap<i32, i64>> {
+        let counts = sqlx::query_as::<_, (i32, i64)>(
+            r#"
+            select todo_id, count(*) from comments
+            where todo_id in (select value from json_each($1))
+            group by todo_id
+            "#,
+        )
+        .bind(serde_json::to_string(todo_ids)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(counts.into_iter().collect())
+    }
+}
+
+/// keeps comments in a table of a `Journal`
+#[derive(Debug, Clone)]
+pub struct CommentRepositoryForMemory {
+    store: Table<i32, Comment>,
+}
+
+impl CommentRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("comments")?,
+        })
+    }
+}
+
+#[async_trait]
+impl CommentRepository for CommentRepositoryForMemory {
+    async fn create(&self, todo_id: i32, payload: CreateComment) -> anyhow::Result<Comment> {
+        let mut store = self.store.write().await;
+        let id = store.next_id();
+        let now = Utc::now();
+        let comment = Comment {
+            id,
+            todo_id,
+            body: payload.body,
+            created_at: now,
+            updated_at: now,
+        };
+        store.insert(id, comment.clone());
+        store.commit().await?;
+        Ok(comment)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Comment>> {
+        let store = self.store.read().await;
+        Ok(store
+            .values()
+            .filter(|comment| comment.todo_id == todo_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn update(
+        &self,
+        todo_id: i32,
+        id: i32,
+        payload: UpdateComment,
+    ) -> anyhow::Result<Comment> {
+        let mut store = self.store.write().await;
+        let comment = store
+            .get_mut(&id)
+            .filter(|comment| comment.todo_id == todo_id)
+            .context(RepositoryError::NotFound(id))?;
+        comment.body = payload.body;
+        comment.updated_at = Utc::now();
+        let comment = comment.clone();
+        store.commit().await?;
+        Ok(comment)
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        store
+            .get(&id)
+            .filter(|comment| comment.todo_id == todo_id)
+            .context(RepositoryError::NotFound(id))?;
+        store.remove(&id);
+        store.commit().await?;
+        Ok(())
+    }
+
+    async fn count_by_todo(&self, todo_ids: &[i32]) -> anyhow::Result<HashMap<i32, i64>> {
+        let store = self.store.read().await;
+        let mut counts = HashMap::new();
+        for comment in store
+            .values()
+            .filter(|comment| todo_ids.contains(&comment.todo_id))
+        {
+            *counts.entry(comment.todo_id).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::todo::{CreateTodo, TodoRepository, TodoRepositoryForDB};
+    use crate::repositories::user::test_utils::prepare_test_user;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+
+        let user_id = prepare_test_user(&pool).await;
+        let todo_repository = TodoRepositoryForDB::new(pool.clone());
+        let todo = todo_repository
+            .create(
+                1,
+                user_id,
+                CreateTodo::new("[comment crud_scenario] todo".to_string(), vec![]),
+            )
+            .await
+            .expect("[create todo] returned Err");
+
+        let repository = CommentRepositoryForDB::new(pool.clone());
+        let body = "[crud_scenario] comment";
+
+        // create
+        let comment = repository
+            .create(
+                todo.id,
+                CreateComment {
+                    body: body.to_string(),
+                },
+            )
+            .await
+            .expect("[create] returned Err");
+        assert_eq!(comment.body, body);
+        assert_eq!(comment.todo_id, todo.id);
+
+        // all
+        let comments = repository.all(todo.id).await.expect("[all] returned Err");
+        assert_eq!(vec![comment.clone()], comments);
+
+        // count
+        let counts = repository
+            .count_by_todo(&[todo.id])
+            .await
+            .expect("[count_by_todo] returned Err");
+        assert_eq!(Some(&1), counts.get(&todo.id));
+        let counts = repository
+            .count_by_todo(&[todo.id + 1])
+            .await
+            .expect("[count_by_todo] returned Err");
+        assert!(counts.is_empty());
+
+        // update
+        let updated_body = "[crud_scenario] updated comment";
+        let updated = repository
+            .update(
+                todo.id,
+                comment.id,
+                UpdateComment {
+                    body: updated_body.to_string(),
+                },
+            )
+            .await
+            .expect("[update] returned Err");
+        assert_eq!(updated.body, updated_body);
+        assert_eq!(updated.created_at, comment.created_at);
+
+        // delete
+        repository
+            .delete(todo.id, comment.id)
+            .await
+            .expect("[delete] returned Err");
+        assert!(repository.delete(todo.id, comment.id).await.is_err());
+
+        todo_repository
+            .delete(1, user_id, todo.id)
+            .await
+            .expect("[delete todo] returned Err");
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    impl CommentRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn comment_crud_scenario() {
+            let todo_id = 1;
+            let body = "comment body".to_string();
+
+            //create
+            let repository = CommentRepositoryForMemory::new();
+            let comment = repository
+                .create(todo_id, CreateComment { body: body.clone() })
+                .await
+                .expect("failed create comment");
+            assert_eq!(1, comment.id);
+            assert_eq!(body, comment.body);
+
+            //all
+            let comments = repository.all(todo_id).await.expect("failed get comments");
+            assert_eq!(vec![comment.clone()], comments);
+            let counts = repository.count_by_todo(&[todo_id]).await.unwrap();
+            assert_eq!(Some(&1), counts.get(&todo_id));
+            let counts = repository.count_by_todo(&[todo_id + 1]).await.unwrap();
+            assert!(counts.is_empty());
+
+            //update
+            let body = "updated comment body".to_string();
+            let comment = repository
+                .update(todo_id, comment.id, UpdateComment { body: body.clone() })
+                .await
+                .expect("failed update comment");
+            assert_eq!(body, comment.body);
+            assert!(repository
+                .update(todo_id + 1, comment.id, UpdateComment { body })
+                .await
+                .is_err());
+
+            //delete
+            let res = repository.delete(todo_id, comment.id).await;
+            assert!(res.is_ok());
+            assert!(repository.all(todo_id).await.unwrap().is_empty());
+        }
+    }
+}
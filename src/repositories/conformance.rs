@@ -0,0 +1,648 @@
+//! semantics every backend has to share, the suite runs against a pair of
+//! `TodoRepository` and `LabelRepository` of each backend
+
+use super::{
+    item::{CreateItem, UpdateItem},
+    label::LabelRepository,
+    todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+    RepositoryError,
+};
+use std::fmt::Debug;
+
+/// users the suite acts as, `user_id` and `other_user_id` belong to the default
+/// workspace, `stranger_id` to `other_workspace_id`
+pub struct Fixture {
+    pub user_id: i32,
+    pub other_user_id: i32,
+    pub other_workspace_id: i32,
+    pub stranger_id: i32,
+}
+
+impl Fixture {
+    fn owners(&self) -> [(i32, i32); 3] {
+        [
+            (1, self.user_id),
+            (1, self.other_user_id),
+            (self.other_workspace_id, self.stranger_id),
+        ]
+    }
+}
+
+pub async fn run<T: TodoRepository, L: LabelRepository>(todos: &T, labels: &L, fixture: Fixture) {
+    reset(todos, labels, &fixture).await;
+    ordering(todos, &fixture).await;
+    reset(todos, labels, &fixture).await;
+    not_found(todos, labels, &fixture).await;
+    reset(todos, labels, &fixture).await;
+    label_resolution(todos, labels, &fixture).await;
+    reset(todos, labels, &fixture).await;
+    dependencies(todos, &fixture).await;
+    reset(todos, labels, &fixture).await;
+}
+
+/// the error of `result`, which has to be a `RepositoryError`
+fn error<T: Debug>(result: anyhow::Result<T>) -> RepositoryError {
+    result
+        .expect_err("returned Ok")
+        .downcast()
+        .expect("returned an error other than RepositoryError")
+}
+
+fn create(text: &str, labels: Vec<i32>) -> CreateTodo {
+    CreateTodo::new(text.to_string(), labels)
+}
+
+/// removes what earlier runs against a persistent database left behind
+async fn reset<T: TodoRepository, L: LabelRepository>(todos: &T, labels: &L, fixture: &Fixture) {
+    for (workspace_id, user_id) in fixture.owners() {
+        for todo in todos.all(workspace_id, user_id).await.unwrap() {
+            todos.delete(workspace_id, user_id, todo.id).await.unwrap();
+        }
+        for label in labels.all(workspace_id, user_id).await.unwrap() {
+            labels
+                .delete(workspace_id, user_id, label.id)
+                .await
+                .unwrap();
+        }
+    }
+}
+
+/// `all` lists the newest TODO first, ids are never reused
+async fn ordering<T: TodoRepository>(todos: &T, fixture: &Fixture) {
+    let user_id = fixture.user_id;
+    let mut created: Vec<TodoEntity> = vec![];
+    for text in ["first", "second", "third"] {
+        created.push(
+            todos
+                .create(1, user_id, create(text, vec![]))
+                .await
+                .unwrap(),
+        );
+    }
+    assert!(created.windows(2).all(|pair| pair[0].id < pair[1].id));
+    let mut expected = created.clone();
+    expected.reverse();
+    assert_eq!(expected, todos.all(1, user_id).await.unwrap());
+    for todo in created.iter() {
+        assert_eq!(*todo, todos.find(1, user_id, todo.id).await.unwrap());
+    }
+
+    let last = created.pop().unwrap();
+    todos.delete(1, user_id, last.id).await.unwrap();
+    let next = todos
+        .create(1, user_id, create("fourth", vec![]))
+        .await
+        .unwrap();
+    assert!(next.id > last.id, "id {} was reused", last.id);
+
+    // items keep checklist order, ties on position by id
+    let todo = &created[0];
+    let item = |text: &str, position| CreateItem {
+        text: text.to_string(),
+        position,
+    };
+    let second = todos
+        .create_item(1, user_id, todo.id, item("second", Some(2)))
+        .await
+        .unwrap();
+    let first = todos
+        .create_item(1, user_id, todo.id, item("first", Some(1)))
+        .await
+        .unwrap();
+    let third = todos
+        .create_item(1, user_id, todo.id, item("third", None))
+        .await
+        .unwrap();
+    assert_eq!(3, third.position);
+    let tied = todos
+        .create_item(1, user_id, todo.id, item("tied", Some(1)))
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![first, tied, second, third],
+        todos.find(1, user_id, todo.id).await.unwrap().items
+    );
+}
+
+/// whatever is missing or belongs to someone else is `NotFound` with the id asked for
+async fn not_found<T: TodoRepository, L: LabelRepository>(
+    todos: &T,
+    labels: &L,
+    fixture: &Fixture,
+) {
+    let user_id = fixture.user_id;
+    let todo = todos
+        .create(1, user_id, create("mine", vec![]))
+        .await
+        .unwrap();
+    let item = todos
+        .create_item(
+            1,
+            user_id,
+            todo.id,
+            CreateItem {
+                text: "item".to_string(),
+                position: None,
+            },
+        )
+        .await
+        .unwrap();
+    let todo = todos.find(1, user_id, todo.id).await.unwrap();
+    let gone = todos
+        .create(1, user_id, create("gone", vec![]))
+        .await
+        .unwrap();
+    todos.delete(1, user_id, gone.id).await.unwrap();
+    let not_found = RepositoryError::NotFound;
+
+    assert_eq!(
+        not_found(gone.id),
+        error(todos.find(1, user_id, gone.id).await)
+    );
+    assert_eq!(
+        not_found(gone.id),
+        error(
+            todos
+                .update(1, user_id, gone.id, UpdateTodo::new(None, Some(true), None))
+                .await
+        )
+    );
+    assert_eq!(
+        not_found(gone.id),
+        error(todos.delete(1, user_id, gone.id).await)
+    );
+
+    for (workspace_id, other_id) in [
+        (1, fixture.other_user_id),
+        (fixture.other_workspace_id, fixture.stranger_id),
+        (fixture.other_workspace_id, user_id),
+    ] {
+        assert_eq!(
+            not_found(todo.id),
+            error(todos.find(workspace_id, other_id, todo.id).await)
+        );
+        assert_eq!(
+            not_found(todo.id),
+            error(
+                todos
+                    .update(
+                        workspace_id,
+                        other_id,
+                        todo.id,
+                        UpdateTodo::new(Some("theirs".to_string()), None, None)
+                    )
+                    .await
+            )
+        );
+        assert_eq!(
+            not_found(todo.id),
+            error(todos.delete(workspace_id, other_id, todo.id).await)
+        );
+        assert_eq!(
+            not_found(todo.id),
+            error(
+                todos
+                    .create_item(
+                        workspace_id,
+                        other_id,
+                        todo.id,
+                        CreateItem {
+                            text: "theirs".to_string(),
+                            position: None,
+                        },
+                    )
+                    .await
+            )
+        );
+        assert_eq!(
+            not_found(item.id),
+            error(
+                todos
+                    .update_item(
+                        workspace_id,
+                        other_id,
+                        todo.id,
+                        item.id,
+                        UpdateItem {
+                            text: None,
+                            done: Some(true),
+                            position: None,
+                        },
+                    )
+                    .await
+            )
+        );
+        assert_eq!(
+            not_found(item.id),
+            error(
+                todos
+                    .delete_item(workspace_id, other_id, todo.id, item.id)
+                    .await
+            )
+        );
+    }
+    assert_eq!(todo, todos.find(1, user_id, todo.id).await.unwrap());
+
+    // items and blockers of another TODO
+    let other = todos
+        .create(1, user_id, create("other", vec![]))
+        .await
+        .unwrap();
+    assert_eq!(
+        not_found(item.id),
+        error(todos.delete_item(1, user_id, other.id, item.id).await)
+    );
+    assert_eq!(
+        not_found(gone.id),
+        error(todos.add_blocker(1, user_id, todo.id, gone.id).await)
+    );
+    assert_eq!(
+        not_found(other.id),
+        error(todos.add_blocker(1, user_id, gone.id, other.id).await)
+    );
+    assert_eq!(
+        not_found(other.id),
+        error(todos.remove_blocker(1, user_id, todo.id, other.id).await)
+    );
+    assert_eq!(
+        not_found(other.id),
+        error(todos.remove_blocker(1, user_id, gone.id, other.id).await)
+    );
+    let theirs = todos
+        .create(1, fixture.other_user_id, create("theirs", vec![]))
+        .await
+        .unwrap();
+    assert_eq!(
+        not_found(theirs.id),
+        error(todos.add_blocker(1, user_id, todo.id, theirs.id).await)
+    );
+
+    todos
+        .delete_item(1, user_id, todo.id, item.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        not_found(item.id),
+        error(todos.delete_item(1, user_id, todo.id, item.id).await)
+    );
+
+    // labels
+    let label = labels
+        .create(1, user_id, "label".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        not_found(label.id),
+        error(labels.delete(1, fixture.other_user_id, label.id).await)
+    );
+    assert_eq!(
+        not_found(label.id),
+        error(
+            labels
+                .delete(fixture.other_workspace_id, user_id, label.id)
+                .await
+        )
+    );
+    labels.delete(1, user_id, label.id).await.unwrap();
+    assert_eq!(
+        not_found(label.id),
+        error(labels.delete(1, user_id, label.id).await)
+    );
+}
+
+/// labels are listed by id, the ones of other users, unknown or deleted are skipped
+async fn label_resolution<T: TodoRepository, L: LabelRepository>(
+    todos: &T,
+    labels: &L,
+    fixture: &Fixture,
+) {
+    let user_id = fixture.user_id;
+    let first = labels
+        .create(1, user_id, "first".to_string())
+        .await
+        .unwrap();
+    let second = labels
+        .create(1, user_id, "second".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        RepositoryError::Duplicate(first.id),
+        error(labels.create(1, user_id, "first".to_string()).await)
+    );
+    let theirs = labels
+        .create(1, fixture.other_user_id, "first".to_string())
+        .await
+        .unwrap();
+    let stranger = labels
+        .create(
+            fixture.other_workspace_id,
+            fixture.stranger_id,
+            "first".to_string(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![first.clone(), second.clone()],
+        labels.all(1, user_id).await.unwrap()
+    );
+    let gone = labels.create(1, user_id, "gone".to_string()).await.unwrap();
+    labels.delete(1, user_id, gone.id).await.unwrap();
+
+    let todo = todos
+        .create(
+            1,
+            user_id,
+            create(
+                "labeled",
+                vec![
+                    second.id,
+                    theirs.id,
+                    first.id,
+                    second.id,
+                    stranger.id,
+                    gone.id,
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+    assert_eq!(vec![first.clone(), second.clone()], todo.labels);
+    assert_eq!(todo, todos.find(1, user_id, todo.id).await.unwrap());
+    assert_eq!(vec![todo.clone()], todos.all(1, user_id).await.unwrap());
+
+    // labels are kept unless given
+    let todo = todos
+        .update(
+            1,
+            user_id,
+            todo.id,
+            UpdateTodo::new(Some("renamed".to_string()), None, None),
+        )
+        .await
+        .unwrap();
+    assert_eq!(vec![first.clone(), second.clone()], todo.labels);
+    let todo = todos
+        .update(
+            1,
+            user_id,
+            todo.id,
+            UpdateTodo::new(None, None, Some(vec![theirs.id, second.id])),
+        )
+        .await
+        .unwrap();
+    assert_eq!(vec![second.clone()], todo.labels);
+
+    // deleting a label drops it from its TODOs
+    labels.delete(1, user_id, second.id).await.unwrap();
+    assert!(todos
+        .find(1, user_id, todo.id)
+        .await
+        .unwrap()
+        .labels
+        .is_empty());
+    let todo = todos
+        .update(
+            1,
+            user_id,
+            todo.id,
+            UpdateTodo::new(None, None, Some(vec![first.id, second.id])),
+        )
+        .await
+        .unwrap();
+    assert_eq!(vec![first], todo.labels);
+}
+
+/// blockers are listed by id, cycles and completing blocked TODOs are refused
+async fn dependencies<T: TodoRepository>(todos: &T, fixture: &Fixture) {
+    let user_id = fixture.user_id;
+    let todo = todos
+        .create(1, user_id, create("todo", vec![]))
+        .await
+        .unwrap();
+    let second = todos
+        .create(1, user_id, create("second", vec![]))
+        .await
+        .unwrap();
+    let first = todos
+        .create(1, user_id, create("first", vec![]))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        RepositoryError::Cycle(todo.id),
+        error(todos.add_blocker(1, user_id, todo.id, todo.id).await)
+    );
+    todos
+        .add_blocker(1, user_id, todo.id, first.id)
+        .await
+        .unwrap();
+    let blocked = todos
+        .add_blocker(1, user_id, todo.id, second.id)
+        .await
+        .unwrap();
+    assert_eq!(vec![second.id, first.id], blocked.blocked_by);
+    assert!(blocked.blocked);
+    // adding twice changes nothing
+    assert_eq!(
+        blocked,
+        todos
+            .add_blocker(1, user_id, todo.id, first.id)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        RepositoryError::Cycle(todo.id),
+        error(todos.add_blocker(1, user_id, first.id, todo.id).await)
+    );
+    todos
+        .add_blocker(1, user_id, second.id, first.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        RepositoryError::Cycle(todo.id),
+        error(todos.add_blocker(1, user_id, first.id, todo.id).await)
+    );
+
+    let complete = || UpdateTodo::new(None, Some(true), None);
+    assert_eq!(
+        RepositoryError::Blocked(todo.id),
+        error(todos.update(1, user_id, todo.id, complete()).await)
+    );
+    assert_eq!(
+        RepositoryError::Blocked(second.id),
+        error(todos.update(1, user_id, second.id, complete()).await)
+    );
+    todos
+        .update(1, user_id, first.id, complete())
+        .await
+        .unwrap();
+    todos
+        .update(1, user_id, second.id, complete())
+        .await
+        .unwrap();
+    let todo = todos.find(1, user_id, todo.id).await.unwrap();
+    assert!(!todo.blocked);
+    assert_eq!(vec![second.id, first.id], todo.blocked_by);
+
+    // deleting a blocker drops it from the TODOs it blocks
+    todos.delete(1, user_id, first.id).await.unwrap();
+    assert_eq!(
+        vec![second.id],
+        todos.find(1, user_id, todo.id).await.unwrap().blocked_by
+    );
+    todos
+        .remove_blocker(1, user_id, todo.id, second.id)
+        .await
+        .unwrap();
+    assert!(todos
+        .find(1, user_id, todo.id)
+        .await
+        .unwrap()
+        .blocked_by
+        .is_empty());
+}
+
+#[cfg(feature = "database-test")]
+mod postgres {
+    use super::*;
+    use crate::repositories::{
+        event::TodoRepositoryForEvents,
+        label::LabelRepositoryforDB,
+        replica::Replica,
+        todo::TodoRepositoryForDB,
+        user::test_utils::{prepare_named_test_user, prepare_workspace_test_user},
+        workspace::test_utils::prepare_test_workspace,
+    };
+    use sqlx::{postgres::PgConnectOptions, PgPool};
+    use std::time::Duration;
+
+    fn database_url() -> String {
+        dotenv::dotenv().ok();
+        std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]")
+    }
+
+    async fn prepare_fixture(pool: &PgPool, name: &str) -> Fixture {
+        let other_workspace_id = prepare_test_workspace(pool, name).await;
+        Fixture {
+            user_id: prepare_named_test_user(pool, &format!("[{}] user", name)).await,
+            other_user_id: prepare_named_test_user(pool, &format!("[{}] other user", name)).await,
+            other_workspace_id,
+            stranger_id: prepare_workspace_test_user(
+                pool,
+                other_workspace_id,
+                &format!("[{}] stranger", name),
+            )
+            .await,
+        }
+    }
+
+    #[tokio::test]
+    async fn conformance() {
+        let pool = PgPool::connect(&database_url())
+            .await
+            .expect("fail connect database");
+        let fixture = prepare_fixture(&pool, "conformance").await;
+        run(
+            &TodoRepositoryForDB::new(pool.clone()),
+            &LabelRepositoryforDB::new(pool),
+            fixture,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn conformance_with_events() {
+        let pool = PgPool::connect(&database_url())
+            .await
+            .expect("fail connect database");
+        let fixture = prepare_fixture(&pool, "events").await;
+        run(
+            &TodoRepositoryForEvents::new(pool.clone()),
+            &LabelRepositoryforDB::new(pool),
+            fixture,
+        )
+        .await;
+    }
+
+    /// the replica is the same database behind read-only transactions, a
+    /// write reaching it fails the suite
+    #[tokio::test]
+    async fn conformance_with_replica() {
+        let pool = PgPool::connect(&database_url())
+            .await
+            .expect("fail connect database");
+        let options = database_url()
+            .parse::<PgConnectOptions>()
+            .expect("invalid [DATABASE_URL]")
+            .options([("default_transaction_read_only", "on")]);
+        let replica = PgPool::connect_with(options)
+            .await
+            .expect("fail connect replica");
+        let replica = Some(Replica::new(replica, Duration::ZERO));
+        let fixture = prepare_fixture(&pool, "replica").await;
+        run(
+            &TodoRepositoryForDB::new(pool.clone()).with_replica(replica.clone()),
+            &LabelRepositoryforDB::new(pool).with_replica(replica),
+            fixture,
+        )
+        .await;
+    }
+}
+
+mod sqlite {
+    use super::*;
+    use crate::repositories::{
+        label::LabelRepositoryforSqlite,
+        test_utils::{
+            prepare_sqlite_pool, prepare_sqlite_test_user, prepare_sqlite_test_workspace,
+        },
+        todo::TodoRepositoryForSqlite,
+    };
+
+    #[tokio::test]
+    async fn conformance() {
+        let pool = prepare_sqlite_pool().await;
+        let other_workspace_id = prepare_sqlite_test_workspace(&pool, "conformance").await;
+        let fixture = Fixture {
+            user_id: prepare_sqlite_test_user(&pool, 1, "[conformance] user").await,
+            other_user_id: prepare_sqlite_test_user(&pool, 1, "[conformance] other user").await,
+            other_workspace_id,
+            stranger_id: prepare_sqlite_test_user(
+                &pool,
+                other_workspace_id,
+                "[conformance] stranger",
+            )
+            .await,
+        };
+        run(
+            &TodoRepositoryForSqlite::new(pool.clone()),
+            &LabelRepositoryforSqlite::new(pool),
+            fixture,
+        )
+        .await;
+    }
+}
+
+mod memory {
+    use super::*;
+    use crate::{
+        journal::Journal,
+        repositories::{label::LabelRepositoryforMemory, todo::TodoRepositoryForMemory},
+    };
+
+    #[tokio::test]
+    async fn conformance() {
+        let journal = Journal::memory();
+        run(
+            &TodoRepositoryForMemory::open(&journal).unwrap(),
+            &LabelRepositoryforMemory::open(&journal).unwrap(),
+            Fixture {
+                user_id: 1,
+                other_user_id: 2,
+                other_workspace_id: 2,
+                stranger_id: 3,
+            },
+        )
+        .await;
+    }
+}
@@ -0,0 +1,873 @@
+//! todos kept as an append-only stream of events per todo, `todos` and
+//! `todo_labels` are a projection of the streams which can be rebuilt at any time
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+
+use super::{
+    item::{CreateItem, TodoItem, UpdateItem},
+    label::Label,
+    replica::Replica,
+    todo::{CreateTodo, TodoEntity, TodoRepository, TodoRepositoryForDB, UpdateTodo},
+    RepositoryError,
+};
+
+/// tables of rows which hang off a todo outside of its stream, `rebuild` puts
+/// them back once the todo is projected again
+const KEPT_TABLES: [&str; 4] = ["todo_items", "comments", "attachments", "reminders"];
+
+/// what happened to a todo, labels carry their names so the history still
+/// reads the same after a label is deleted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TodoEvent {
+    TodoCreated { text: String, labels: Vec<Label> },
+    TextChanged { text: String },
+    Completed { completed: bool },
+    LabelsChanged { labels: Vec<Label> },
+    Deleted,
+}
+
+impl TodoEvent {
+    /// the todo `id` after the event, `None` before it is created and once it is deleted
+    fn apply(self, id: i32, todo: Option<TodoEntity>) -> Option<TodoEntity> {
+        match (self, todo) {
+            (TodoEvent::TodoCreated { text, labels }, _) => Some(TodoEntity {
+                text,
+                id,
+                completed: false,
+                labels,
+                items: vec![],
+                blocked: false,
+                blocked_by: vec![],
+            }),
+            (TodoEvent::TextChanged { text }, Some(todo)) => Some(TodoEntity { text, ..todo }),
+            (TodoEvent::Completed { completed }, Some(todo)) => {
+                Some(TodoEntity { completed, ..todo })
+            }
+            (TodoEvent::LabelsChanged { labels }, Some(todo)) => {
+                Some(TodoEntity { labels, ..todo })
+            }
+            (TodoEvent::Deleted, _) | (_, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct EventRow {
+    todo_id: i32,
+    workspace_id: i32,
+    user_id: i32,
+    payload: String,
+}
+
+/// text, completion and labels are recorded as events before they are projected,
+/// checklist items and blockers are not part of the history and go to the projection
+#[derive(Debug, Clone)]
+pub struct TodoRepositoryForEvents {
+    pool: PgPool,
+    projection: TodoRepositoryForDB,
+}
+
+impl TodoRepositoryForEvents {
+    pub fn new(pool: PgPool) -> Self {
+        TodoRepositoryForEvents {
+            projection: TodoRepositoryForDB::new(pool.clone()),
+            pool,
+        }
+    }
+
+    /// `find` and `all` read the projection from the replica, when there is one
+    pub fn with_replica(self, replica: Option<Replica>) -> Self {
+        Self {
+            projection: self.projection.with_replica(replica),
+            ..self
+        }
+    }
+
+    /// replays every stream into `todos` and `todo_labels` from scratch and returns
+    /// the number of events. writes made while event sourcing was off are recorded
+    /// first: a todo without a stream starts one with its current state and a
+    /// todo which is gone has its deletion appended, anything else changed outside
+    /// the streams is undone. checklist items and blockers are not part of the
+    /// history and are put back after the replay
+    pub async fn rebuild(&self) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        // reads go on, writes wait until the projection is consistent again
+        sqlx::query(
+            r#"
+            lock table todo_events, todos, todo_labels, todo_items, todo_blockers,
+                comments, attachments, reminders
+            in exclusive mode
+            "#,
+        )
+        .execute(&mut tx)
+        .await?;
+        self.adopt(&mut tx).await?;
+        sqlx::query(
+            r#"
+            insert into todo_events (todo_id, workspace_id, user_id, payload)
+            select last.todo_id, last.workspace_id, last.user_id, $1::jsonb
+            from (
+                select distinct on (todo_id) todo_id, workspace_id, user_id, payload
+                from todo_events
+                order by todo_id, id desc
+            ) last
+            where last.payload ->> 'type' <> 'Deleted'
+            and not exists (select 1 from todos where todos.id = last.todo_id)
+            "#,
+        )
+        .bind(serde_json::to_string(&TodoEvent::Deleted)?)
+        .execute(&mut tx)
+        .await?;
+
+        // the streams only know todos and their labels, the rows which hang off a
+        // todo are put aside and put back under the same ids once it is projected
+        for table in KEPT_TABLES.iter().chain(["todo_blockers"].iter()) {
+            sqlx::query(&format!(
+                "create temporary table kept_{0} on commit drop as select * from {0}",
+                table
+            ))
+            .execute(&mut tx)
+            .await?;
+        }
+        // every todo has a stream by now, its labels and what was put aside go by cascade
+        sqlx::query("delete from todos where id in (select todo_id from todo_events)")
+            .execute(&mut tx)
+            .await?;
+        let rows = sqlx::query_as::<_, EventRow>(
+            r#"
+            select todo_id, workspace_id, user_id, payload::text as payload
+            from todo_events
+            order by id
+            "#,
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        for row in rows.iter() {
+            let event = serde_json::from_str(&row.payload)?;
+            Self::project(&mut tx, row.workspace_id, row.user_id, row.todo_id, &event).await?;
+        }
+        for table in KEPT_TABLES {
+            sqlx::query(&format!(
+                "insert into {0} select * from kept_{0} where todo_id in (select id from todos)",
+                table
+            ))
+            .execute(&mut tx)
+            .await?;
+        }
+        sqlx::query(
+            r#"
+            insert into todo_blockers
+            select * from kept_todo_blockers
+            where todo_id in (select id from todos) and blocker_id in (select id from todos)
+            "#,
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(rows.len() as u64)
+    }
+
+    /// starts a stream for every todo written without one, its history begins now
+    async fn adopt(&self, tx: &mut Transaction<'_, Postgres>) -> anyhow::Result<()> {
+        let todos = sqlx::query_as::<_, (i32, i32, i32)>(
+            r#"
+            select id, workspace_id, user_id from todos
+            where not exists (select 1 from todo_events where todo_events.todo_id = todos.id)
+            order by id
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for (id, workspace_id, user_id) in todos {
+            let todo = self.projection.load(tx, workspace_id, user_id, id).await?;
+            let mut events = vec![TodoEvent::TodoCreated {
+                text: todo.text,
+                labels: todo.labels,
+            }];
+            if todo.completed {
+                events.push(TodoEvent::Completed { completed: true });
+            }
+            for event in events.iter() {
+                Self::append(tx, workspace_id, user_id, id, event).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// writes to the stream of a todo wait for each other, so each derives its
+    /// events from the state the one before left
+    async fn lock_stream(tx: &mut Transaction<'_, Postgres>, todo_id: i32) -> anyhow::Result<()> {
+        sqlx::query("select pg_advisory_xact_lock('todo_events'::regclass::oid::integer, $1)")
+            .bind(todo_id)
+            .execute(tx)
+            .await?;
+        Ok(())
+    }
+
+    /// labels of the owner among `ids`, others are silently skipped
+    async fn labels(
+        tx: &mut Transaction<'_, Postgres>,
+        workspace_id: i32,
+        user_id: i32,
+        ids: Vec<i32>,
+    ) -> anyhow::Result<Vec<Label>> {
+        let labels = sqlx::query_as::<_, Label>(
+            r#"
+            select id, name from labels
+            where id = any($1) and user_id = $2 and workspace_id = $3
+            order by id asc
+            "#,
+        )
+        .bind(ids)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_all(tx)
+        .await?;
+        Ok(labels)
+    }
+
+    /// appends `event` to the stream of the todo and projects it
+    async fn record(
+        tx: &mut Transaction<'_, Postgres>,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        event: &TodoEvent,
+    ) -> anyhow::Result<()> {
+        Self::append(tx, workspace_id, user_id, todo_id, event).await?;
+        Self::project(tx, workspace_id, user_id, todo_id, event).await
+    }
+
+    async fn append(
+        tx: &mut Transaction<'_, Postgres>,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        event: &TodoEvent,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into todo_events (todo_id, workspace_id, user_id, payload)
+            values ($1, $2, $3, $4::jsonb)
+            "#,
+        )
+        .bind(todo_id)
+        .bind(workspace_id)
+        .bind(user_id)
+        .bind(serde_json::to_string(event)?)
+        .execute(tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn project(
+        tx: &mut Transaction<'_, Postgres>,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        event: &TodoEvent,
+    ) -> anyhow::Result<()> {
+        match event {
+            TodoEvent::TodoCreated { text, labels } => {
+                sqlx::query(
+                    r#"
+                    insert into todos (id, text, completed, user_id, workspace_id)
+                    values ($1, $2, false, $3, $4)
+                    on conflict (id) do update
+                    set text = excluded.text, completed = false,
+                        user_id = excluded.user_id, workspace_id = excluded.workspace_id
+                    "#,
+                )
+                .bind(todo_id)
+                .bind(text)
+                .bind(user_id)
+                .bind(workspace_id)
+                .execute(&mut *tx)
+                .await?;
+                Self::project_labels(tx, todo_id, labels).await?;
+            }
+            TodoEvent::TextChanged { text } => {
+                sqlx::query("update todos set text = $2 where id = $1")
+                    .bind(todo_id)
+                    .bind(text)
+                    .execute(tx)
+                    .await?;
+            }
+            TodoEvent::Completed { completed } => {
+                sqlx::query("update todos set completed = $2 where id = $1")
+                    .bind(todo_id)
+                    .bind(completed)
+                    .execute(tx)
+                    .await?;
+            }
+            TodoEvent::LabelsChanged { labels } => {
+                Self::project_labels(tx, todo_id, labels).await?;
+            }
+            TodoEvent::Deleted => {
+                // items, blockers and todo_labels follow by cascade
+                sqlx::query("delete from todos where id = $1")
+                    .bind(todo_id)
+                    .execute(tx)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// labels deleted since the event are left out
+    async fn project_labels(
+        tx: &mut Transaction<'_, Postgres>,
+        todo_id: i32,
+        labels: &[Label],
+    ) -> anyhow::Result<()> {
+        sqlx::query("delete from todo_labels where todo_id = $1")
+            .bind(todo_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            insert into todo_labels (todo_id, label_id)
+            select $1, id from labels where id = any($2)
+            "#,
+        )
+        .bind(todo_id)
+        .bind(labels.iter().map(|label| label.id).collect::<Vec<_>>())
+        .execute(tx)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TodoRepository for TodoRepositoryForEvents {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.projection.written(workspace_id, user_id);
+        let mut tx = self.pool.begin().await?;
+        // the stream needs the id before the projection has a row
+        let (id,) = sqlx::query_as::<_, (i32,)>(
+            "select nextval(pg_get_serial_sequence('todos', 'id'))::integer",
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        let labels = Self::labels(&mut tx, workspace_id, user_id, payload.labels).await?;
+        let event = TodoEvent::TodoCreated {
+            text: payload.text,
+            labels,
+        };
+        Self::record(&mut tx, workspace_id, user_id, id, &event).await?;
+        let todo = self
+            .projection
+            .load(&mut tx, workspace_id, user_id, id)
+            .await?;
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity> {
+        self.projection.find(workspace_id, user_id, id).await
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        self.projection.all(workspace_id, user_id).await
+    }
+
+    /// only what actually changes is recorded
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.projection.written(workspace_id, user_id);
+        let mut tx = self.pool.begin().await?;
+        Self::lock_stream(&mut tx, id).await?;
+        let old_todo = self
+            .projection
+            .load(&mut tx, workspace_id, user_id, id)
+            .await?;
+        if payload.completed == Some(true) && !old_todo.completed && old_todo.blocked {
+            return Err(RepositoryError::Blocked(id).into());
+        }
+
+        let mut events = vec![];
+        if let Some(text) = payload.text.filter(|text| *text != old_todo.text) {
+            events.push(TodoEvent::TextChanged { text });
+        }
+        if let Some(completed) = payload
+            .completed
+            .filter(|completed| *completed != old_todo.completed)
+        {
+            events.push(TodoEvent::Completed { completed });
+        }
+        if let Some(ids) = payload.labels {
+            let labels = Self::labels(&mut tx, workspace_id, user_id, ids).await?;
+            if labels != old_todo.labels {
+                events.push(TodoEvent::LabelsChanged { labels });
+            }
+        }
+        for event in events.iter() {
+            Self::record(&mut tx, workspace_id, user_id, id, event).await?;
+        }
+        let todo = self
+            .projection
+            .load(&mut tx, workspace_id, user_id, id)
+            .await?;
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        self.projection.written(workspace_id, user_id);
+        let mut tx = self.pool.begin().await?;
+        Self::lock_stream(&mut tx, id).await?;
+        let (found,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            select exists(
+                select 1 from todos where id = $1 and user_id = $2 and workspace_id = $3
+            )
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_one(&mut tx)
+        .await?;
+        if !found {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Self::record(&mut tx, workspace_id, user_id, id, &TodoEvent::Deleted).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem> {
+        self.projection
+            .create_item(workspace_id, user_id, todo_id, payload)
+            .await
+    }
+
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem> {
+        self.projection
+            .update_item(workspace_id, user_id, todo_id, item_id, payload)
+            .await
+    }
+
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()> {
+        self.projection
+            .delete_item(workspace_id, user_id, todo_id, item_id)
+            .await
+    }
+
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        self.projection
+            .add_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await
+    }
+
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()> {
+        self.projection
+            .remove_blocker(workspace_id, user_id, todo_id, blocker_id)
+            .await
+    }
+
+    /// folds the events recorded up to `at`, checklist items and blockers are left empty
+    async fn find_as_of(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<TodoEntity> {
+        let payloads = sqlx::query_as::<_, (String,)>(
+            r#"
+            select payload::text from todo_events
+            where todo_id = $1 and user_id = $2 and workspace_id = $3 and recorded_at <= $4
+            order by id
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .bind(at)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut todo = None;
+        for (payload,) in payloads {
+            let event: TodoEvent = serde_json::from_str(&payload)?;
+            todo = event.apply(id, todo);
+        }
+        Ok(todo.ok_or(RepositoryError::NotFound(id))?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn apply_test() {
+        let label = Label {
+            id: 1,
+            name: String::from("label 1"),
+        };
+        let events = vec![
+            TodoEvent::TodoCreated {
+                text: String::from("todo"),
+                labels: vec![],
+            },
+            TodoEvent::TextChanged {
+                text: String::from("changed todo"),
+            },
+            TodoEvent::Completed { completed: true },
+            TodoEvent::LabelsChanged {
+                labels: vec![label.clone()],
+            },
+        ];
+        let todo = events
+            .into_iter()
+            .fold(None, |todo, event| event.apply(7, todo));
+        assert_eq!(
+            Some(TodoEntity {
+                text: String::from("changed todo"),
+                id: 7,
+                completed: true,
+                labels: vec![label],
+                items: vec![],
+                blocked: false,
+                blocked_by: vec![],
+            }),
+            todo
+        );
+        assert_eq!(None, TodoEvent::Deleted.apply(7, todo));
+        // nothing can change a todo before it is created
+        assert_eq!(
+            None,
+            TodoEvent::Completed { completed: true }.apply(7, None)
+        );
+
+        let payload = serde_json::to_string(&TodoEvent::Completed { completed: true }).unwrap();
+        assert_eq!(r#"{"type":"Completed","completed":true}"#, payload);
+    }
+
+    #[cfg(feature = "database-test")]
+    mod postgres {
+        use super::*;
+        use crate::repositories::{
+            attachment::{AttachmentRepository, AttachmentRepositoryForDB, CreateAttachment},
+            comment::{CommentRepository, CommentRepositoryForDB, CreateComment},
+            label::{LabelRepository, LabelRepositoryforDB},
+            reminder::{CreateReminder, ReminderRepository, ReminderRepositoryForDB},
+            user::test_utils::prepare_named_test_user,
+        };
+        use dotenv::dotenv;
+        use std::{env, time::Duration};
+
+        async fn connect() -> PgPool {
+            dotenv().ok();
+            let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+            PgPool::connect(database_url)
+                .await
+                .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url))
+        }
+
+        /// a moment between two writes, `now()` of postgres is the start of the transaction
+        async fn moment() -> DateTime<Utc> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let at = Utc::now();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            at
+        }
+
+        #[tokio::test]
+        async fn history_scenario() {
+            let pool = connect().await;
+            let user_id = prepare_named_test_user(&pool, "[events] history user").await;
+            let repository = TodoRepositoryForEvents::new(pool.clone());
+            let label = LabelRepositoryforDB::new(pool.clone())
+                .create(1, user_id, String::from("[events] label"))
+                .await
+                .expect("[create label] returned Err");
+
+            let before = moment().await;
+            let todo = repository
+                .create(1, user_id, CreateTodo::new(String::from("first"), vec![]))
+                .await
+                .expect("[create] returned Err");
+            let created = moment().await;
+            repository
+                .update(
+                    1,
+                    user_id,
+                    todo.id,
+                    UpdateTodo::new(
+                        Some(String::from("second")),
+                        Some(true),
+                        Some(vec![label.id]),
+                    ),
+                )
+                .await
+                .expect("[update] returned Err");
+            let updated = moment().await;
+            repository
+                .delete(1, user_id, todo.id)
+                .await
+                .expect("[delete] returned Err");
+
+            let as_of = |at| repository.find_as_of(1, user_id, todo.id, at);
+            let first = as_of(created).await.expect("[find_as_of] returned Err");
+            assert_eq!(("first", false), (first.text.as_str(), first.completed));
+            assert!(first.labels.is_empty());
+            let second = as_of(updated).await.expect("[find_as_of] returned Err");
+            assert_eq!(("second", true), (second.text.as_str(), second.completed));
+            assert_eq!(vec![label.clone()], second.labels);
+            for at in [before, Utc::now()] {
+                let error = as_of(at).await.expect_err("[find_as_of] returned Ok");
+                assert_eq!(
+                    Some(&RepositoryError::NotFound(todo.id)),
+                    error.downcast_ref::<RepositoryError>()
+                );
+            }
+            // someone else's todo has no history to them
+            let error = repository
+                .find_as_of(1, user_id + 1, todo.id, updated)
+                .await
+                .expect_err("[find_as_of] returned Ok");
+            assert_eq!(
+                Some(&RepositoryError::NotFound(todo.id)),
+                error.downcast_ref::<RepositoryError>()
+            );
+
+            let error = TodoRepositoryForDB::new(pool.clone())
+                .find_as_of(1, user_id, todo.id, updated)
+                .await
+                .expect_err("[find_as_of] returned Ok");
+            assert_eq!(
+                Some(&RepositoryError::NoHistory(todo.id)),
+                error.downcast_ref::<RepositoryError>()
+            );
+
+            LabelRepositoryforDB::new(pool)
+                .delete(1, user_id, label.id)
+                .await
+                .expect("[delete label] returned Err");
+        }
+
+        #[tokio::test]
+        async fn rebuild_scenario() {
+            let pool = connect().await;
+            let user_id = prepare_named_test_user(&pool, "[events] rebuild user").await;
+            let repository = TodoRepositoryForEvents::new(pool.clone());
+            for todo in repository.all(1, user_id).await.unwrap() {
+                repository.delete(1, user_id, todo.id).await.unwrap();
+            }
+            let kept = repository
+                .create(1, user_id, CreateTodo::new(String::from("kept"), vec![]))
+                .await
+                .expect("[create] returned Err");
+            let kept = repository
+                .update(1, user_id, kept.id, UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("[update] returned Err");
+            repository
+                .create_item(
+                    1,
+                    user_id,
+                    kept.id,
+                    CreateItem {
+                        text: String::from("item"),
+                        position: None,
+                    },
+                )
+                .await
+                .expect("[create_item] returned Err");
+            let kept = repository.find(1, user_id, kept.id).await.unwrap();
+            // rows which hang off the todo outside of its stream
+            let comment = CommentRepositoryForDB::new(pool.clone())
+                .create(
+                    kept.id,
+                    CreateComment {
+                        body: String::from("comment"),
+                    },
+                )
+                .await
+                .expect("[create comment] returned Err");
+            let attachment = AttachmentRepositoryForDB::new(pool.clone())
+                .create(
+                    kept.id,
+                    CreateAttachment {
+                        file_name: String::from("log.txt"),
+                        content_type: String::from("text/plain"),
+                        size: 3,
+                    },
+                )
+                .await
+                .expect("[create attachment] returned Err");
+            let reminder = ReminderRepositoryForDB::new(pool.clone())
+                .create(
+                    kept.id,
+                    CreateReminder {
+                        remind_at: Utc::now() + chrono::Duration::days(1),
+                    },
+                )
+                .await
+                .expect("[create reminder] returned Err");
+            let gone = repository
+                .create(1, user_id, CreateTodo::new(String::from("gone"), vec![]))
+                .await
+                .expect("[create] returned Err");
+            let deleted = repository
+                .create(1, user_id, CreateTodo::new(String::from("deleted"), vec![]))
+                .await
+                .expect("[create] returned Err");
+            repository
+                .delete(1, user_id, deleted.id)
+                .await
+                .expect("[delete] returned Err");
+            // written while event sourcing was off
+            let adopted = TodoRepositoryForDB::new(pool.clone())
+                .create(1, user_id, CreateTodo::new(String::from("adopted"), vec![]))
+                .await
+                .expect("[create] returned Err");
+            let adopted = TodoRepositoryForDB::new(pool.clone())
+                .update(
+                    1,
+                    user_id,
+                    adopted.id,
+                    UpdateTodo::new(None, Some(true), None),
+                )
+                .await
+                .expect("[update] returned Err");
+            sqlx::query("delete from todos where id = $1")
+                .bind(gone.id)
+                .execute(&pool)
+                .await
+                .unwrap();
+            // the projection drifts away from the streams
+            sqlx::query("update todos set text = 'drifted', completed = false where id = $1")
+                .bind(kept.id)
+                .execute(&pool)
+                .await
+                .unwrap();
+
+            let replayed = repository.rebuild().await.expect("[rebuild] returned Err");
+            assert!(replayed >= 9, "replayed {} events", replayed);
+            assert_eq!(
+                vec![adopted.clone(), kept.clone()],
+                repository
+                    .all(1, user_id)
+                    .await
+                    .expect("[all] returned Err")
+            );
+            assert_eq!(
+                vec![comment],
+                CommentRepositoryForDB::new(pool.clone())
+                    .all(kept.id)
+                    .await
+                    .unwrap()
+            );
+            assert_eq!(
+                vec![attachment],
+                AttachmentRepositoryForDB::new(pool.clone())
+                    .all(kept.id)
+                    .await
+                    .unwrap()
+            );
+            assert_eq!(
+                vec![reminder],
+                ReminderRepositoryForDB::new(pool.clone())
+                    .all(kept.id)
+                    .await
+                    .unwrap()
+            );
+            // both now have a history, the adopted todo from the rebuild on
+            let now = Utc::now();
+            let history = repository
+                .find_as_of(1, user_id, adopted.id, now)
+                .await
+                .expect("[find_as_of] returned Err");
+            assert_eq!(
+                ("adopted", true),
+                (history.text.as_str(), history.completed)
+            );
+            let error = repository
+                .find_as_of(1, user_id, gone.id, now)
+                .await
+                .expect_err("[find_as_of] returned Ok");
+            assert_eq!(
+                Some(&RepositoryError::NotFound(gone.id)),
+                error.downcast_ref::<RepositoryError>()
+            );
+        }
+
+        #[tokio::test]
+        async fn concurrent_update_scenario() {
+            let pool = connect().await;
+            let user_id = prepare_named_test_user(&pool, "[events] concurrent user").await;
+            let repository = TodoRepositoryForEvents::new(pool.clone());
+            let todo = repository
+                .create(1, user_id, CreateTodo::new(String::from("todo"), vec![]))
+                .await
+                .expect("[create] returned Err");
+
+            let complete =
+                || repository.update(1, user_id, todo.id, UpdateTodo::new(None, Some(true), None));
+            let (first, second) = tokio::join!(complete(), complete());
+            first.expect("[update] returned Err");
+            second.expect("[update] returned Err");
+            // the second saw the first and had nothing to record
+            let (completions,) = sqlx::query_as::<_, (i64,)>(
+                r#"
+                select count(*) from todo_events
+                where todo_id = $1 and payload ->> 'type' = 'Completed'
+                "#,
+            )
+            .bind(todo.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            assert_eq!(1, completions);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use axum::async_trait;
+use serde::Serialize;
+use sqlx::{PgPool, SqlitePool};
+
+use crate::{
+    journal::Journal,
+    migrate::{self, Schema},
+};
+
+/// checks behind the readiness probe
+/// migrations -- reaches the database and reads which migrations it has applied
+#[async_trait]
+pub trait HealthRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn migrations(&self) -> anyhow::Result<MigrationStatus>;
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub applied: i64,
+    /// embedded in this build but not applied yet
+    pub pending: i64,
+    /// version of the newest applied migration
+    pub latest: Option<i64>,
+    /// a migration failed halfway and has to be fixed by hand
+    pub dirty: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthRepositoryForDB {
+    pool: PgPool,
+}
+
+impl HealthRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthRepository for HealthRepositoryForDB {
+    async fn migrations(&self) -> anyhow::Result<MigrationStatus> {
+        migration_status(&self.pool).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl HealthRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthRepository for HealthRepositoryForSqlite {
+    async fn migrations(&self) -> anyhow::Result<MigrationStatus> {
+        migration_status(&self.pool).await
+    }
+}
+
+/// the journal has no migrations, it is ready as long as it can be reached
+#[derive(Debug, Clone)]
+pub struct HealthRepositoryForJournal {
+    journal: Journal,
+}
+
+impl HealthRepositoryForJournal {
+    pub fn new(journal: Journal) -> Self {
+        Self { journal }
+    }
+}
+
+#[async_trait]
+impl HealthRepository for HealthRepositoryForJournal {
+    async fn migrations(&self) -> anyhow::Result<MigrationStatus> {
+        self.journal.check()?;
+        Ok(MigrationStatus {
+            applied: 0,
+            pending: 0,
+            latest: None,
+            dirty: false,
+        })
+    }
+}
+
+async fn migration_status(pool: &impl Schema) -> anyhow::Result<MigrationStatus> {
+    let applied = pool.applied().await?;
+    Ok(MigrationStatus {
+        applied: applied.len() as i64,
+        pending: migrate::pending(pool.migrator(), &applied) as i64,
+        latest: applied.iter().map(|(version, _)| *version).max(),
+        dirty: applied.iter().any(|(_, success)| !success),
+    })
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn migrations_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+        let repository = HealthRepositoryForDB::new(pool);
+
+        let status = repository
+            .migrations()
+            .await
+            .expect("[migrations] returned Err");
+        assert_eq!(status.applied > 0, status.latest.is_some());
+        assert!(!status.dirty);
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+
+    /// healthy with one applied migration until `fail` is called
+    #[derive(Debug, Clone, Default)]
+    pub struct HealthRepositoryForMemory {
+        failing: Arc<RwLock<bool>>,
+    }
+
+    impl HealthRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn fail(&self) {
+            *self.failing.write().unwrap() = true;
+        }
+    }
+
+    #[async_trait]
+    impl HealthRepository for HealthRepositoryForMemory {
+        async fn migrations(&self) -> anyhow::Result<MigrationStatus> {
+            if *self.failing.read().unwrap() {
+                anyhow::bail!("database unreachable");
+            }
+            Ok(MigrationStatus {
+                applied: 1,
+                pending: 0,
+                latest: Some(1),
+                dirty: false,
+            })
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+pub use ::todo::entity::TodoItem;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateItem {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 100, message = "Over text length"))]
+    pub text: String,
+    /// appended to the end of the checklist when omitted
+    pub position: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct UpdateItem {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 100, message = "Over text length"))]
+    pub text: Option<String>,
+    pub done: Option<bool>,
+    pub position: Option<i32>,
+}
+
+/// group items by the TODO they belong to, keeping checklist order
+pub fn group_items(items: Vec<TodoItem>) -> HashMap<i32, Vec<TodoItem>> {
+    let mut grouped: HashMap<i32, Vec<TodoItem>> = HashMap::new();
+    for item in items {
+        grouped.entry(item.todo_id).or_default().push(item);
+    }
+    for items in grouped.values_mut() {
+        items.sort_by_key(|item| (item.position, item.id));
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn item(id: i32, todo_id: i32, position: i32) -> TodoItem {
+        TodoItem {
+            id,
+            todo_id,
+            text: format!("item {}", id),
+            done: false,
+            position,
+        }
+    }
+
+    #[test]
+    fn group_items_test() {
+        let grouped = group_items(vec![item(1, 1, 2), item(2, 2, 1), item(3, 1, 1)]);
+        assert_eq!(grouped[&1], vec![item(3, 1, 1), item(1, 1, 2)]);
+        assert_eq!(grouped[&2], vec![item(2, 2, 1)]);
+    }
+}
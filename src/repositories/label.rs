@@ -1,20 +1,25 @@
-use super::RepositoryError;
+use super::{replica::Replica, retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+pub use ::todo::entity::Label;
 use axum::async_trait;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{PgPool, SqlitePool};
 use validator::Validate;
 
+/// operation to labels, every method is limited to labels of `user_id` in `workspace_id`
 #[async_trait]
 pub trait LabelRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
-    async fn create(&self, name: String) -> anyhow::Result<Label>;
-    async fn all(&self) -> anyhow::Result<Vec<Label>>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
-pub struct Label {
-    pub name: String,
-    pub id: i32,
+    async fn create(&self, workspace_id: i32, user_id: i32, name: String) -> anyhow::Result<Label>;
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<Label>>;
+    /// renames the label, every todo carrying it shows the new name
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        name: String,
+    ) -> anyhow::Result<Label>;
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()>;
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate, Clone, PartialEq, Eq)]
@@ -22,32 +27,49 @@ pub struct CreateLabel {
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Validate, Clone, PartialEq, Eq)]
 pub struct UpdateLabel {
-    id: i32,
-    name: String,
+    pub name: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct LabelRepositoryforDB {
     pool: PgPool,
+    replica: Option<Replica>,
 }
 
 impl LabelRepositoryforDB {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            replica: None,
+        }
+    }
+
+    /// `all` reads from the replica, when there is one
+    pub fn with_replica(self, replica: Option<Replica>) -> Self {
+        Self { replica, ..self }
+    }
+
+    fn written(&self, workspace_id: i32, user_id: i32) {
+        if let Some(replica) = &self.replica {
+            replica.written(workspace_id, user_id);
+        }
     }
 }
 
 #[async_trait]
 impl LabelRepository for LabelRepositoryforDB {
-    async fn create(&self, name: String) -> anyhow::Result<Label> {
+    async fn create(&self, workspace_id: i32, user_id: i32, name: String) -> anyhow::Result<Label> {
+        self.written(workspace_id, user_id);
         let optional_label = sqlx::query_as::<_, Label>(
             r#"
-                select * from labels where name=$1
+                select id, name from labels where name=$1 and user_id=$2 and workspace_id=$3
             "#,
         )
         .bind(name.clone())
+        .bind(user_id)
+        .bind(workspace_id)
         .fetch_optional(&self.pool)
         .await?;
 
@@ -57,157 +79,422 @@ impl LabelRepository for LabelRepositoryforDB {
 
         let label = sqlx::query_as::<_, Label>(
             r#"
-            insert into labels ( name )
-            values ($1)
-            returning *
+            insert into labels ( name, user_id, workspace_id )
+            values ($1, $2, $3)
+            returning id, name
             "#,
         )
         .bind(name.clone())
+        .bind(user_id)
+        .bind(workspace_id)
         .fetch_one(&self.pool)
         .await?;
 
         Ok(label)
     }
 
-    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<Label>> {
+        retry::read(|| async move {
+            let pool = match &self.replica {
+                Some(replica) => replica.reader(&self.pool, workspace_id, user_id),
+                None => &self.pool,
+            };
+            let labels = sqlx::query_as::<_, Label>(
+                r#"
+                select id, name from labels
+                where user_id = $1 and workspace_id = $2
+                order by labels.id asc;
+                "#,
+            )
+            .bind(user_id)
+            .bind(workspace_id)
+            .fetch_all(pool)
+            .await?;
+            Ok(labels)
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        name: String,
+    ) -> anyhow::Result<Label> {
+        self.written(workspace_id, user_id);
+        let mut tx = self.pool.begin().await?;
+        let duplicate = sqlx::query_as::<_, Label>(
+            r#"
+                select id, name from labels
+                where name=$1 and user_id=$2 and workspace_id=$3 and id<>$4
+            "#,
+        )
+        .bind(name.clone())
+        .bind(user_id)
+        .bind(workspace_id)
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?;
+
+        if let Some(label) = duplicate {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+            update labels set name=$1
+            where id=$2 and user_id=$3 and workspace_id=$4
+            returning id, name
+            "#,
+        )
+        .bind(name)
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_all(&mut tx)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::NotFound(id))?;
+        tx.commit().await?;
+
+        Ok(label)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        self.written(workspace_id, user_id);
+        let result = sqlx::query(
+            r#"
+            delete from labels where id=$1 and user_id=$2 and workspace_id=$3
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| RepositoryError::Unexpected(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+}
+
+/// the queries are the same on sqlite
+#[derive(Debug, Clone)]
+pub struct LabelRepositoryforSqlite {
+    pool: SqlitePool,
+}
+
+impl LabelRepositoryforSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl LabelRepository for LabelRepositoryforSqlite {
+    async fn create(&self, workspace_id: i32, user_id: i32, name: String) -> anyhow::Result<Label> {
+        let optional_label = sqlx::query_as::<_, Label>(
+            r#"
+                select id, name from labels where name=$1 and user_id=$2 and workspace_id=$3
+            "#,
+        )
+        .bind(name.clone())
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(label) = optional_label {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+            insert into labels ( name, user_id, workspace_id )
+            values ($1, $2, $3)
+            returning id, name
+            "#,
+        )
+        .bind(name.clone())
+        .bind(user_id)
+        .bind(workspace_id)
+        // read to the end, a statement left unfinished keeps the insert uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or_else(|| RepositoryError::Unexpected("no label returned".to_string()))?;
+
+        Ok(label)
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<Label>> {
         let labels = sqlx::query_as::<_, Label>(
             r#"
-            select * from labels
+            select id, name from labels
+            where user_id = $1 and workspace_id = $2
             order by labels.id asc;
             "#,
         )
+        .bind(user_id)
+        .bind(workspace_id)
         .fetch_all(&self.pool)
         .await?;
         Ok(labels)
     }
 
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        sqlx::query(
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        name: String,
+    ) -> anyhow::Result<Label> {
+        let mut tx = self.pool.begin().await?;
+        let duplicate = sqlx::query_as::<_, Label>(
             r#"
-            delete from labels where id=$1
+                select id, name from labels
+                where name=$1 and user_id=$2 and workspace_id=$3 and id<>$4
             "#,
         )
+        .bind(name.clone())
+        .bind(user_id)
+        .bind(workspace_id)
         .bind(id)
+        .fetch_optional(&mut tx)
+        .await?;
+
+        if let Some(label) = duplicate {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+            update labels set name=$1
+            where id=$2 and user_id=$3 and workspace_id=$4
+            returning id, name
+            "#,
+        )
+        .bind(name)
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_all(&mut tx)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::NotFound(id))?;
+        tx.commit().await?;
+
+        Ok(label)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from labels where id=$1 and user_id=$2 and workspace_id=$3
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
         .execute(&self.pool)
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .map_err(|e| RepositoryError::Unexpected(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+}
+
+/// labels with the ids of their workspace and user
+pub type LabelRow = ((i32, i32), Label);
+
+/// keeps labels in a table of a `Journal`
+#[derive(Debug, Clone)]
+pub struct LabelRepositoryforMemory {
+    store: Table<i32, LabelRow>,
+}
+
+impl LabelRepositoryforMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("labels")?,
+        })
+    }
+}
+
+#[async_trait]
+impl LabelRepository for LabelRepositoryforMemory {
+    async fn create(&self, workspace_id: i32, user_id: i32, name: String) -> anyhow::Result<Label> {
+        let owner = (workspace_id, user_id);
+        let mut store = self.store.write().await;
+        if let Some((_, label)) = store
+            .values()
+            .find(|(label_owner, label)| *label_owner == owner && label.name == name)
+        {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+        let id = store.next_id();
+        let label = Label { id, name };
+        store.insert(id, (owner, label.clone()));
+        store.commit().await?;
+        Ok(label)
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<Label>> {
+        let store = self.store.read().await;
+        Ok(store
+            .values()
+            .filter(|(owner, _)| *owner == (workspace_id, user_id))
+            .map(|(_, label)| label.clone())
+            .collect())
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        name: String,
+    ) -> anyhow::Result<Label> {
+        let owner = (workspace_id, user_id);
+        let mut store = self.store.write().await;
+        if let Some((_, label)) = store.values().find(|(label_owner, label)| {
+            *label_owner == owner && label.name == name && label.id != id
+        }) {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+        let label = match store.get(&id) {
+            Some((label_owner, _)) if *label_owner == owner => Label { id, name },
+            _ => return Err(RepositoryError::NotFound(id).into()),
+        };
+        store.insert(id, (owner, label.clone()));
+        store.commit().await?;
+        Ok(label)
+    }
 
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        match store.get(&id) {
+            Some((owner, _)) if *owner == (workspace_id, user_id) => store.remove(&id),
+            _ => return Err(RepositoryError::NotFound(id).into()),
+        };
+        store.commit().await?;
         Ok(())
     }
 }
 
 #[cfg(test)]
-#[cfg(feature = "database-test")]
 mod test {
     use super::*;
-    use dotenv::dotenv;
-    use sqlx::PgPool;
-    use std::env;
-
-    #[tokio::test]
-    async fn crud_scenario() {
-        dotenv().ok();
-        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
-        let pool = PgPool::connect(database_url)
-            .await
-            .expect(&format!("fail connect database, url is [{}]", database_url));
 
-        let repository = LabelRepositoryforDB::new(pool.clone());
+    /// the same scenario runs against every backend
+    pub async fn crud_scenario<R: LabelRepository>(repository: &R, user_id: i32) {
         let label_text = "[crud_scenario] label";
 
         // create
         let label = repository
-            .create(label_text.to_string())
+            .create(1, user_id, label_text.to_string())
             .await
             .expect("[create] returned Err");
         assert_eq!(label.name, label_text);
 
         // all
-        let labels = repository.all().await.expect("[all] returened Err");
+        let labels = repository
+            .all(1, user_id)
+            .await
+            .expect("[all] returened Err");
         let label = labels.last().unwrap();
         assert_eq!(label.name, label_text);
+        assert!(repository.delete(1, user_id + 1, label.id).await.is_err());
+        assert!(repository.delete(2, user_id, label.id).await.is_err());
+
+        // update
+        let renamed = repository
+            .update(1, user_id, label.id, "[crud_scenario] renamed".to_string())
+            .await
+            .expect("[update] returned Err");
+        assert_eq!(label.id, renamed.id);
+        assert_eq!("[crud_scenario] renamed", renamed.name);
+        assert!(repository
+            .update(1, user_id + 1, label.id, "stolen".to_string())
+            .await
+            .is_err());
+        let other = repository
+            .create(1, user_id, "[crud_scenario] other".to_string())
+            .await
+            .expect("[create] returned Err");
+        let err = repository
+            .update(1, user_id, other.id, renamed.name.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(&RepositoryError::Duplicate(label.id)),
+            err.downcast_ref::<RepositoryError>()
+        );
+        repository
+            .delete(1, user_id, other.id)
+            .await
+            .expect("[delete] returned Err");
 
         // delete
         repository
-            .delete(label.id)
+            .delete(1, user_id, label.id)
             .await
             .expect("[delete] returned Err");
     }
-}
 
-pub mod test_utils {
-    use super::*;
-    use anyhow::Context;
-    use axum::async_trait;
-    use std::{
-        collections::HashMap,
-        sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    };
-
-    impl Label {
-        pub fn new(id: i32, name: String) -> Self {
-            Self { id, name }
-        }
-    }
+    #[cfg(feature = "database-test")]
+    mod postgres {
+        use crate::repositories::user::test_utils::prepare_test_user;
+        use dotenv::dotenv;
+        use sqlx::PgPool;
+        use std::env;
 
-    impl CreateLabel {
-        pub fn new(name: String) -> Self {
-            Self { name }
+        #[tokio::test]
+        async fn crud_scenario() {
+            dotenv().ok();
+            let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+            let pool = PgPool::connect(database_url)
+                .await
+                .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+            let user_id = prepare_test_user(&pool).await;
+            super::crud_scenario(&super::LabelRepositoryforDB::new(pool), user_id).await;
         }
     }
 
-    type LabelDatas = HashMap<i32, Label>;
-
-    #[derive(Debug, Clone)]
-    pub struct LabelRepositoryforMemory {
-        store: Arc<RwLock<LabelDatas>>,
-    }
+    mod sqlite {
+        use crate::repositories::test_utils::{prepare_sqlite_pool, prepare_sqlite_test_user};
 
-    impl LabelRepositoryforMemory {
-        pub fn new() -> Self {
-            LabelRepositoryforMemory {
-                store: Arc::default(),
-            }
-        }
-
-        fn write_store_ref(&self) -> RwLockWriteGuard<LabelDatas> {
-            self.store.write().unwrap()
-        }
-
-        fn read_store_ref(&self) -> RwLockReadGuard<LabelDatas> {
-            self.store.read().unwrap()
+        #[tokio::test]
+        async fn crud_scenario() {
+            let pool = prepare_sqlite_pool().await;
+            let user_id = prepare_sqlite_test_user(&pool, 1, "[test] user").await;
+            super::crud_scenario(&super::LabelRepositoryforSqlite::new(pool), user_id).await;
         }
     }
+}
 
-    #[async_trait]
-    impl LabelRepository for LabelRepositoryforMemory {
-        async fn create(&self, name: String) -> anyhow::Result<Label> {
-            let mut store = self.write_store_ref();
-            let id = (store.len() + 1) as i32;
-            let label = Label::new(id, name.clone());
-            store.insert(id, label.clone());
-            Ok(label)
-        }
-
-        async fn all(&self) -> anyhow::Result<Vec<Label>> {
-            let store = self.write_store_ref();
-            Ok(Vec::from_iter(store.values().map(|label| label.clone())))
-        }
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
 
-        async fn delete(&self, id: i32) -> anyhow::Result<()> {
-            let mut store = self.write_store_ref();
-            store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
-            Ok(())
+    impl LabelRepositoryforMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
         }
     }
 
     #[cfg(test)]
     mod test {
         use super::*;
-        use crate::repositories::label::{CreateLabel, Label};
+        use crate::repositories::label::Label;
 
         #[tokio::test]
         async fn label_crud_scenario() {
@@ -218,17 +505,32 @@ pub mod test_utils {
             //create
             let repository = LabelRepositoryforMemory::new();
             let label = repository
-                .create(name.clone())
+                .create(1, 1, name.clone())
                 .await
                 .expect("failed create label");
             assert_eq!(expected, label);
 
             //all
-            let labels = repository.all().await.expect("failed get all labels");
+            let labels = repository.all(1, 1).await.expect("failed get all labels");
             assert_eq!(vec![expected], labels);
+            assert!(repository.all(1, 2).await.unwrap().is_empty());
+            assert!(repository.all(2, 1).await.unwrap().is_empty());
+
+            //update
+            let renamed = repository
+                .update(1, 1, id, "renamed".to_string())
+                .await
+                .expect("failed update label");
+            assert_eq!(Label::new(id, "renamed".to_string()), renamed);
+            assert!(repository
+                .update(1, 2, id, "stolen".to_string())
+                .await
+                .is_err());
 
             //delete
-            let res = repository.delete(id).await;
+            assert!(repository.delete(1, 2, id).await.is_err());
+            assert!(repository.delete(2, 1, id).await.is_err());
+            let res = repository.delete(1, 1, id).await;
             assert!(res.is_ok())
         }
     }
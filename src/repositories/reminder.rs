@@ -0,0 +1,581 @@
+use super::todo::TodoRow;
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+use validator::Validate;
+
+/// operation to reminders of a TODO
+/// create: POST -- schedule a reminder
+/// all: GET -- reminders of a TODO
+/// delete: DELETE -- cancel a reminder
+/// claim_due -- reminders to send now, hidden from other schedulers for `lease`
+/// mark_sent, mark_failed -- bookkeeping after a delivery attempt
+#[async_trait]
+pub trait ReminderRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, todo_id: i32, payload: CreateReminder) -> anyhow::Result<Reminder>;
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Reminder>>;
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()>;
+    async fn claim_due(
+        &self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: i64,
+    ) -> anyhow::Result<Vec<DueReminder>>;
+    async fn mark_sent(&self, id: i32, sent_at: DateTime<Utc>) -> anyhow::Result<()>;
+    /// `retry_at` of `None` gives up on the reminder
+    async fn mark_failed(
+        &self,
+        id: i32,
+        error: String,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct Reminder {
+    pub id: i32,
+    pub todo_id: i32,
+    pub remind_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    /// `None` once sent or given up
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+}
+
+/// reminder joined with the TODO it is about
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct DueReminder {
+    pub id: i32,
+    pub todo_id: i32,
+    pub text: String,
+    pub remind_at: DateTime<Utc>,
+    pub attempts: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateReminder {
+    pub remind_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReminderRepositoryForDB {
+    pool: PgPool,
+}
+
+impl ReminderRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ReminderRepository for ReminderRepositoryForDB {
+    async fn create(&self, todo_id: i32, payload: CreateReminder) -> anyhow::Result<Reminder> {
+        let reminder = sqlx::query_as::<_, Reminder>(
+            r#"
+            insert into reminders (todo_id, remind_at, next_attempt_at)
+            values ($1, $2, $2)
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.remind_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(reminder)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Reminder>> {
+        retry::read(|| async move {
+            let reminders = sqlx::query_as::<_, Reminder>(
+                r#"
+                select * from reminders
+                where todo_id = $1
+                order by remind_at asc, id asc
+                "#,
+            )
+            .bind(todo_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(reminders)
+        })
+        .await
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from reminders where todo_id = $1 and id = $2
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn claim_due(
+        &self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: i64,
+    ) -> anyhow::Result<Vec<DueReminder>> {
+        // pushing next_attempt_at forward is the claim, skip locked rows of other schedulers
+        let reminders = sqlx::query_as::<_, DueReminder>(
+            r#"
+            with due as (
+                select id from reminders
+                where sent_at is null and next_attempt_at <= $1
+                order by next_attempt_at asc
+                limit $3
+                for update skip locked
+            )
+            update reminders set next_attempt_at = $2
+            from due, todos
+            where reminders.id = due.id and todos.id = reminders.todo_id
+            returning reminders.id, reminders.todo_id, todos.text,
+                reminders.remind_at, reminders.attempts
+            "#,
+        )
+        .bind(now)
+        .bind(now + lease)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(reminders)
+    }
+
+    async fn mark_sent(&self, id: i32, sent_at: DateTime<Utc>) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            update reminders
+            set sent_at = $2, next_attempt_at = null, attempts = attempts + 1, last_error = null
+            where id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(sent_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: i32,
+        error: String,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            update reminders
+            set next_attempt_at = $3, attempts = attempts + 1, last_error = $2
+            where id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .bind(retry_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReminderRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl ReminderRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ReminderRepository for ReminderRepositoryForSqlite {
+    async fn create(&self, todo_id: i32, payload: CreateReminder) -> anyhow::Result<Reminder> {
+        let reminder = sqlx::query_as::<_, Reminder>(
+            r#"
+            insert into reminders (todo_id, remind_at, next_attempt_at)
+            values ($1, $2, $2)
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.remind_at)
+        // read to the end, a statement left unfinished keeps the write uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or_else(|| RepositoryError::Unexpected("no reminder returned".to_string()))?;
+
+        Ok(reminder)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Reminder>> {
+        let reminders = sqlx::query_as::<_, Reminder>(
+            r#"
+            select * from reminders
+            where todo_id = $1
+            order by remind_at asc, id asc
+            "#,
+        )
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(reminders)
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from reminders where todo_id = $1 and id = $2
+            "#,
+        )
+        .bind(todo_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn claim_due(
+        &self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: i64,
+    ) -> anyhow::Result<Vec<DueReminder>> {
+        // pushing next_attempt_at forward is the claim, sqlite has a single writer
+        // so no other scheduler can claim the same rows in between
+        let mut tx = self.pool.begin().await?;
+        let claimed = sqlx::query_as::<_, (i32,)>(
+            r#"
+            update reminders set next_attempt_at = $2
+            where id in (
+                select id from reminders
+                where sent_at is null and next_attempt_at <= $1
+                order by next_attempt_at asc
+                limit $3
+            )
+            returning id
+            "#,
+        )
+        .bind(now)
+        .bind(now + lease)
+        .bind(limit)
+        .fetch_all(&mut tx)
+        .await?;
+        let ids: Vec<i32> = claimed.into_iter().map(|(id,)| id).collect();
+        let reminders = sqlx::query_as::<_, DueReminder>(
+            r#"
+            select reminders.id, reminders.todo_id, todos.text,
+                reminders.remind_at, reminders.attempts
+            from reminders
+            inner join todos on todos.id = reminders.todo_id
+            where reminders.id in (select value from json_each($1))
+            order by reminders.next_attempt_at asc, reminders.id asc
+            "#,
+        )
+        .bind(serde_json::to_string(&ids)?)
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(reminders)
+    }
+
+    async fn mark_sent(&self, id: i32, sent_at: DateTime<Utc>) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            update reminders
+            set sent_at = $2, next_attempt_at = null, attempts = attempts + 1, last_error = null
+            where id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(sent_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: i32,
+        error: String,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            update reminders
+            set next_attempt_at = $3, attempts = attempts + 1, last_error = $2
+            where id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .bind(retry_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// keeps reminders in a table of a `Journal`, the text of due ones is read
+/// from the todos table
+#[derive(Debug, Clone)]
+pub struct ReminderRepositoryForMemory {
+    store: Table<i32, Reminder>,
+    todos: Table<i32, TodoRow>,
+}
+
+impl ReminderRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("reminders")?,
+            todos: journal.table("todos")?,
+        })
+    }
+}
+
+#[async_trait]
+impl ReminderRepository for ReminderRepositoryForMemory {
+    async fn create(&self, todo_id: i32, payload: CreateReminder) -> anyhow::Result<Reminder> {
+        let mut store = self.store.write().await;
+        let id = store.next_id();
+        let reminder = Reminder {
+            id,
+            todo_id,
+            remind_at: payload.remind_at,
+            sent_at: None,
+            next_attempt_at: Some(payload.remind_at),
+            attempts: 0,
+            last_error: None,
+        };
+        store.insert(id, reminder.clone());
+        store.commit().await?;
+        Ok(reminder)
+    }
+
+    async fn all(&self, todo_id: i32) -> anyhow::Result<Vec<Reminder>> {
+        let store = self.store.read().await;
+        let mut reminders: Vec<Reminder> = store
+            .values()
+            .filter(|reminder| reminder.todo_id == todo_id)
+            .cloned()
+            .collect();
+        reminders.sort_by_key(|reminder| (reminder.remind_at, reminder.id));
+        Ok(reminders)
+    }
+
+    async fn delete(&self, todo_id: i32, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        store
+            .get(&id)
+            .filter(|reminder| reminder.todo_id == todo_id)
+            .context(RepositoryError::NotFound(id))?;
+        store.remove(&id);
+        store.commit().await?;
+        Ok(())
+    }
+
+    async fn claim_due(
+        &self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: i64,
+    ) -> anyhow::Result<Vec<DueReminder>> {
+        let mut store = self.store.write().await;
+        let todos = self.todos.read().await;
+        // reminders of deleted TODOs are skipped like the join of the database does
+        let mut due: Vec<(DateTime<Utc>, i32)> = store
+            .values()
+            .filter(|reminder| reminder.sent_at.is_none() && todos.contains_key(&reminder.todo_id))
+            .filter_map(|reminder| {
+                let at = reminder.next_attempt_at.filter(|at| *at <= now)?;
+                Some((at, reminder.id))
+            })
+            .collect();
+        due.sort();
+        let mut claimed = vec![];
+        for (_, id) in due.into_iter().take(limit as usize) {
+            let reminder = store.get_mut(&id).unwrap();
+            reminder.next_attempt_at = Some(now + lease);
+            claimed.push(DueReminder {
+                id,
+                todo_id: reminder.todo_id,
+                text: todos[&reminder.todo_id].text.clone(),
+                remind_at: reminder.remind_at,
+                attempts: reminder.attempts,
+            });
+        }
+        store.commit().await?;
+        Ok(claimed)
+    }
+
+    async fn mark_sent(&self, id: i32, sent_at: DateTime<Utc>) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        let reminder = store.get_mut(&id).context(RepositoryError::NotFound(id))?;
+        reminder.sent_at = Some(sent_at);
+        reminder.next_attempt_at = None;
+        reminder.attempts += 1;
+        reminder.last_error = None;
+        store.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: i32,
+        error: String,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        let reminder = store.get_mut(&id).context(RepositoryError::NotFound(id))?;
+        reminder.next_attempt_at = retry_at;
+        reminder.attempts += 1;
+        reminder.last_error = Some(error);
+        store.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::todo::{CreateTodo, TodoRepository, TodoRepositoryForDB};
+    use crate::repositories::user::test_utils::prepare_test_user;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+
+        let user_id = prepare_test_user(&pool).await;
+        let todo_repository = TodoRepositoryForDB::new(pool.clone());
+        let todo = todo_repository
+            .create(
+                1,
+                user_id,
+                CreateTodo::new("[reminder crud_scenario] todo".to_string(), vec![]),
+            )
+            .await
+            .expect("[create todo] returned Err");
+
+        let repository = ReminderRepositoryForDB::new(pool.clone());
+        // far in the past so reminders of other tests are claimed after this one
+        let remind_at = DateTime::parse_from_rfc3339("2000-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        // create
+        let reminder = repository
+            .create(todo.id, CreateReminder { remind_at })
+            .await
+            .expect("[create] returned Err");
+        assert_eq!(reminder.next_attempt_at, Some(remind_at));
+
+        // all
+        let reminders = repository.all(todo.id).await.expect("[all] returned Err");
+        assert_eq!(vec![reminder.clone()], reminders);
+
+        // claim, a second claim within the lease does not see it again
+        let now = Utc::now();
+        let due = repository
+            .claim_due(now, Duration::minutes(1), 1000)
+            .await
+            .expect("[claim_due] returned Err");
+        assert!(due.iter().any(|due| due.id == reminder.id));
+        let due = repository
+            .claim_due(now, Duration::minutes(1), 1000)
+            .await
+            .expect("[claim_due] returned Err");
+        assert!(due.iter().all(|due| due.id != reminder.id));
+
+        // failed attempt, then sent
+        repository
+            .mark_failed(reminder.id, "refused".to_string(), Some(now))
+            .await
+            .expect("[mark_failed] returned Err");
+        repository
+            .mark_sent(reminder.id, now)
+            .await
+            .expect("[mark_sent] returned Err");
+        let reminders = repository.all(todo.id).await.expect("[all] returned Err");
+        assert_eq!(2, reminders[0].attempts);
+        assert!(reminders[0].sent_at.is_some());
+        assert!(reminders[0].next_attempt_at.is_none());
+
+        // delete
+        repository
+            .delete(todo.id, reminder.id)
+            .await
+            .expect("[delete] returned Err");
+        assert!(repository.delete(todo.id, reminder.id).await.is_err());
+
+        todo_repository
+            .delete(1, user_id, todo.id)
+            .await
+            .expect("[delete todo] returned Err");
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    impl ReminderRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+
+        /// reminders are only claimed for TODOs the repository knows about
+        pub fn with_todo(self, todo_id: i32, text: &str) -> Self {
+            let mut todos = self.todos.try_write().unwrap();
+            todos.insert(
+                todo_id,
+                TodoRow {
+                    workspace_id: 1,
+                    user_id: 1,
+                    text: text.to_string(),
+                    completed: false,
+                    labels: vec![],
+                    blocked_by: vec![],
+                },
+            );
+            todos.commit_blocking().unwrap();
+            self
+        }
+    }
+}
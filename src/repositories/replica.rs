@@ -0,0 +1,77 @@
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// read-only pool `find` and `all` go to, an owner who wrote within `lag` reads
+/// from the primary until the replica has surely caught up with the write
+#[derive(Debug, Clone)]
+pub struct Replica {
+    pool: PgPool,
+    lag: Duration,
+    writes: Arc<Mutex<HashMap<(i32, i32), Instant>>>,
+}
+
+impl Replica {
+    pub fn new(pool: PgPool, lag: Duration) -> Self {
+        Self {
+            pool,
+            lag,
+            writes: Arc::default(),
+        }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// called before each write of the owner
+    pub fn written(&self, workspace_id: i32, user_id: i32) {
+        let mut writes = self.writes.lock().unwrap();
+        let now = Instant::now();
+        writes.retain(|_, at| now.duration_since(*at) < self.lag);
+        writes.insert((workspace_id, user_id), now);
+    }
+
+    /// the pool reads of the owner go to
+    pub fn reader<'a>(
+        &'a self,
+        primary: &'a PgPool,
+        workspace_id: i32,
+        user_id: i32,
+    ) -> &'a PgPool {
+        let writes = self.writes.lock().unwrap();
+        match writes.get(&(workspace_id, user_id)) {
+            Some(at) if at.elapsed() < self.lag => primary,
+            _ => &self.pool,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+
+    fn lazy(url: &str) -> PgPool {
+        PgPoolOptions::new().connect_lazy(url).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reader_test() {
+        let primary = lazy("postgres://primary/todos");
+        let replica = Replica::new(lazy("postgres://replica/todos"), Duration::from_millis(20));
+        assert!(std::ptr::eq(replica.pool(), replica.reader(&primary, 1, 1)));
+
+        replica.written(1, 1);
+        assert!(std::ptr::eq(&primary, replica.reader(&primary, 1, 1)));
+        // other owners keep reading from the replica
+        assert!(std::ptr::eq(replica.pool(), replica.reader(&primary, 1, 2)));
+        assert!(std::ptr::eq(replica.pool(), replica.reader(&primary, 2, 1)));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(std::ptr::eq(replica.pool(), replica.reader(&primary, 1, 1)));
+    }
+}
@@ -0,0 +1,76 @@
+//! reads which fail on a dropped connection or a busy database are tried again,
+//! writes never are since they may have gone through before the error
+
+use std::{future::Future, time::Duration};
+
+const ATTEMPTS: u32 = 3;
+const BACKOFF: Duration = Duration::from_millis(50);
+
+/// errors which are gone by the next attempt more often than not
+pub fn transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // connection exceptions, serialization failures, deadlocks and shutdowns
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| {
+            code.starts_with("08") || code.starts_with("57P") || code == "40001" || code == "40P01"
+        }),
+        _ => false,
+    }
+}
+
+/// runs `query` up to `ATTEMPTS` times while it fails transiently, doubling the pause
+pub async fn read<T, F, Fut>(mut query: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut attempt = 1;
+    let mut backoff = BACKOFF;
+    loop {
+        match query().await {
+            Err(e) if attempt < ATTEMPTS && e.downcast_ref().is_some_and(transient) => {
+                tracing::warn!("read failed on attempt {}, retrying: {}", attempt, e);
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::RepositoryError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn read_test() {
+        let calls = AtomicU32::new(0);
+        let flaky = || async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(sqlx::Error::PoolTimedOut.into()),
+                n => Ok(n),
+            }
+        };
+        assert_eq!(1, read(flaky).await.unwrap());
+
+        calls.store(0, Ordering::SeqCst);
+        let down = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(sqlx::Error::PoolTimedOut.into())
+        };
+        assert!(read(down).await.is_err());
+        assert_eq!(ATTEMPTS, calls.load(Ordering::SeqCst));
+
+        // an answer of the database is not retried
+        calls.store(0, Ordering::SeqCst);
+        let missing = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(RepositoryError::NotFound(1).into())
+        };
+        assert!(read(missing).await.is_err());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+}
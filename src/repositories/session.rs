@@ -0,0 +1,308 @@
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+
+/// operation to browser sessions, the cookie holds a token of which only the hash is stored
+/// create: POST -- start a session
+/// find_by_hash -- unexpired session of a cookie
+/// delete: DELETE -- end a session
+#[async_trait]
+pub trait SessionRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        csrf_token: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<Session>;
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<Session>;
+    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    /// has to be sent back with every mutating request of the session
+    pub csrf_token: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionRepositoryForDB {
+    pool: PgPool,
+}
+
+impl SessionRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SessionRepositoryForDB {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        csrf_token: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            insert into sessions (user_id, token_hash, csrf_token, expires_at)
+            values ($1, $2, $3, $4)
+            returning id, user_id, csrf_token, created_at, expires_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(csrf_token)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<Session> {
+        retry::read(|| async move {
+            let session = sqlx::query_as::<_, Session>(
+                r#"
+                select id, user_id, csrf_token, created_at, expires_at from sessions
+                where token_hash = $1 and expires_at > now()
+                "#,
+            )
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(0))?;
+
+            Ok(session)
+        })
+        .await
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from sessions where id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl SessionRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SessionRepositoryForSqlite {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        csrf_token: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            insert into sessions (user_id, token_hash, csrf_token, created_at, expires_at)
+            values ($1, $2, $3, $4, $5)
+            returning id, user_id, csrf_token, created_at, expires_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(csrf_token)
+        .bind(Utc::now())
+        .bind(expires_at)
+        // read to the end, a statement left unfinished keeps the write uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or_else(|| RepositoryError::Unexpected("no session returned".to_string()))?;
+
+        Ok(session)
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            select id, user_id, csrf_token, created_at, expires_at from sessions
+            where token_hash = $1 and expires_at > $2
+            "#,
+        )
+        .bind(token_hash)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(0))?;
+
+        Ok(session)
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from sessions where id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+}
+
+/// keeps sessions with the hash of their token in a table of a `Journal`
+#[derive(Debug, Clone)]
+pub struct SessionRepositoryForMemory {
+    store: Table<i32, (String, Session)>,
+}
+
+impl SessionRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("sessions")?,
+        })
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SessionRepositoryForMemory {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        csrf_token: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<Session> {
+        let mut store = self.store.write().await;
+        let id = store.next_id();
+        let session = Session {
+            id,
+            user_id,
+            csrf_token,
+            created_at: Utc::now(),
+            expires_at,
+        };
+        store.insert(id, (token_hash, session.clone()));
+        store.commit().await?;
+        Ok(session)
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<Session> {
+        let store = self.store.read().await;
+        let (_, session) = store
+            .values()
+            .find(|(hash, session)| hash == token_hash && session.expires_at > Utc::now())
+            .context(RepositoryError::NotFound(0))?;
+        Ok(session.clone())
+    }
+
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        store.remove(&id).context(RepositoryError::NotFound(id))?;
+        store.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::user::test_utils::prepare_test_user;
+    use chrono::Duration;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+        let user_id = prepare_test_user(&pool).await;
+        let repository = SessionRepositoryForDB::new(pool.clone());
+
+        // create
+        let session = repository
+            .create(
+                user_id,
+                "[crud_scenario] session".to_string(),
+                "csrf".to_string(),
+                Utc::now() + Duration::hours(1),
+            )
+            .await
+            .expect("[create] returned Err");
+        let expired = repository
+            .create(
+                user_id,
+                "[crud_scenario] expired session".to_string(),
+                "csrf".to_string(),
+                Utc::now(),
+            )
+            .await
+            .expect("[create] returned Err");
+
+        // find
+        let found = repository
+            .find_by_hash("[crud_scenario] session")
+            .await
+            .expect("[find_by_hash] returned Err");
+        assert_eq!(session, found);
+        assert!(repository
+            .find_by_hash("[crud_scenario] expired session")
+            .await
+            .is_err());
+
+        // delete
+        for id in [session.id, expired.id] {
+            repository.delete(id).await.expect("[delete] returned Err");
+        }
+        assert!(repository
+            .find_by_hash("[crud_scenario] session")
+            .await
+            .is_err());
+        assert!(repository.delete(session.id).await.is_err());
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    impl SessionRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+    }
+}
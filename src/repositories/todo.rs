@@ -1,74 +1,99 @@
 use axum::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{
+    pool::PoolConnection, FromRow, PgConnection, PgPool, Sqlite, SqliteConnection, SqlitePool,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
 use validator::Validate;
 
-use super::{label::Label, RepositoryError};
-
-/// operation to TODO information
+use super::{
+    attachment::Attachment,
+    comment::Comment,
+    item::{group_items, CreateItem, TodoItem, UpdateItem},
+    label::LabelRow,
+    reminder::Reminder,
+    replica::Replica,
+    retry, RepositoryError,
+};
+use crate::journal::{Journal, JournalError, Table};
+pub use ::todo::entity::{fold_entities, TodoEntity, TodoWithLabelFromRow};
+
+/// operation to TODO information, every method is limited to TODOs of `user_id` in `workspace_id`
 /// create: POST -- create new TODO
 /// find: GET -- find a TODO
 /// all: GET -- find all TODOs
 /// update: PUT,PATCH -- change a specify TODO
+/// create_item, update_item, delete_item -- change checklist items of a TODO
+/// add_blocker, remove_blocker -- change TODOs which have to be completed first
+/// find_as_of: GET -- find a TODO as it was at a point in time
 #[async_trait]
 pub trait TodoRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
     // change returned type TodoWithLabelFromRow to TodoEntity
-    async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity>;
-    async fn find(&self, id: i32) -> anyhow::Result<TodoEntity>;
-    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>>;
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
-}
-
-//add Todo Entity
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct TodoEntity {
-    pub text: String,
-    pub id: i32,
-    pub completed: bool,
-    pub labels: Vec<Label>,
-}
-
-fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
-    let mut rows = rows.iter();
-    let mut accum: Vec<TodoEntity> = vec![];
-
-    'outer: while let Some(row) = rows.next() {
-        let mut todos = accum.iter_mut();
-        while let Some(todo) = todos.next() {
-            if todo.id == row.id {
-                todo.labels.push(Label {
-                    id: row.label_id.unwrap(),
-                    name: row.label_name.clone().unwrap(),
-                });
-                continue 'outer;
-            }
-        }
-
-        let labels = if row.label_id.is_some() {
-            vec![Label {
-                id: row.label_id.unwrap(),
-                name: row.label_name.clone().unwrap(),
-            }]
-        } else {
-            vec![]
-        };
-
-        accum.push(TodoEntity {
-            text: row.text.clone(),
-            id: row.id,
-            completed: row.completed,
-            labels,
-        });
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity>;
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()>;
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem>;
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem>;
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()>;
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()>;
+    /// text, completion and labels of the TODO at `at`, only repositories
+    /// which keep the history can answer
+    async fn find_as_of(
+        &self,
+        _workspace_id: i32,
+        _user_id: i32,
+        id: i32,
+        _at: DateTime<Utc>,
+    ) -> anyhow::Result<TodoEntity> {
+        Err(RepositoryError::NoHistory(id).into())
     }
-
-    accum
-
-    /*rows.iter()
-    .fold(vec![], |mut accum: Vec<TodoEntity>, current| {
-        accum.push(TodoEntity { text: current.text.clone(), id: current.id, completed: current.completed, labels: vec![] });
-        accum
-    })*/
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, FromRow)]
@@ -78,128 +103,280 @@ pub struct TodoFromRow {
     pub completed: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
-pub struct TodoWithLabelFromRow {
-    pub text: String,
-    pub id: i32,
-    pub completed: bool,
-    pub label_id: Option<i32>,
-    pub label_name: Option<String>,
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
 pub struct CreateTodo {
     #[validate(length(min = 1, message = "Can not be empty"))]
     #[validate(length(max = 100, message = "Over text length"))]
-    text: String,
-    labels: Vec<i32>,
+    pub(super) text: String,
+    pub(super) labels: Vec<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
 pub struct UpdateTodo {
     #[validate(length(min = 1, message = "Can not be empty"))]
     #[validate(length(max = 100, message = "Over text length"))]
-    text: Option<String>,
-    completed: Option<bool>,
-    labels: Option<Vec<i32>>,
+    pub(super) text: Option<String>,
+    pub(super) completed: Option<bool>,
+    pub(super) labels: Option<Vec<i32>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct AddBlocker {
+    pub blocker_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+struct BlockerFromRow {
+    todo_id: i32,
+    blocker_id: i32,
+    completed: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct TodoRepositoryForDB {
     pool: PgPool,
+    replica: Option<Replica>,
 }
 
 impl TodoRepositoryForDB {
     pub fn new(pool: PgPool) -> Self {
-        TodoRepositoryForDB { pool }
+        TodoRepositoryForDB {
+            pool,
+            replica: None,
+        }
+    }
+
+    /// `find` and `all` read from the replica, when there is one
+    pub fn with_replica(self, replica: Option<Replica>) -> Self {
+        Self { replica, ..self }
+    }
+
+    fn reader(&self, workspace_id: i32, user_id: i32) -> &PgPool {
+        match &self.replica {
+            Some(replica) => replica.reader(&self.pool, workspace_id, user_id),
+            None => &self.pool,
+        }
+    }
+
+    pub(super) fn written(&self, workspace_id: i32, user_id: i32) {
+        if let Some(replica) = &self.replica {
+            replica.written(workspace_id, user_id);
+        }
+    }
+
+    pub(super) async fn load(
+        &self,
+        conn: &mut PgConnection,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            select todos.id, todos.text, todos.completed, labels.id as label_id, labels.name as label_name from todos
+            left outer join todo_labels tl on todos.id = tl.todo_id
+            left outer join labels on labels.id = tl.label_id
+            where todos.id = $1 and todos.user_id = $2 and todos.workspace_id = $3
+            order by labels.id asc
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut todos = fold_entities(items);
+        self.attach_items(conn, &mut todos).await?;
+        self.attach_blockers(conn, &mut todos).await?;
+        let todo = todos.first().ok_or(RepositoryError::NotFound(id))?;
+        Ok(todo.clone())
+    }
+
+    /// locks the row of the todo until the end of the transaction, so concurrent
+    /// writes of the same todo do not work from the same stale state
+    pub(super) async fn lock(
+        &self,
+        conn: &mut PgConnection,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            select id from todos
+            where id = $1 and user_id = $2 and workspace_id = $3
+            for update
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+        Ok(())
+    }
+
+    /// load checklist items of the given TODOs with one query
+    async fn attach_items(
+        &self,
+        conn: &mut PgConnection,
+        todos: &mut [TodoEntity],
+    ) -> anyhow::Result<()> {
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        let items = sqlx::query_as::<_, TodoItem>(
+            r#"
+            select * from todo_items
+            where todo_id = any($1)
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(conn)
+        .await?;
+
+        let mut grouped = group_items(items);
+        for todo in todos.iter_mut() {
+            todo.items = grouped.remove(&todo.id).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    /// load blockers of the given TODOs with one query
+    async fn attach_blockers(
+        &self,
+        conn: &mut PgConnection,
+        todos: &mut [TodoEntity],
+    ) -> anyhow::Result<()> {
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        let rows = sqlx::query_as::<_, BlockerFromRow>(
+            r#"
+            select tb.todo_id, tb.blocker_id, blockers.completed from todo_blockers tb
+            inner join todos blockers on blockers.id = tb.blocker_id
+            where tb.todo_id = any($1)
+            order by tb.blocker_id asc
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(conn)
+        .await?;
+
+        let mut grouped: HashMap<i32, Vec<BlockerFromRow>> = HashMap::new();
+        for row in rows {
+            grouped.entry(row.todo_id).or_default().push(row);
+        }
+        for todo in todos.iter_mut() {
+            let blockers = grouped.remove(&todo.id).unwrap_or_default();
+            todo.blocked = blockers.iter().any(|blocker| !blocker.completed);
+            todo.blocked_by = blockers.iter().map(|blocker| blocker.blocker_id).collect();
+        }
+        Ok(())
     }
 }
 
 #[async_trait]
 impl TodoRepository for TodoRepositoryForDB {
-    /// insert into todos (text, completed)
-    /// values ($1, false)
+    /// insert into todos (text, completed, user_id, workspace_id)
+    /// values ($1, false, $2, $3)
     /// returning *
-    async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.written(workspace_id, user_id);
+        let mut tx = self.pool.begin().await?;
         let row = sqlx::query_as::<_, TodoFromRow>(
             r#"
-            insert into todos (text, completed)
-            values ($1, false)
-            returning *;
+            insert into todos (text, completed, user_id, workspace_id)
+            values ($1, false, $2, $3)
+            returning id, text, completed;
             "#,
         )
         .bind(payload.text.clone())
-        .fetch_one(&self.pool)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_one(&mut tx)
         .await?;
-    
+
+        // labels of other users are silently skipped
         sqlx::query(
             r#"
             insert into todo_labels (todo_id, label_id)
             select $1, id
-            from unnest($2) as t(id);
+            from labels
+            where id = any($2) and user_id = $3 and workspace_id = $4;
             "#,
         )
         .bind(row.id)
         .bind(payload.labels)
-        .execute(&self.pool)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(&mut tx)
         .await?;
 
+        let todo = self.load(&mut tx, workspace_id, user_id, row.id).await?;
         tx.commit().await?;
-        
-        let todo = self.find(row.id).await?;
         Ok(todo)
     }
-      
-    async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
-        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
-            r#"
-            select todos.*, labels.id as label_id, labels.name as label_name from todos
-            left outer join todo_labels tl on todos.id = tl.todo_id
-            left outer join labels on labels.id = tl.label_id
-            where todos.id = $1
-            "#
-        )
-        .bind(id)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
 
-        let todos = fold_entities(items);
-        let todo = todos.first().ok_or(RepositoryError::NotFound(id))?;
-        Ok(todo.clone())
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity> {
+        retry::read(|| async move {
+            let mut conn = self.reader(workspace_id, user_id).acquire().await?;
+            self.load(&mut conn, workspace_id, user_id, id).await
+        })
+        .await
     }
-    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
-        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
-            r#"
-            select todos.*, labels.id as label_id, labels.name as label_name from todos
-            left outer join todo_labels tl on todos.id = tl.todo_id
-            left outer join labels on labels.id = tl.label_id
-            order by todos.id desc;
-            "#
-        )
-        .fetch_all(&self.pool)
-        .await?;
-        Ok(fold_entities(items))
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        retry::read(|| async move {
+            let mut conn = self.reader(workspace_id, user_id).acquire().await?;
+            let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+                r#"
+                select todos.id, todos.text, todos.completed, labels.id as label_id, labels.name as label_name from todos
+                left outer join todo_labels tl on todos.id = tl.todo_id
+                left outer join labels on labels.id = tl.label_id
+                where todos.user_id = $1 and todos.workspace_id = $2
+                order by todos.id desc, labels.id asc;
+                "#,
+            )
+            .bind(user_id)
+            .bind(workspace_id)
+            .fetch_all(&mut conn)
+            .await?;
+            let mut todos = fold_entities(items);
+            self.attach_items(&mut conn, &mut todos).await?;
+            self.attach_blockers(&mut conn, &mut todos).await?;
+            Ok(todos)
+        })
+        .await
     }
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
-
-        let old_todo = self.find(id).await?;
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.written(workspace_id, user_id);
+        let mut tx = self.pool.begin().await?;
+        self.lock(&mut tx, workspace_id, user_id, id).await?;
+        let old_todo = self.load(&mut tx, workspace_id, user_id, id).await?;
+        if payload.completed == Some(true) && !old_todo.completed && old_todo.blocked {
+            return Err(RepositoryError::Blocked(id).into());
+        }
         sqlx::query(
             r#"
             update todos set text=$1, completed=$2
-            where id=$3
-            returning *
+            where id=$3 and user_id=$4 and workspace_id=$5
             "#,
         )
         .bind(payload.text.unwrap_or(old_todo.text))
         .bind(payload.completed.unwrap_or(old_todo.completed))
         .bind(id)
-        .fetch_one(&self.pool)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(&mut tx)
         .await?;
 
         if let Some(labels) = payload.labels {
@@ -211,68 +388,1096 @@ impl TodoRepository for TodoRepositoryForDB {
                 "#,
             )
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut tx)
             .await?;
 
             sqlx::query(
                 r#"
                 insert into todo_labels (todo_id, label_id)
                 select $1, id
-                from unnest($2) as t(id);
-                "#
+                from labels
+                where id = any($2) and user_id = $3 and workspace_id = $4;
+                "#,
             )
             .bind(id)
             .bind(labels)
-            .execute(&self.pool)
+            .bind(user_id)
+            .bind(workspace_id)
+            .execute(&mut tx)
             .await?;
         };
 
+        let todo = self.load(&mut tx, workspace_id, user_id, id).await?;
         tx.commit().await?;
-        let todo = self.find(id).await?;
 
         Ok(todo)
     }
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        let tx = self.pool.begin().await?;
-
-        sqlx::query(
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        self.written(workspace_id, user_id);
+        // todo_labels follow by cascade
+        let result = sqlx::query(
             r#"
-            delete from todo_labels where todo_id=$1
+            delete from todos where id=$1 and user_id=$2 and workspace_id=$3
             "#,
         )
         .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
         .execute(&self.pool)
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .map_err(|e| RepositoryError::Unexpected(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem> {
+        self.written(workspace_id, user_id);
+        let item = sqlx::query_as::<_, TodoItem>(
+            r#"
+            insert into todo_items (todo_id, text, done, position)
+            select todos.id, $2, false, coalesce(
+                $3,
+                (select coalesce(max(position), 0) + 1 from todo_items where todo_id = $1)
+            )
+            from todos where todos.id = $1 and todos.user_id = $4 and todos.workspace_id = $5
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.text)
+        .bind(payload.position)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(todo_id))?;
+
+        Ok(item)
+    }
+
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem> {
+        self.written(workspace_id, user_id);
+        let item = sqlx::query_as::<_, TodoItem>(
+            r#"
+            update todo_items
+            set text = coalesce($3, text),
+                done = coalesce($4, done),
+                position = coalesce($5, position)
+            where todo_id = $1 and id = $2
+              and exists(
+                select 1 from todos
+                where todos.id = $1 and todos.user_id = $6 and todos.workspace_id = $7
+              )
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(item_id)
+        .bind(payload.text)
+        .bind(payload.done)
+        .bind(payload.position)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(item_id))?;
+
+        Ok(item)
+    }
+
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()> {
+        self.written(workspace_id, user_id);
+        let result = sqlx::query(
+            r#"
+            delete from todo_items where todo_id = $1 and id = $2
+              and exists(
+                select 1 from todos
+                where todos.id = $1 and todos.user_id = $3 and todos.workspace_id = $4
+              )
+            "#,
+        )
+        .bind(todo_id)
+        .bind(item_id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(item_id).into());
+        }
+        Ok(())
+    }
+
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        self.written(workspace_id, user_id);
+        if todo_id == blocker_id {
+            return Err(RepositoryError::Cycle(blocker_id).into());
+        }
+        let mut tx = self.pool.begin().await?;
+        // serialize dependency changes, two concurrent inserts could close a cycle
+        sqlx::query("lock table todo_blockers in share row exclusive mode")
+            .execute(&mut tx)
+            .await?;
+
+        let found = sqlx::query_as::<_, (i64,)>(
+            r#"
+            select count(*) from todos
+            where (id = $1 or id = $2) and user_id = $3 and workspace_id = $4
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_one(&mut tx)
+        .await?;
+        if found.0 < 2 {
+            return Err(RepositoryError::NotFound(blocker_id).into());
+        }
+
+        // the new edge closes a cycle when the todo already blocks the blocker
+        let (cycle,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            with recursive chain(id) as (
+                select blocker_id from todo_blockers where todo_id = $2
+                union
+                select tb.blocker_id from todo_blockers tb
+                inner join chain on tb.todo_id = chain.id
+            )
+            select exists(select 1 from chain where id = $1)
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .fetch_one(&mut tx)
+        .await?;
+        if cycle {
+            return Err(RepositoryError::Cycle(blocker_id).into());
+        }
+
+        sqlx::query(
+            r#"
+            insert into todo_blockers (todo_id, blocker_id)
+            values ($1, $2)
+            on conflict do nothing
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .execute(&mut tx)
+        .await?;
+
+        let todo = self.load(&mut tx, workspace_id, user_id, todo_id).await?;
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()> {
+        self.written(workspace_id, user_id);
+        let result = sqlx::query(
+            r#"
+            delete from todo_blockers tb
+            using todos
+            where tb.todo_id = $1 and tb.blocker_id = $2
+              and todos.id = tb.todo_id and todos.user_id = $3 and todos.workspace_id = $4
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(blocker_id).into());
+        }
+        Ok(())
+    }
+}
+
+/// the same operations on sqlite, which has no arrays, so id lists are bound
+/// as JSON and read with `json_each`
+#[derive(Debug, Clone)]
+pub struct TodoRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl TodoRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        TodoRepositoryForSqlite { pool }
+    }
+
+    async fn load(
+        &self,
+        conn: &mut SqliteConnection,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            select todos.id, todos.text, todos.completed, labels.id as label_id, labels.name as label_name from todos
+            left outer join todo_labels tl on todos.id = tl.todo_id
+            left outer join labels on labels.id = tl.label_id
+            where todos.id = $1 and todos.user_id = $2 and todos.workspace_id = $3
+            order by labels.id asc
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut todos = fold_entities(items);
+        self.attach_items(conn, &mut todos).await?;
+        self.attach_blockers(conn, &mut todos).await?;
+        let todo = todos.first().ok_or(RepositoryError::NotFound(id))?;
+        Ok(todo.clone())
+    }
+
+    /// load checklist items of the given TODOs with one query
+    async fn attach_items(
+        &self,
+        conn: &mut SqliteConnection,
+        todos: &mut [TodoEntity],
+    ) -> anyhow::Result<()> {
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        let items = sqlx::query_as::<_, TodoItem>(
+            r#"
+            select * from todo_items
+            where todo_id in (select value from json_each($1))
+            "#,
+        )
+        .bind(serde_json::to_string(&ids)?)
+        .fetch_all(conn)
+        .await?;
+
+        let mut grouped = group_items(items);
+        for todo in todos.iter_mut() {
+            todo.items = grouped.remove(&todo.id).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    /// load blockers of the given TODOs with one query
+    async fn attach_blockers(
+        &self,
+        conn: &mut SqliteConnection,
+        todos: &mut [TodoEntity],
+    ) -> anyhow::Result<()> {
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        let rows = sqlx::query_as::<_, BlockerFromRow>(
+            r#"
+            select tb.todo_id, tb.blocker_id, blockers.completed from todo_blockers tb
+            inner join todos blockers on blockers.id = tb.blocker_id
+            where tb.todo_id in (select value from json_each($1))
+            order by tb.blocker_id asc
+            "#,
+        )
+        .bind(serde_json::to_string(&ids)?)
+        .fetch_all(conn)
+        .await?;
+
+        let mut grouped: HashMap<i32, Vec<BlockerFromRow>> = HashMap::new();
+        for row in rows {
+            grouped.entry(row.todo_id).or_default().push(row);
+        }
+        for todo in todos.iter_mut() {
+            let blockers = grouped.remove(&todo.id).unwrap_or_default();
+            todo.blocked = blockers.iter().any(|blocker| !blocker.completed);
+            todo.blocked_by = blockers.iter().map(|blocker| blocker.blocker_id).collect();
+        }
+        Ok(())
+    }
+
+    /// link the labels of `user_id` among `labels`, labels of other users are silently skipped
+    async fn link_labels(
+        &self,
+        conn: &mut SqliteConnection,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        labels: Vec<i32>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into todo_labels (todo_id, label_id)
+            select $1, id
+            from labels
+            where id in (select value from json_each($2)) and user_id = $3 and workspace_id = $4;
+            "#,
+        )
+        .bind(id)
+        .bind(serde_json::to_string(&labels)?)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// a sqlite transaction opened with `begin immediate`, which takes the write lock
+/// before the first read, sqlx only opens deferred ones. dropped without `commit`
+/// the connection is closed instead of going back to the pool, which rolls it back
+struct ImmediateTransaction {
+    conn: Option<PoolConnection<Sqlite>>,
+}
+
+impl ImmediateTransaction {
+    async fn begin(pool: &SqlitePool) -> anyhow::Result<Self> {
+        let mut conn = pool.acquire().await?;
+        sqlx::query("begin immediate").execute(&mut conn).await?;
+        Ok(Self { conn: Some(conn) })
+    }
+
+    async fn commit(mut self) -> anyhow::Result<()> {
+        sqlx::query("commit").execute(&mut *self).await?;
+        self.conn.take();
+        Ok(())
+    }
+}
+
+impl Deref for ImmediateTransaction {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &SqliteConnection {
+        self.conn.as_ref().expect("transaction is finished")
+    }
+}
+
+impl DerefMut for ImmediateTransaction {
+    fn deref_mut(&mut self) -> &mut SqliteConnection {
+        self.conn.as_mut().expect("transaction is finished")
+    }
+}
+
+impl Drop for ImmediateTransaction {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            drop(conn.detach());
+        }
+    }
+}
+
+#[async_trait]
+impl TodoRepository for TodoRepositoryForSqlite {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query_as::<_, TodoFromRow>(
+            r#"
+            insert into todos (text, completed, user_id, workspace_id)
+            values ($1, false, $2, $3)
+            returning id, text, completed;
+            "#,
+        )
+        .bind(payload.text.clone())
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_one(&mut tx)
+        .await?;
+        self.link_labels(&mut tx, workspace_id, user_id, row.id, payload.labels)
+            .await?;
+        tx.commit().await?;
+
+        let todo = self.find(workspace_id, user_id, row.id).await?;
+        Ok(todo)
+    }
+
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity> {
+        let mut conn = self.pool.acquire().await?;
+        self.load(&mut conn, workspace_id, user_id, id).await
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        let mut conn = self.pool.acquire().await?;
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            select todos.id, todos.text, todos.completed, labels.id as label_id, labels.name as label_name from todos
+            left outer join todo_labels tl on todos.id = tl.todo_id
+            left outer join labels on labels.id = tl.label_id
+            where todos.user_id = $1 and todos.workspace_id = $2
+            order by todos.id desc, labels.id asc;
+            "#,
+        )
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_all(&mut conn)
+        .await?;
+        let mut todos = fold_entities(items);
+        self.attach_items(&mut conn, &mut todos).await?;
+        self.attach_blockers(&mut conn, &mut todos).await?;
+        Ok(todos)
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        // the write lock is taken before the blockers are read, a blocker reopened
+        // concurrently waits for this update instead of slipping past the check
+        let mut tx = ImmediateTransaction::begin(&self.pool).await?;
+        let old_todo = self.load(&mut tx, workspace_id, user_id, id).await?;
+        if payload.completed == Some(true) && !old_todo.completed && old_todo.blocked {
+            return Err(RepositoryError::Blocked(id).into());
+        }
 
         sqlx::query(
             r#"
-            delete from todos where id=$1
+            update todos set text=$1, completed=$2
+            where id=$3 and user_id=$4 and workspace_id=$5
             "#,
         )
+        .bind(payload.text.unwrap_or(old_todo.text))
+        .bind(payload.completed.unwrap_or(old_todo.completed))
         .bind(id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some(labels) = payload.labels {
+            sqlx::query("delete from todo_labels where todo_id=$1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            self.link_labels(&mut tx, workspace_id, user_id, id, labels)
+                .await?;
+        }
+        let todo = self.load(&mut tx, workspace_id, user_id, id).await?;
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        // todo_labels follow by cascade
+        let result =
+            sqlx::query("delete from todos where id=$1 and user_id=$2 and workspace_id=$3")
+                .bind(id)
+                .bind(user_id)
+                .bind(workspace_id)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| RepositoryError::Unexpected(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let item = sqlx::query_as::<_, TodoItem>(
+            r#"
+            insert into todo_items (todo_id, text, done, position)
+            select todos.id, $2, false, coalesce(
+                $3,
+                (select coalesce(max(position), 0) + 1 from todo_items where todo_id = $1)
+            )
+            from todos where todos.id = $1 and todos.user_id = $4 and todos.workspace_id = $5
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.text)
+        .bind(payload.position)
+        .bind(user_id)
+        .bind(workspace_id)
+        // read to the end, a statement left unfinished keeps the insert uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::NotFound(todo_id))?;
+
+        Ok(item)
+    }
+
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let item = sqlx::query_as::<_, TodoItem>(
+            r#"
+            update todo_items
+            set text = coalesce($3, text),
+                done = coalesce($4, done),
+                position = coalesce($5, position)
+            where todo_id = $1 and id = $2
+              and exists(
+                select 1 from todos
+                where todos.id = $1 and todos.user_id = $6 and todos.workspace_id = $7
+              )
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(item_id)
+        .bind(payload.text)
+        .bind(payload.done)
+        .bind(payload.position)
+        .bind(user_id)
+        .bind(workspace_id)
+        // read to the end, see create_item
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::NotFound(item_id))?;
+
+        Ok(item)
+    }
+
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from todo_items where todo_id = $1 and id = $2
+              and exists(
+                select 1 from todos
+                where todos.id = $1 and todos.user_id = $3 and todos.workspace_id = $4
+              )
+            "#,
+        )
+        .bind(todo_id)
+        .bind(item_id)
+        .bind(user_id)
+        .bind(workspace_id)
         .execute(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(item_id).into());
+        }
+        Ok(())
+    }
+
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        if todo_id == blocker_id {
+            return Err(RepositoryError::Cycle(blocker_id).into());
+        }
+        // sqlite transactions are serializable, a concurrent insert makes this one
+        // fail instead of closing a cycle
+        let mut tx = self.pool.begin().await?;
+
+        let found = sqlx::query_as::<_, (i64,)>(
+            r#"
+            select count(*) from todos
+            where (id = $1 or id = $2) and user_id = $3 and workspace_id = $4
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_one(&mut tx)
+        .await?;
+        if found.0 < 2 {
+            return Err(RepositoryError::NotFound(blocker_id).into());
+        }
+
+        // the new edge closes a cycle when the todo already blocks the blocker
+        let (cycle,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            with recursive chain(id) as (
+                select blocker_id from todo_blockers where todo_id = $2
+                union
+                select tb.blocker_id from todo_blockers tb
+                inner join chain on tb.todo_id = chain.id
+            )
+            select exists(select 1 from chain where id = $1)
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .fetch_one(&mut tx)
+        .await?;
+        if cycle {
+            return Err(RepositoryError::Cycle(blocker_id).into());
+        }
+
+        sqlx::query(
+            r#"
+            insert into todo_blockers (todo_id, blocker_id)
+            values ($1, $2)
+            on conflict do nothing
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        let todo = self.find(workspace_id, user_id, todo_id).await?;
+        Ok(todo)
+    }
+
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from todo_blockers
+            where todo_id = $1 and blocker_id = $2
+              and exists(
+                select 1 from todos
+                where todos.id = $1 and todos.user_id = $3 and todos.workspace_id = $4
+              )
+            "#,
+        )
+        .bind(todo_id)
+        .bind(blocker_id)
+        .bind(user_id)
+        .bind(workspace_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(blocker_id).into());
+        }
+        Ok(())
+    }
+}
+
+/// a TODO as the memory backend keeps it, labels, items and `blocked` are
+/// looked up when it is read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TodoRow {
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub text: String,
+    pub completed: bool,
+    pub labels: Vec<i32>,
+    pub blocked_by: Vec<i32>,
+}
+
+/// keeps TODOs in tables of a `Journal`, tables are always locked in the order
+/// todos, todo_items, labels
+#[derive(Debug, Clone)]
+pub struct TodoRepositoryForMemory {
+    store: Table<i32, TodoRow>,
+    items: Table<i32, TodoItem>,
+    labels: Table<i32, LabelRow>,
+    comments: Table<i32, Comment>,
+    attachments: Table<i32, Attachment>,
+    reminders: Table<i32, Reminder>,
+}
+
+impl TodoRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("todos")?,
+            items: journal.table("todo_items")?,
+            labels: journal.table("labels")?,
+            comments: journal.table("comments")?,
+            attachments: journal.table("attachments")?,
+            reminders: journal.table("reminders")?,
+        })
+    }
+
+    fn owned(
+        store: &BTreeMap<i32, TodoRow>,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+    ) -> Result<&TodoRow, RepositoryError> {
+        store
+            .get(&id)
+            .filter(|todo| todo.workspace_id == workspace_id && todo.user_id == user_id)
+            .ok_or(RepositoryError::NotFound(id))
+    }
+
+    async fn entity(&self, store: &BTreeMap<i32, TodoRow>, id: i32, todo: &TodoRow) -> TodoEntity {
+        let mut items: Vec<TodoItem> = self
+            .items
+            .read()
+            .await
+            .values()
+            .filter(|item| item.todo_id == id)
+            .cloned()
+            .collect();
+        items.sort_by_key(|item| (item.position, item.id));
+        let labels = self.labels.read().await;
+        TodoEntity {
+            id,
+            text: todo.text.clone(),
+            completed: todo.completed,
+            labels: todo
+                .labels
+                .iter()
+                .filter_map(|label_id| labels.get(label_id))
+                .map(|(_, label)| label.clone())
+                .collect(),
+            items,
+            blocked: todo.blocked_by.iter().any(|blocker_id| {
+                store
+                    .get(blocker_id)
+                    .is_some_and(|blocker| !blocker.completed)
+            }),
+            blocked_by: todo.blocked_by.clone(),
+        }
+    }
+
+    /// labels of other users are silently skipped
+    async fn resolve_labels(&self, workspace_id: i32, user_id: i32, mut ids: Vec<i32>) -> Vec<i32> {
+        let labels = self.labels.read().await;
+        ids.sort();
+        ids.dedup();
+        ids.retain(|id| {
+            labels
+                .get(id)
+                .is_some_and(|(owner, _)| *owner == (workspace_id, user_id))
+        });
+        ids
+    }
+
+    /// whether `to` can be reached from `from` by following blockers
+    fn reaches(store: &BTreeMap<i32, TodoRow>, from: i32, to: i32) -> bool {
+        let mut stack = vec![from];
+        let mut visited = vec![];
+        while let Some(id) = stack.pop() {
+            if id == to {
+                return true;
+            }
+            if visited.contains(&id) {
+                continue;
+            }
+            visited.push(id);
+            if let Some(todo) = store.get(&id) {
+                stack.extend(todo.blocked_by.iter().copied());
+            }
+        }
+        false
+    }
+}
+
+async fn remove_orphans<V: Serialize + Clone>(
+    table: &Table<i32, V>,
+    orphaned: impl Fn(&V) -> bool,
+) -> anyhow::Result<()> {
+    let mut rows = table.write().await;
+    let orphans: Vec<i32> = rows
+        .iter()
+        .filter(|(_, row)| orphaned(row))
+        .map(|(id, _)| *id)
+        .collect();
+    for id in orphans {
+        rows.remove(&id);
+    }
+    rows.commit().await?;
+    Ok(())
+}
+
+#[async_trait]
+impl TodoRepository for TodoRepositoryForMemory {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let labels = self
+            .resolve_labels(workspace_id, user_id, payload.labels)
+            .await;
+        let mut store = self.store.write().await;
+        let id = store.next_id();
+        let row = TodoRow {
+            workspace_id,
+            user_id,
+            text: payload.text,
+            completed: false,
+            labels,
+            blocked_by: vec![],
+        };
+        store.insert(id, row.clone());
+        let todo = self.entity(&store, id, &row).await;
+        store.commit().await?;
+        Ok(todo)
+    }
+
+    async fn find(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<TodoEntity> {
+        let store = self.store.read().await;
+        let todo = Self::owned(&store, workspace_id, user_id, id)?;
+        Ok(self.entity(&store, id, todo).await)
+    }
+
+    async fn all(&self, workspace_id: i32, user_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        let store = self.store.read().await;
+        let mut todos = vec![];
+        for (id, todo) in store.iter().rev() {
+            if todo.workspace_id == workspace_id && todo.user_id == user_id {
+                todos.push(self.entity(&store, *id, todo).await);
+            }
+        }
+        Ok(todos)
+    }
+
+    async fn update(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let labels = match payload.labels {
+            Some(labels) => Some(self.resolve_labels(workspace_id, user_id, labels).await),
+            None => None,
+        };
+        let mut store = self.store.write().await;
+        let todo = Self::owned(&store, workspace_id, user_id, id)?.clone();
+        let blocked = todo.blocked_by.iter().any(|blocker_id| {
+            store
+                .get(blocker_id)
+                .is_some_and(|blocker| !blocker.completed)
+        });
+        if payload.completed == Some(true) && !todo.completed && blocked {
+            return Err(RepositoryError::Blocked(id).into());
+        }
+        let row = TodoRow {
+            text: payload.text.unwrap_or(todo.text),
+            completed: payload.completed.unwrap_or(todo.completed),
+            labels: labels.unwrap_or(todo.labels),
+            ..todo
+        };
+        store.insert(id, row.clone());
+        let todo = self.entity(&store, id, &row).await;
+        store.commit().await?;
+        Ok(todo)
+    }
+
+    async fn delete(&self, workspace_id: i32, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        Self::owned(&store, workspace_id, user_id, id)?;
+        store.remove(&id);
+        let blocked: Vec<i32> = store
+            .iter()
+            .filter(|(_, todo)| todo.blocked_by.contains(&id))
+            .map(|(blocked_id, _)| *blocked_id)
+            .collect();
+        for blocked_id in blocked {
+            if let Some(todo) = store.get_mut(&blocked_id) {
+                todo.blocked_by.retain(|blocker_id| *blocker_id != id);
+            }
+        }
+        store.commit().await?;
+
+        // what the database removes by cascade, ids are never reused, so rows
+        // left behind by a crash here can not show up on another TODO
+        remove_orphans(&self.items, |item| item.todo_id == id).await?;
+        remove_orphans(&self.comments, |comment| comment.todo_id == id).await?;
+        remove_orphans(&self.attachments, |attachment| attachment.todo_id == id).await?;
+        remove_orphans(&self.reminders, |reminder| reminder.todo_id == id).await?;
+        Ok(())
+    }
+
+    async fn create_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        payload: CreateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let store = self.store.read().await;
+        Self::owned(&store, workspace_id, user_id, todo_id)?;
+        let mut items = self.items.write().await;
+        let position = payload.position.unwrap_or_else(|| {
+            items
+                .values()
+                .filter(|item| item.todo_id == todo_id)
+                .map(|item| item.position)
+                .max()
+                .unwrap_or(0)
+                + 1
+        });
+        let id = items.next_id();
+        let item = TodoItem {
+            id,
+            todo_id,
+            text: payload.text,
+            done: false,
+            position,
+        };
+        items.insert(id, item.clone());
+        items.commit().await?;
+        Ok(item)
+    }
+
+    async fn update_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+        payload: UpdateItem,
+    ) -> anyhow::Result<TodoItem> {
+        let store = self.store.read().await;
+        Self::owned(&store, workspace_id, user_id, todo_id)
+            .map_err(|_| RepositoryError::NotFound(item_id))?;
+        let mut items = self.items.write().await;
+        let item = items
+            .get_mut(&item_id)
+            .filter(|item| item.todo_id == todo_id)
+            .ok_or(RepositoryError::NotFound(item_id))?;
+        if let Some(text) = payload.text {
+            item.text = text;
+        }
+        if let Some(done) = payload.done {
+            item.done = done;
+        }
+        if let Some(position) = payload.position {
+            item.position = position;
+        }
+        let item = item.clone();
+        items.commit().await?;
+        Ok(item)
+    }
 
-        tx.commit().await?;
+    async fn delete_item(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        item_id: i32,
+    ) -> anyhow::Result<()> {
+        let store = self.store.read().await;
+        Self::owned(&store, workspace_id, user_id, todo_id)
+            .map_err(|_| RepositoryError::NotFound(item_id))?;
+        let mut items = self.items.write().await;
+        items
+            .get(&item_id)
+            .filter(|item| item.todo_id == todo_id)
+            .ok_or(RepositoryError::NotFound(item_id))?;
+        items.remove(&item_id);
+        items.commit().await?;
+        Ok(())
+    }
+
+    async fn add_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        if todo_id == blocker_id {
+            return Err(RepositoryError::Cycle(blocker_id).into());
+        }
+        let mut store = self.store.write().await;
+        if Self::owned(&store, workspace_id, user_id, todo_id).is_err()
+            || Self::owned(&store, workspace_id, user_id, blocker_id).is_err()
+        {
+            return Err(RepositoryError::NotFound(blocker_id).into());
+        }
+        if Self::reaches(&store, blocker_id, todo_id) {
+            return Err(RepositoryError::Cycle(blocker_id).into());
+        }
+        let row = store.get_mut(&todo_id).unwrap();
+        if !row.blocked_by.contains(&blocker_id) {
+            row.blocked_by.push(blocker_id);
+            row.blocked_by.sort();
+        }
+        let row = row.clone();
+        let todo = self.entity(&store, todo_id, &row).await;
+        store.commit().await?;
+        Ok(todo)
+    }
 
+    async fn remove_blocker(
+        &self,
+        workspace_id: i32,
+        user_id: i32,
+        todo_id: i32,
+        blocker_id: i32,
+    ) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        Self::owned(&store, workspace_id, user_id, todo_id)
+            .map_err(|_| RepositoryError::NotFound(blocker_id))?;
+        let row = store.get_mut(&todo_id).unwrap();
+        let index = row
+            .blocked_by
+            .iter()
+            .position(|id| *id == blocker_id)
+            .ok_or(RepositoryError::NotFound(blocker_id))?;
+        row.blocked_by.remove(index);
+        store.commit().await?;
         Ok(())
     }
 }
 
-#[cfg(feature = "database-test")]
+#[cfg(test)]
 mod test {
     use super::*;
-    use dotenv::dotenv;
-    use sqlx::PgPool;
-    use std::env;
+    use crate::repositories::label::Label;
 
     #[test]
     fn fold_entities_test() {
@@ -285,6 +1490,7 @@ mod test {
             name: String::from("label 2"),
         };
 
+        // rows of a todo are not necessarily next to each other
         let rows = vec![
             TodoWithLabelFromRow {
                 id: 1,
@@ -293,13 +1499,6 @@ mod test {
                 label_id: Some(label_1.id),
                 label_name: Some(label_1.name.clone()),
             },
-            TodoWithLabelFromRow {
-                id: 1,
-                text: String::from("todo 1"),
-                completed: false,
-                label_id: Some(label_2.id),
-                label_name: Some(label_2.name.clone()),
-            },
             TodoWithLabelFromRow {
                 id: 2,
                 text: String::from("todo 2"),
@@ -307,6 +1506,13 @@ mod test {
                 label_id: Some(label_1.id),
                 label_name: Some(label_1.name.clone()),
             },
+            TodoWithLabelFromRow {
+                id: 1,
+                text: String::from("todo 1"),
+                completed: false,
+                label_id: Some(label_2.id),
+                label_name: Some(label_2.name.clone()),
+            },
         ];
 
         let res = fold_entities(rows);
@@ -318,63 +1524,42 @@ mod test {
                     text: String::from("todo 1"),
                     completed: false,
                     labels: vec![label_1.clone(), label_2.clone()],
+                    items: vec![],
+                    blocked: false,
+                    blocked_by: vec![],
                 },
                 TodoEntity {
                     id: 2,
                     text: String::from("todo 2"),
                     completed: false,
                     labels: vec![label_1.clone()],
+                    items: vec![],
+                    blocked: false,
+                    blocked_by: vec![],
                 },
             ]
         );
     }
 
-    #[cfg(feature = "database-test")]
-    #[tokio::test]
-    async fn crud_scenario() {
-        dotenv().ok();
-        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
-        let pool = PgPool::connect(database_url)
-            .await
-            .expect(&format!("fail connect database, url is [{}]", database_url));
-
-        // label data prepare
-        let label_name = String::from("test label");
-        let optional_label = sqlx::query_as::<_, Label>(
-            r#"
-                select * from labels where name=$1
-            "#,
-        )
-        .bind(label_name.clone())
-        .fetch_optional(&pool)
-        .await
-        .expect("Failed to prepare label data.");
-        let label_1 = if let Some(label) = optional_label {
-            label
-        } else {
-            let label = sqlx::query_as::<_, Label>(
-                r#"
-                insert into labels (name)
-                values ($1)
-                returning *
-            "#,
-            )
-            .bind(label_name)
-            .fetch_one(&pool)
-            .await
-            .expect("Failed to insert label data.");
-            label
-        };
-
-        let repository = TodoRepositoryForDB::new(pool.clone());
+    /// the same scenarios run against every backend, `label_1` belongs to
+    /// `user_id` in the default workspace
+    pub async fn crud_scenario<R: TodoRepository>(
+        repository: &R,
+        user_id: i32,
+        label_1: Label,
+    ) -> i32 {
         let todo_text = "[crud_scenario] text";
 
         // create
         let created = repository
-            .create(CreateTodo {
-                text: todo_text.to_string(),
-                labels: vec![label_1.id],
-            })
+            .create(
+                1,
+                user_id,
+                CreateTodo {
+                    text: todo_text.to_string(),
+                    labels: vec![label_1.id],
+                },
+            )
             .await
             .expect("[create] returned Err");
         assert_eq!(created.text, todo_text);
@@ -383,20 +1568,27 @@ mod test {
 
         // find
         let found = repository
-            .find(created.id)
+            .find(1, user_id, created.id)
             .await
             .expect("[find] return Err");
         assert_eq!(created, found);
+        assert!(repository.find(1, user_id + 1, created.id).await.is_err());
+        assert!(repository.find(2, user_id, created.id).await.is_err());
 
         // all
-        let founds = repository.all().await.expect("[all find] returned Err");
-        let todo = founds.first().unwrap();
+        let founds = repository
+            .all(1, user_id)
+            .await
+            .expect("[all find] returned Err");
+        let todo = founds.iter().find(|todo| todo.id == created.id).unwrap();
         assert_eq!(created, *todo);
 
         // update
         let updated_text = "[crud_scenario] updated text";
         let updated = repository
             .update(
+                1,
+                user_id,
                 created.id,
                 UpdateTodo {
                     text: Some(updated_text.to_string()),
@@ -410,152 +1602,454 @@ mod test {
         assert_ne!(todo_text, updated_text);
         assert!(todo.labels.len() == 1);
 
+        // checklist items
+        let first = repository
+            .create_item(
+                1,
+                user_id,
+                created.id,
+                CreateItem {
+                    text: "[crud_scenario] first item".to_string(),
+                    position: None,
+                },
+            )
+            .await
+            .expect("[create_item] returned Err");
+        let second = repository
+            .create_item(
+                1,
+                user_id,
+                created.id,
+                CreateItem {
+                    text: "[crud_scenario] second item".to_string(),
+                    position: None,
+                },
+            )
+            .await
+            .expect("[create_item] returned Err");
+        assert_eq!(first.position + 1, second.position);
+        let moved = repository
+            .update_item(
+                1,
+                user_id,
+                created.id,
+                second.id,
+                UpdateItem {
+                    text: None,
+                    done: Some(true),
+                    position: Some(0),
+                },
+            )
+            .await
+            .expect("[update_item] returned Err");
+        assert!(moved.done);
+        let found = repository
+            .find(1, user_id, created.id)
+            .await
+            .expect("[find] return Err");
+        assert_eq!(found.items, vec![moved, first.clone()]);
+        repository
+            .delete_item(1, user_id, created.id, first.id)
+            .await
+            .expect("[delete_item] returned Err");
+        assert!(repository
+            .delete_item(1, user_id, created.id, first.id)
+            .await
+            .is_err());
+
         // delete
-        let _ = repository
-            .delete(created.id)
+        repository
+            .delete(1, user_id, created.id)
             .await
             .expect("[delete] returned Err");
-        let res = repository.find(created.id).await;
+        let res = repository.find(1, user_id, created.id).await;
         assert!(res.is_err());
 
-        let todo_rows = sqlx::query(
-            r#"
-                select * from todos where id=$1
-            "#,
-        )
-        .bind(created.id)
-        .fetch_all(&pool)
-        .await
-        .expect("[dekete] todo_labels fetch error");
-        assert!(todo_rows.len() == 0);
-
-        let rows = sqlx::query(
-            r#"
-                select * from todo_labels where todo_id=$1
-            "#,
-        )
-        .bind(todo.id)
-        .fetch_all(&pool)
-        .await
-        .expect("[delete] todo_labels fetch error");
-        assert!(rows.len() == 0);
+        created.id
     }
-}
-
-#[cfg(test)]
-pub mod test_utils {
-    use super::*;
-    use anyhow::Context;
-    use axum::async_trait;
-    use std::{
-        collections::HashMap,
-        sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    };
-
-    impl TodoEntity {
-        pub fn new(id: i32, text: String, labels: Vec<Label>) -> Self {
-            Self {
-                id,
-                text,
-                completed: false,
-                labels,
 
-            }
+    /// `label_id` belongs to `other_user_id` in `other_workspace_id`
+    pub async fn workspace_scenario<R: TodoRepository>(
+        repository: &R,
+        user_id: i32,
+        other_workspace_id: i32,
+        other_user_id: i32,
+        label_id: i32,
+    ) {
+        // labels of another workspace are not linked
+        let todo = repository
+            .create(
+                1,
+                user_id,
+                CreateTodo::new("[workspace_scenario] todo".to_string(), vec![label_id]),
+            )
+            .await
+            .expect("[create] returned Err");
+        assert!(todo.labels.is_empty());
+
+        // the same ids in another workspace reach nothing
+        for (workspace_id, user_id) in [
+            (other_workspace_id, user_id),
+            (other_workspace_id, other_user_id),
+        ] {
+            assert!(repository
+                .find(workspace_id, user_id, todo.id)
+                .await
+                .is_err());
+            assert!(repository
+                .all(workspace_id, user_id)
+                .await
+                .expect("[all] returned Err")
+                .iter()
+                .all(|found| found.id != todo.id));
+            assert!(repository
+                .update(
+                    workspace_id,
+                    user_id,
+                    todo.id,
+                    UpdateTodo {
+                        text: Some("[workspace_scenario] taken over".to_string()),
+                        completed: None,
+                        labels: None,
+                    },
+                )
+                .await
+                .is_err());
+            assert!(repository
+                .create_item(
+                    workspace_id,
+                    user_id,
+                    todo.id,
+                    CreateItem {
+                        text: "[workspace_scenario] item".to_string(),
+                        position: None,
+                    },
+                )
+                .await
+                .is_err());
+            assert!(repository
+                .delete(workspace_id, user_id, todo.id)
+                .await
+                .is_err());
         }
+
+        repository
+            .delete(1, user_id, todo.id)
+            .await
+            .expect("[delete] returned Err");
     }
 
-    impl CreateTodo {
-        pub fn new(text: String, labels: Vec<i32>) -> Self {
-            Self { text, labels }
+    pub async fn blocker_scenario<R: TodoRepository>(repository: &R, user_id: i32) {
+        let mut todos = vec![];
+        for text in ["first", "second", "third"] {
+            let todo = repository
+                .create(
+                    1,
+                    user_id,
+                    CreateTodo::new(format!("[blocker_scenario] {}", text), vec![]),
+                )
+                .await
+                .expect("[create] returned Err");
+            todos.push(todo);
         }
-    }
+        let (first, second, third) = (todos[0].id, todos[1].id, todos[2].id);
+
+        // first <- second <- third
+        let blocked = repository
+            .add_blocker(1, user_id, first, second)
+            .await
+            .expect("[add_blocker] returned Err");
+        assert!(blocked.blocked);
+        assert_eq!(vec![second], blocked.blocked_by);
+        repository
+            .add_blocker(1, user_id, second, third)
+            .await
+            .expect("[add_blocker] returned Err");
+
+        // closing the loop is rejected
+        let err = repository
+            .add_blocker(1, user_id, third, first)
+            .await
+            .expect_err("[add_blocker] accepted a cycle");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Cycle(_))
+        ));
+        assert!(repository
+            .add_blocker(1, user_id, first, first)
+            .await
+            .is_err());
 
-    type TodoDatas = HashMap<i32, TodoEntity>;
+        // completing needs open blockers to be done first
+        let complete = UpdateTodo {
+            text: None,
+            completed: Some(true),
+            labels: None,
+        };
+        let err = repository
+            .update(1, user_id, first, complete.clone())
+            .await
+            .expect_err("[update] completed a blocked todo");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Blocked(_))
+        ));
+        repository
+            .remove_blocker(1, user_id, second, third)
+            .await
+            .expect("[remove_blocker] returned Err");
+        repository
+            .update(1, user_id, second, complete)
+            .await
+            .expect("[update] returned Err");
+        let unblocked = repository
+            .find(1, user_id, first)
+            .await
+            .expect("[find] returned Err");
+        assert!(!unblocked.blocked);
 
-    #[derive(Debug, Clone)]
-    pub struct TodoRepositoryForMemory {
-        store: Arc<RwLock<TodoDatas>>,
-        labels: Vec<Label>,
+        for todo in todos {
+            repository
+                .delete(1, user_id, todo.id)
+                .await
+                .expect("[delete] returned Err");
+        }
     }
 
-    impl TodoRepositoryForMemory {
-        pub fn new(labels: Vec<Label>) -> Self {
-            TodoRepositoryForMemory {
-                store: Arc::default(),
-                labels,
-            }
+    #[cfg(feature = "database-test")]
+    mod postgres {
+        use super::*;
+        use crate::repositories::{
+            user::test_utils::{prepare_test_user, prepare_workspace_test_user},
+            workspace::test_utils::prepare_test_workspace,
+        };
+        use dotenv::dotenv;
+        use sqlx::PgPool;
+        use std::env;
+
+        async fn connect() -> PgPool {
+            dotenv().ok();
+            let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+            PgPool::connect(database_url)
+                .await
+                .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url))
         }
 
-        fn write_store_ref(&self) -> RwLockWriteGuard<TodoDatas> {
-            self.store.write().unwrap()
+        #[tokio::test]
+        async fn crud_scenario() {
+            let pool = connect().await;
+            let user_id = prepare_test_user(&pool).await;
+
+            // label data prepare
+            let label_name = String::from("test label");
+            let optional_label = sqlx::query_as::<_, Label>(
+                r#"
+                    select * from labels where name=$1 and user_id=$2 and workspace_id=1
+                "#,
+            )
+            .bind(label_name.clone())
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("Failed to prepare label data.");
+            let label_1 = if let Some(label) = optional_label {
+                label
+            } else {
+                let label = sqlx::query_as::<_, Label>(
+                    r#"
+                    insert into labels (name, user_id, workspace_id)
+                    values ($1, $2, 1)
+                    returning *
+                "#,
+                )
+                .bind(label_name)
+                .bind(user_id)
+                .fetch_one(&pool)
+                .await
+                .expect("Failed to insert label data.");
+                label
+            };
+
+            let id =
+                super::crud_scenario(&TodoRepositoryForDB::new(pool.clone()), user_id, label_1)
+                    .await;
+
+            let todo_rows = sqlx::query(
+                r#"
+                    select * from todos where id=$1
+                "#,
+            )
+            .bind(id)
+            .fetch_all(&pool)
+            .await
+            .expect("[dekete] todo_labels fetch error");
+            assert!(todo_rows.is_empty());
+
+            let rows = sqlx::query(
+                r#"
+                    select * from todo_labels where todo_id=$1
+                "#,
+            )
+            .bind(id)
+            .fetch_all(&pool)
+            .await
+            .expect("[delete] todo_labels fetch error");
+            assert!(rows.is_empty());
         }
 
-        fn read_store_ref(&self) -> RwLockReadGuard<TodoDatas> {
-            self.store.read().unwrap()
+        #[tokio::test]
+        async fn workspace_scenario() {
+            let pool = connect().await;
+            let user_id = prepare_test_user(&pool).await;
+            let other_workspace_id = prepare_test_workspace(&pool, "workspace-scenario").await;
+            let other_user_id =
+                prepare_workspace_test_user(&pool, other_workspace_id, "[workspace_scenario] user")
+                    .await;
+            let (label_id,) = sqlx::query_as::<_, (i32,)>(
+                r#"
+                insert into labels (name, user_id, workspace_id)
+                values ('[workspace_scenario] label', $1, $2)
+                returning id
+                "#,
+            )
+            .bind(other_user_id)
+            .bind(other_workspace_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data.");
+
+            super::workspace_scenario(
+                &TodoRepositoryForDB::new(pool.clone()),
+                user_id,
+                other_workspace_id,
+                other_user_id,
+                label_id,
+            )
+            .await;
+
+            sqlx::query("delete from labels where id = $1")
+                .bind(label_id)
+                .execute(&pool)
+                .await
+                .expect("Failed to clean label data.");
         }
 
-        fn resolove_labels(&self, labels: Vec<i32>) -> Vec<Label> {
-            let mut label_list = self.labels.iter().cloned();
-            let labels = labels
-                .iter()
-                .map(|label_id| {
-                    label_list
-                        .find(|label| label.id == *label_id)
-                        .unwrap()
-                })
-                .collect();
-            labels
+        #[tokio::test]
+        async fn blocker_scenario() {
+            let pool = connect().await;
+            let user_id = prepare_test_user(&pool).await;
+            super::blocker_scenario(&TodoRepositoryForDB::new(pool), user_id).await;
         }
     }
 
-    #[async_trait]
-    impl TodoRepository for TodoRepositoryForMemory {
-        async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
-            let mut store = self.write_store_ref();
-            let id = (store.len() + 1) as i32;
-            let labels = self.resolove_labels(payload.labels);
-            let todo = TodoEntity::new(id, payload.text.clone(), labels);
-            store.insert(id, todo.clone());
-            Ok(todo)
+    mod sqlite {
+        use super::*;
+        use crate::repositories::test_utils::{
+            prepare_sqlite_pool, prepare_sqlite_test_user, prepare_sqlite_test_workspace,
+        };
+
+        #[tokio::test]
+        async fn crud_scenario() {
+            let pool = prepare_sqlite_pool().await;
+            let user_id = prepare_sqlite_test_user(&pool, 1, "[test] user").await;
+            let label_1 = sqlx::query_as::<_, Label>(
+                "insert into labels (name, user_id, workspace_id) values ('test label', $1, 1) returning *",
+            )
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data.");
+
+            let id = super::crud_scenario(
+                &TodoRepositoryForSqlite::new(pool.clone()),
+                user_id,
+                label_1,
+            )
+            .await;
+
+            for table in ["todos", "todo_labels"] {
+                let column = if table == "todos" { "id" } else { "todo_id" };
+                let rows = sqlx::query(&format!("select * from {} where {} = $1", table, column))
+                    .bind(id)
+                    .fetch_all(&pool)
+                    .await
+                    .expect("[delete] rows fetch error");
+                assert!(rows.is_empty(), "{} left rows", table);
+            }
         }
 
-        async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
-            let store = self.read_store_ref();
-            let todo = store
-                .get(&id)
-                .map(|todo| todo.clone())
-                .ok_or(RepositoryError::NotFound(id))?;
-            Ok(todo)
+        #[tokio::test]
+        async fn workspace_scenario() {
+            let pool = prepare_sqlite_pool().await;
+            let user_id = prepare_sqlite_test_user(&pool, 1, "[test] user").await;
+            let other_workspace_id =
+                prepare_sqlite_test_workspace(&pool, "workspace-scenario").await;
+            let other_user_id =
+                prepare_sqlite_test_user(&pool, other_workspace_id, "[workspace_scenario] user")
+                    .await;
+            let (label_id,) = sqlx::query_as::<_, (i32,)>(
+                "insert into labels (name, user_id, workspace_id) values ('label', $1, $2) returning id",
+            )
+            .bind(other_user_id)
+            .bind(other_workspace_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data.");
+
+            super::workspace_scenario(
+                &TodoRepositoryForSqlite::new(pool),
+                user_id,
+                other_workspace_id,
+                other_user_id,
+                label_id,
+            )
+            .await;
         }
 
-        async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
-            let store = self.read_store_ref();
-            Ok(Vec::from_iter(store.values().map(|todo| todo.clone())))
+        #[tokio::test]
+        async fn blocker_scenario() {
+            let pool = prepare_sqlite_pool().await;
+            let user_id = prepare_sqlite_test_user(&pool, 1, "[test] user").await;
+            super::blocker_scenario(&TodoRepositoryForSqlite::new(pool), user_id).await;
         }
+    }
+}
 
-        async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
-            let mut store = self.write_store_ref();
-            let todo = store.get(&id).context(RepositoryError::NotFound(id))?;
-            let text = payload.text.unwrap_or(todo.text.clone());
-            let completed = payload.completed.unwrap_or(todo.completed);
-            let labels = match payload.labels {
-                Some(label_ids) => self.resolove_labels(label_ids),
-                None => todo.labels.clone(),
-            };
-            let todo = TodoEntity {
-                id,
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+    use crate::repositories::label::Label;
+
+    impl CreateTodo {
+        pub fn new(text: String, labels: Vec<i32>) -> Self {
+            Self { text, labels }
+        }
+    }
+
+    impl UpdateTodo {
+        pub fn new(
+            text: Option<String>,
+            completed: Option<bool>,
+            labels: Option<Vec<i32>>,
+        ) -> Self {
+            Self {
                 text,
                 completed,
                 labels,
-            };
-            store.insert(id, todo.clone());
-            Ok(todo)
+            }
         }
+    }
 
-        async fn delete(&self, id: i32) -> anyhow::Result<()> {
-            let mut store = self.write_store_ref();
-            store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
-            Ok(())
+    impl TodoRepositoryForMemory {
+        /// `labels` belong to the user 1 of the default workspace the tests act as
+        pub fn new(labels: Vec<Label>) -> Self {
+            let repository = Self::open(&Journal::memory()).unwrap();
+            let mut store = repository.labels.try_write().unwrap();
+            for label in labels {
+                store.insert(label.id, ((1, 1), label));
+            }
+            store.commit_blocking().unwrap();
+            repository
         }
     }
 
@@ -577,9 +2071,12 @@ pub mod test_utils {
             let labels = vec![label_data.clone()];
             let expected = TodoEntity {
                 id,
-                text: text.clone(), 
-                completed: false, 
-                labels: labels.clone()
+                text: text.clone(),
+                completed: false,
+                labels: labels.clone(),
+                items: vec![],
+                blocked: false,
+                blocked_by: vec![],
             };
 
             //create
@@ -590,23 +2087,32 @@ pub mod test_utils {
             let labels = vec![label_data.clone()];
             let repository = TodoRepositoryForMemory::new(labels.clone());
             let todo = repository
-                .create(CreateTodo::new(text, vec![label_data.id]))
+                .create(1, 1, CreateTodo::new(text, vec![label_data.id]))
                 .await
                 .expect("failed create todo");
             assert_eq!(expected, todo);
 
             //find
-            let todo = repository.find(todo.id).await.unwrap();
+            let todo = repository.find(1, 1, todo.id).await.unwrap();
             assert_eq!(expected, todo);
 
             //all
-            let todo = repository.all().await.expect("failed get all todos");
+            let todo = repository.all(1, 1).await.expect("failed get all todos");
             assert_eq!(vec![expected], todo);
 
+            //other users and workspaces
+            assert!(repository.find(1, 2, id).await.is_err());
+            assert!(repository.find(2, 1, id).await.is_err());
+            assert!(repository.all(2, 1).await.unwrap().is_empty());
+            assert!(repository.all(1, 2).await.unwrap().is_empty());
+            assert!(repository.delete(1, 2, id).await.is_err());
+
             //update
             let text = "update todo text".to_string();
             let todo = repository
                 .update(
+                    1,
+                    1,
                     1,
                     UpdateTodo {
                         text: Some(text.clone()),
@@ -622,13 +2128,183 @@ pub mod test_utils {
                     text,
                     completed: true,
                     labels: vec![],
+                    items: vec![],
+                    blocked: false,
+                    blocked_by: vec![],
                 },
                 todo
             );
 
+            //items
+            let item = repository
+                .create_item(
+                    1,
+                    1,
+                    id,
+                    CreateItem {
+                        text: "item text".to_string(),
+                        position: None,
+                    },
+                )
+                .await
+                .expect("failed to create item.");
+            assert_eq!(
+                TodoItem {
+                    id: 1,
+                    todo_id: id,
+                    text: "item text".to_string(),
+                    done: false,
+                    position: 1,
+                },
+                item
+            );
+            let item = repository
+                .update_item(
+                    1,
+                    1,
+                    id,
+                    item.id,
+                    UpdateItem {
+                        text: None,
+                        done: Some(true),
+                        position: None,
+                    },
+                )
+                .await
+                .expect("failed to update item.");
+            assert!(item.done);
+            let todo = repository.find(1, 1, id).await.unwrap();
+            assert_eq!(vec![item.clone()], todo.items);
+            let res = repository.delete_item(1, 1, id, item.id).await;
+            assert!(res.is_ok());
+
             //delete
-            let res = repository.delete(id).await;
+            let res = repository.delete(1, 1, id).await;
             assert!(res.is_ok())
         }
+
+        #[tokio::test]
+        async fn todo_blocker_scenario() {
+            let repository = TodoRepositoryForMemory::new(vec![]);
+            let todo = repository
+                .create(1, 1, CreateTodo::new("blocked todo".to_string(), vec![]))
+                .await
+                .unwrap();
+            let blocker = repository
+                .create(1, 1, CreateTodo::new("blocker todo".to_string(), vec![]))
+                .await
+                .unwrap();
+
+            let blocked = repository
+                .add_blocker(1, 1, todo.id, blocker.id)
+                .await
+                .unwrap();
+            assert!(blocked.blocked);
+            assert_eq!(vec![blocker.id], blocked.blocked_by);
+            assert!(repository
+                .add_blocker(1, 1, blocker.id, todo.id)
+                .await
+                .is_err());
+            assert!(repository
+                .add_blocker(1, 1, todo.id, todo.id)
+                .await
+                .is_err());
+
+            let complete = UpdateTodo {
+                text: None,
+                completed: Some(true),
+                labels: None,
+            };
+            assert!(repository
+                .update(1, 1, todo.id, complete.clone())
+                .await
+                .is_err());
+            repository
+                .update(1, 1, blocker.id, complete.clone())
+                .await
+                .unwrap();
+            assert!(!repository.find(1, 1, todo.id).await.unwrap().blocked);
+            repository.update(1, 1, todo.id, complete).await.unwrap();
+
+            repository
+                .remove_blocker(1, 1, todo.id, blocker.id)
+                .await
+                .unwrap();
+            assert!(repository
+                .find(1, 1, todo.id)
+                .await
+                .unwrap()
+                .blocked_by
+                .is_empty());
+            assert!(repository
+                .remove_blocker(1, 1, todo.id, blocker.id)
+                .await
+                .is_err());
+        }
+
+        #[tokio::test]
+        async fn todo_delete_scenario() {
+            use crate::repositories::{
+                attachment::{
+                    AttachmentRepository, AttachmentRepositoryForMemory, CreateAttachment,
+                },
+                comment::{CommentRepository, CommentRepositoryForMemory, CreateComment},
+                reminder::{CreateReminder, ReminderRepository, ReminderRepositoryForMemory},
+            };
+
+            let journal = Journal::memory();
+            let repository = TodoRepositoryForMemory::open(&journal).unwrap();
+            let comments = CommentRepositoryForMemory::open(&journal).unwrap();
+            let attachments = AttachmentRepositoryForMemory::open(&journal).unwrap();
+            let reminders = ReminderRepositoryForMemory::open(&journal).unwrap();
+            let todo = repository
+                .create(1, 1, CreateTodo::new("deleted todo".to_string(), vec![]))
+                .await
+                .unwrap();
+            let kept = repository
+                .create(1, 1, CreateTodo::new("kept todo".to_string(), vec![]))
+                .await
+                .unwrap();
+            for todo_id in [todo.id, kept.id] {
+                comments
+                    .create(
+                        todo_id,
+                        CreateComment {
+                            body: "comment".to_string(),
+                        },
+                    )
+                    .await
+                    .unwrap();
+                attachments
+                    .create(
+                        todo_id,
+                        CreateAttachment {
+                            file_name: "log.txt".to_string(),
+                            content_type: "text/plain".to_string(),
+                            size: 3,
+                        },
+                    )
+                    .await
+                    .unwrap();
+                reminders
+                    .create(
+                        todo_id,
+                        CreateReminder {
+                            remind_at: Utc::now(),
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            // the journal has no foreign keys, so the rows of the todo go with it
+            repository.delete(1, 1, todo.id).await.unwrap();
+            assert!(comments.all(todo.id).await.unwrap().is_empty());
+            assert!(attachments.all(todo.id).await.unwrap().is_empty());
+            assert!(reminders.all(todo.id).await.unwrap().is_empty());
+            assert_eq!(1, comments.all(kept.id).await.unwrap().len());
+            assert_eq!(1, attachments.all(kept.id).await.unwrap().len());
+            assert_eq!(1, reminders.all(kept.id).await.unwrap().len());
+        }
     }
 }
@@ -0,0 +1,497 @@
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+use std::{fmt, str::FromStr};
+use validator::Validate;
+
+/// operation to personal access tokens of a user
+/// create: POST -- issue a token, only its hash is stored
+/// all: GET -- tokens of a user
+/// delete: DELETE -- revoke a token
+/// find_by_hash -- unexpired token for a request, stamps `last_used_at`
+#[async_trait]
+pub trait PersonalTokenRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        payload: CreatePersonalToken,
+    ) -> anyhow::Result<PersonalToken>;
+    async fn all(&self, user_id: i32) -> anyhow::Result<Vec<PersonalToken>>;
+    async fn delete(&self, user_id: i32, id: i32) -> anyhow::Result<()>;
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<PersonalToken>;
+}
+
+/// what a personal access token may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "todos:read")]
+    TodosRead,
+    #[serde(rename = "todos:write")]
+    TodosWrite,
+    #[serde(rename = "labels:read")]
+    LabelsRead,
+    #[serde(rename = "labels:write")]
+    LabelsWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::TodosRead => "todos:read",
+            Scope::TodosWrite => "todos:write",
+            Scope::LabelsRead => "labels:read",
+            Scope::LabelsWrite => "labels:write",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = RepositoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "todos:read" => Ok(Scope::TodosRead),
+            "todos:write" => Ok(Scope::TodosWrite),
+            "labels:read" => Ok(Scope::LabelsRead),
+            "labels:write" => Ok(Scope::LabelsWrite),
+            _ => Err(RepositoryError::Unexpected(format!("unknown scope {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PersonalToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct PersonalTokenFromRow {
+    id: i32,
+    user_id: i32,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<PersonalTokenFromRow> for PersonalToken {
+    type Error = RepositoryError;
+
+    fn try_from(row: PersonalTokenFromRow) -> Result<Self, Self::Error> {
+        Ok(PersonalToken {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            scopes: row
+                .scopes
+                .iter()
+                .map(|scope| scope.parse())
+                .collect::<Result<_, _>>()?,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+        })
+    }
+}
+
+/// sqlite has no arrays, the scopes are stored as a JSON array
+#[derive(Debug, Clone, FromRow)]
+struct PersonalTokenFromSqliteRow {
+    id: i32,
+    user_id: i32,
+    name: String,
+    scopes: String,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<PersonalTokenFromSqliteRow> for PersonalToken {
+    type Error = RepositoryError;
+
+    fn try_from(row: PersonalTokenFromSqliteRow) -> Result<Self, Self::Error> {
+        let scopes = serde_json::from_str(&row.scopes)
+            .map_err(|e| RepositoryError::Unexpected(format!("invalid scopes: {}", e)))?;
+        PersonalTokenFromRow {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            scopes,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+        }
+        .try_into()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreatePersonalToken {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 100, message = "Over text length"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PersonalTokenRepositoryForDB {
+    pool: PgPool,
+}
+
+impl PersonalTokenRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PersonalTokenRepository for PersonalTokenRepositoryForDB {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        payload: CreatePersonalToken,
+    ) -> anyhow::Result<PersonalToken> {
+        let scopes: Vec<&str> = payload.scopes.iter().map(Scope::as_str).collect();
+        let row = sqlx::query_as::<_, PersonalTokenFromRow>(
+            r#"
+            insert into personal_tokens (user_id, name, token_hash, scopes, expires_at)
+            values ($1, $2, $3, $4, $5)
+            returning *
+            "#,
+        )
+        .bind(user_id)
+        .bind(payload.name)
+        .bind(token_hash)
+        .bind(scopes)
+        .bind(payload.expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.try_into()?)
+    }
+
+    async fn all(&self, user_id: i32) -> anyhow::Result<Vec<PersonalToken>> {
+        retry::read(|| async move {
+            let rows = sqlx::query_as::<_, PersonalTokenFromRow>(
+                r#"
+                select * from personal_tokens
+                where user_id = $1
+                order by id asc
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let tokens = rows
+                .into_iter()
+                .map(PersonalToken::try_from)
+                .collect::<Result<_, _>>()?;
+            Ok(tokens)
+        })
+        .await
+    }
+
+    async fn delete(&self, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from personal_tokens where id = $1 and user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<PersonalToken> {
+        retry::read(|| async move {
+            let row = sqlx::query_as::<_, PersonalTokenFromRow>(
+                r#"
+                update personal_tokens set last_used_at = now()
+                where token_hash = $1 and (expires_at is null or expires_at > now())
+                returning *
+                "#,
+            )
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(0))?;
+
+            Ok(row.try_into()?)
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PersonalTokenRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl PersonalTokenRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PersonalTokenRepository for PersonalTokenRepositoryForSqlite {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        payload: CreatePersonalToken,
+    ) -> anyhow::Result<PersonalToken> {
+        let scopes: Vec<&str> = payload.scopes.iter().map(Scope::as_str).collect();
+        let row = sqlx::query_as::<_, PersonalTokenFromSqliteRow>(
+            r#"
+            insert into personal_tokens (user_id, name, token_hash, scopes, created_at, expires_at)
+            values ($1, $2, $3, $4, $5, $6)
+            returning *
+            "#,
+        )
+        .bind(user_id)
+        .bind(payload.name)
+        .bind(token_hash)
+        .bind(serde_json::to_string(&scopes)?)
+        .bind(Utc::now())
+        .bind(payload.expires_at)
+        // read to the end, a statement left unfinished keeps the write uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or_else(|| RepositoryError::Unexpected("no token returned".to_string()))?;
+
+        Ok(row.try_into()?)
+    }
+
+    async fn all(&self, user_id: i32) -> anyhow::Result<Vec<PersonalToken>> {
+        let rows = sqlx::query_as::<_, PersonalTokenFromSqliteRow>(
+            r#"
+            select * from personal_tokens
+            where user_id = $1
+            order by id asc
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let tokens = rows
+            .into_iter()
+            .map(PersonalToken::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(tokens)
+    }
+
+    async fn delete(&self, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from personal_tokens where id = $1 and user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        Ok(())
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<PersonalToken> {
+        let row = sqlx::query_as::<_, PersonalTokenFromSqliteRow>(
+            r#"
+            update personal_tokens set last_used_at = $2
+            where token_hash = $1 and (expires_at is null or expires_at > $2)
+            returning *
+            "#,
+        )
+        .bind(token_hash)
+        .bind(Utc::now())
+        // read to the end, see create
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::NotFound(0))?;
+
+        Ok(row.try_into()?)
+    }
+}
+
+/// keeps tokens with their hash in a table of a `Journal`
+#[derive(Debug, Clone)]
+pub struct PersonalTokenRepositoryForMemory {
+    store: Table<i32, (String, PersonalToken)>,
+}
+
+impl PersonalTokenRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            store: journal.table("personal_tokens")?,
+        })
+    }
+}
+
+#[async_trait]
+impl PersonalTokenRepository for PersonalTokenRepositoryForMemory {
+    async fn create(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        payload: CreatePersonalToken,
+    ) -> anyhow::Result<PersonalToken> {
+        let mut store = self.store.write().await;
+        let id = store.next_id();
+        let token = PersonalToken {
+            id,
+            user_id,
+            name: payload.name,
+            scopes: payload.scopes,
+            created_at: Utc::now(),
+            expires_at: payload.expires_at,
+            last_used_at: None,
+        };
+        store.insert(id, (token_hash, token.clone()));
+        store.commit().await?;
+        Ok(token)
+    }
+
+    async fn all(&self, user_id: i32) -> anyhow::Result<Vec<PersonalToken>> {
+        let store = self.store.read().await;
+        Ok(store
+            .values()
+            .filter(|(_, token)| token.user_id == user_id)
+            .map(|(_, token)| token.clone())
+            .collect())
+    }
+
+    async fn delete(&self, user_id: i32, id: i32) -> anyhow::Result<()> {
+        let mut store = self.store.write().await;
+        match store.get(&id) {
+            Some((_, token)) if token.user_id == user_id => store.remove(&id),
+            _ => return Err(RepositoryError::NotFound(id).into()),
+        };
+        store.commit().await?;
+        Ok(())
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> anyhow::Result<PersonalToken> {
+        let mut store = self.store.write().await;
+        let now = Utc::now();
+        // `is_none_or` would need rust 1.82
+        #[allow(clippy::unnecessary_map_or)]
+        let id = store
+            .iter()
+            .find(|(_, (hash, token))| {
+                hash == token_hash && token.expires_at.map_or(true, |at| at > now)
+            })
+            .map(|(id, _)| *id)
+            .context(RepositoryError::NotFound(0))?;
+        let (_, token) = store.get_mut(&id).unwrap();
+        token.last_used_at = Some(now);
+        let token = token.clone();
+        store.commit().await?;
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::user::test_utils::prepare_test_user;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+        let user_id = prepare_test_user(&pool).await;
+        let repository = PersonalTokenRepositoryForDB::new(pool.clone());
+
+        // create
+        let token = repository
+            .create(
+                user_id,
+                "[crud_scenario] token hash".to_string(),
+                CreatePersonalToken {
+                    name: "[crud_scenario] ci".to_string(),
+                    scopes: vec![Scope::TodosRead, Scope::TodosWrite],
+                    expires_at: None,
+                },
+            )
+            .await
+            .expect("[create] returned Err");
+        assert_eq!(vec![Scope::TodosRead, Scope::TodosWrite], token.scopes);
+        assert!(token.last_used_at.is_none());
+
+        // find
+        let found = repository
+            .find_by_hash("[crud_scenario] token hash")
+            .await
+            .expect("[find_by_hash] returned Err");
+        assert_eq!(token.id, found.id);
+        assert!(found.last_used_at.is_some());
+
+        // all
+        let tokens = repository.all(user_id).await.expect("[all] returned Err");
+        assert!(tokens.iter().any(|t| t.id == token.id));
+
+        // delete
+        assert!(repository.delete(user_id + 1, token.id).await.is_err());
+        repository
+            .delete(user_id, token.id)
+            .await
+            .expect("[delete] returned Err");
+        assert!(repository
+            .find_by_hash("[crud_scenario] token hash")
+            .await
+            .is_err());
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    impl PersonalTokenRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use anyhow::Context;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+use validator::Validate;
+
+/// operation to user accounts and their login tokens, names are unique per workspace
+/// create: POST -- register a new user
+/// find -- user of an id
+/// find_by_name -- user for a login
+/// create_token -- remember the hash of an issued token
+/// find_by_token -- user owning an unexpired token
+#[async_trait]
+pub trait UserRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        name: String,
+        password_hash: String,
+    ) -> anyhow::Result<User>;
+    async fn find(&self, id: i32) -> anyhow::Result<User>;
+    async fn find_by_name(&self, workspace_id: i32, name: &str) -> anyhow::Result<User>;
+    async fn create_token(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+    async fn find_by_token(&self, token_hash: &str) -> anyhow::Result<User>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct User {
+    pub id: i32,
+    pub workspace_id: i32,
+    pub name: String,
+    #[serde(skip)]
+    pub password_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct Credentials {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 100, message = "Over text length"))]
+    pub name: String,
+    #[validate(length(min = 8, message = "Too short password"))]
+    #[validate(length(max = 1000, message = "Over text length"))]
+    pub password: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserRepositoryForDB {
+    pool: PgPool,
+}
+
+impl UserRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for UserRepositoryForDB {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        name: String,
+        password_hash: String,
+    ) -> anyhow::Result<User> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            insert into users (workspace_id, name, password_hash)
+            values ($1, $2, $3)
+            on conflict (workspace_id, name) do nothing
+            returning id, workspace_id, name, password_hash
+            "#,
+        )
+        .bind(workspace_id)
+        .bind(name)
+        .bind(password_hash)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::Duplicate(0))?;
+
+        Ok(user)
+    }
+
+    async fn find(&self, id: i32) -> anyhow::Result<User> {
+        retry::read(|| async move {
+            let user = sqlx::query_as::<_, User>(
+                r#"
+                select id, workspace_id, name, password_hash from users where id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(id))?;
+
+            Ok(user)
+        })
+        .await
+    }
+
+    async fn find_by_name(&self, workspace_id: i32, name: &str) -> anyhow::Result<User> {
+        retry::read(|| async move {
+            let user = sqlx::query_as::<_, User>(
+                r#"
+                select id, workspace_id, name, password_hash from users
+                where workspace_id = $1 and name = $2
+                "#,
+            )
+            .bind(workspace_id)
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(0))?;
+
+            Ok(user)
+        })
+        .await
+    }
+
+    async fn create_token(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into user_tokens (user_id, token_hash, expires_at)
+            values ($1, $2, $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_by_token(&self, token_hash: &str) -> anyhow::Result<User> {
+        retry::read(|| async move {
+            let user = sqlx::query_as::<_, User>(
+                r#"
+                select users.id, users.workspace_id, users.name, users.password_hash from users
+                inner join user_tokens on user_tokens.user_id = users.id
+                where user_tokens.token_hash = $1 and user_tokens.expires_at > now()
+                "#,
+            )
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(0))?;
+
+            Ok(user)
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl UserRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for UserRepositoryForSqlite {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        name: String,
+        password_hash: String,
+    ) -> anyhow::Result<User> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            insert into users (workspace_id, name, password_hash)
+            values ($1, $2, $3)
+            on conflict (workspace_id, name) do nothing
+            returning id, workspace_id, name, password_hash
+            "#,
+        )
+        .bind(workspace_id)
+        .bind(name)
+        .bind(password_hash)
+        // read to the end, a statement left unfinished keeps the write uncommitted
+        // and invisible to the other connections of the pool
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(RepositoryError::Duplicate(0))?;
+
+        Ok(user)
+    }
+
+    async fn find(&self, id: i32) -> anyhow::Result<User> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            select id, workspace_id, name, password_hash from users where id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(user)
+    }
+
+    async fn find_by_name(&self, workspace_id: i32, name: &str) -> anyhow::Result<User> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            select id, workspace_id, name, password_hash from users
+            where workspace_id = $1 and name = $2
+            "#,
+        )
+        .bind(workspace_id)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(0))?;
+
+        Ok(user)
+    }
+
+    async fn create_token(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into user_tokens (user_id, token_hash, expires_at)
+            values ($1, $2, $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_by_token(&self, token_hash: &str) -> anyhow::Result<User> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            select users.id, users.workspace_id, users.name, users.password_hash from users
+            inner join user_tokens on user_tokens.user_id = users.id
+            where user_tokens.token_hash = $1 and user_tokens.expires_at > $2
+            "#,
+        )
+        .bind(token_hash)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(0))?;
+
+        Ok(user)
+    }
+}
+
+/// keeps users with their password hash and login tokens by their hash in
+/// tables of a `Journal`
+#[derive(Debug, Clone)]
+pub struct UserRepositoryForMemory {
+    users: Table<i32, (String, User)>,
+    tokens: Table<String, (i32, DateTime<Utc>)>,
+}
+
+impl UserRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        Ok(Self {
+            users: journal.table("users")?,
+            tokens: journal.table("user_tokens")?,
+        })
+    }
+}
+
+#[async_trait]
+impl UserRepository for UserRepositoryForMemory {
+    async fn create(
+        &self,
+        workspace_id: i32,
+        name: String,
+        password_hash: String,
+    ) -> anyhow::Result<User> {
+        let mut users = self.users.write().await;
+        if users
+            .values()
+            .any(|(_, user)| user.workspace_id == workspace_id && user.name == name)
+        {
+            return Err(RepositoryError::Duplicate(0).into());
+        }
+        let id = users.next_id();
+        let user = User {
+            id,
+            workspace_id,
+            name,
+            password_hash: password_hash.clone(),
+        };
+        users.insert(id, (password_hash, user.clone()));
+        users.commit().await?;
+        Ok(user)
+    }
+
+    async fn find(&self, id: i32) -> anyhow::Result<User> {
+        let users = self.users.read().await;
+        let user = users
+            .get(&id)
+            .map(with_password_hash)
+            .context(RepositoryError::NotFound(id))?;
+        Ok(user)
+    }
+
+    async fn find_by_name(&self, workspace_id: i32, name: &str) -> anyhow::Result<User> {
+        let users = self.users.read().await;
+        let user = users
+            .values()
+            .find(|(_, user)| user.workspace_id == workspace_id && user.name == name)
+            .map(with_password_hash)
+            .context(RepositoryError::NotFound(0))?;
+        Ok(user)
+    }
+
+    async fn create_token(
+        &self,
+        user_id: i32,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let mut tokens = self.tokens.write().await;
+        tokens.insert(token_hash, (user_id, expires_at));
+        tokens.commit().await?;
+        Ok(())
+    }
+
+    async fn find_by_token(&self, token_hash: &str) -> anyhow::Result<User> {
+        let user_id = self
+            .tokens
+            .read()
+            .await
+            .get(token_hash)
+            .filter(|(_, expires_at)| *expires_at > Utc::now())
+            .map(|(user_id, _)| *user_id)
+            .context(RepositoryError::NotFound(0))?;
+        self.find(user_id).await
+    }
+}
+
+/// the hash is not serialized with the user, so it is kept next to it
+fn with_password_hash((password_hash, user): &(String, User)) -> User {
+    User {
+        password_hash: password_hash.clone(),
+        ..user.clone()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+        sqlx::query("delete from users where name = $1")
+            .bind("[crud_scenario] user")
+            .execute(&pool)
+            .await
+            .expect("Failed to clean user data.");
+
+        let repository = UserRepositoryForDB::new(pool.clone());
+
+        // create
+        let user = repository
+            .create(1, "[crud_scenario] user".to_string(), "hash".to_string())
+            .await
+            .expect("[create] returned Err");
+        assert!(repository
+            .create(1, "[crud_scenario] user".to_string(), "hash".to_string())
+            .await
+            .is_err());
+
+        // find
+        let found = repository
+            .find_by_name(1, "[crud_scenario] user")
+            .await
+            .expect("[find_by_name] returned Err");
+        assert_eq!(user, found);
+        let found = repository.find(user.id).await.expect("[find] returned Err");
+        assert_eq!(user, found);
+
+        // tokens
+        repository
+            .create_token(user.id, "live".to_string(), Utc::now() + Duration::hours(1))
+            .await
+            .expect("[create_token] returned Err");
+        repository
+            .create_token(user.id, "expired".to_string(), Utc::now())
+            .await
+            .expect("[create_token] returned Err");
+        let found = repository
+            .find_by_token("live")
+            .await
+            .expect("[find_by_token] returned Err");
+        assert_eq!(user, found);
+        assert!(repository.find_by_token("expired").await.is_err());
+
+        sqlx::query("delete from users where id = $1")
+            .bind(user.id)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean user data.");
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    /// id of a user of the default workspace shared by the database tests,
+    /// created on first use
+    #[cfg(feature = "database-test")]
+    pub async fn prepare_test_user(pool: &PgPool) -> i32 {
+        prepare_named_test_user(pool, "[test] user").await
+    }
+
+    #[cfg(feature = "database-test")]
+    pub async fn prepare_named_test_user(pool: &PgPool, name: &str) -> i32 {
+        prepare_workspace_test_user(pool, 1, name).await
+    }
+
+    #[cfg(feature = "database-test")]
+    pub async fn prepare_workspace_test_user(pool: &PgPool, workspace_id: i32, name: &str) -> i32 {
+        let (user_id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            insert into users (workspace_id, name, password_hash)
+            values ($1, $2, '')
+            on conflict (workspace_id, name) do update set name = excluded.name
+            returning id
+            "#,
+        )
+        .bind(workspace_id)
+        .bind(name)
+        .fetch_one(pool)
+        .await
+        .expect("Failed to prepare user data.");
+        user_id
+    }
+
+    impl UserRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use super::{retry, RepositoryError};
+use crate::journal::{Journal, JournalError, Table};
+use anyhow::Context;
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, SqlitePool};
+
+/// slug of the workspace requests naming no other one belong to
+pub const DEFAULT_WORKSPACE: &str = "default";
+
+/// operation to workspaces, the tenants partitioning users, TODOs and labels
+/// find_by_slug -- workspace named by a request
+#[async_trait]
+pub trait WorkspaceRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn find_by_slug(&self, slug: &str) -> anyhow::Result<Workspace>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct Workspace {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkspaceRepositoryForDB {
+    pool: PgPool,
+}
+
+impl WorkspaceRepositoryForDB {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WorkspaceRepository for WorkspaceRepositoryForDB {
+    async fn find_by_slug(&self, slug: &str) -> anyhow::Result<Workspace> {
+        retry::read(|| async move {
+            let workspace = sqlx::query_as::<_, Workspace>(
+                r#"
+                select id, slug, name from workspaces where slug = $1
+                "#,
+            )
+            .bind(slug)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(0))?;
+
+            Ok(workspace)
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkspaceRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl WorkspaceRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WorkspaceRepository for WorkspaceRepositoryForSqlite {
+    async fn find_by_slug(&self, slug: &str) -> anyhow::Result<Workspace> {
+        let workspace = sqlx::query_as::<_, Workspace>(
+            r#"
+            select id, slug, name from workspaces where slug = $1
+            "#,
+        )
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(0))?;
+
+        Ok(workspace)
+    }
+}
+
+/// keeps workspaces in a table of a `Journal`, the default workspace is
+/// created as id 1 like the migrations do
+#[derive(Debug, Clone)]
+pub struct WorkspaceRepositoryForMemory {
+    store: Table<i32, Workspace>,
+}
+
+impl WorkspaceRepositoryForMemory {
+    pub fn open(journal: &Journal) -> Result<Self, JournalError> {
+        let store: Table<i32, Workspace> = journal.table("workspaces")?;
+        let mut workspaces = store
+            .try_write()
+            .expect("workspaces are seeded before they are shared");
+        if !workspaces
+            .values()
+            .any(|workspace| workspace.slug == DEFAULT_WORKSPACE)
+        {
+            let id = workspaces.next_id();
+            workspaces.insert(
+                id,
+                Workspace {
+                    id,
+                    slug: DEFAULT_WORKSPACE.to_string(),
+                    name: "Default".to_string(),
+                },
+            );
+        }
+        workspaces.commit_blocking()?;
+        Ok(Self { store })
+    }
+}
+
+#[async_trait]
+impl WorkspaceRepository for WorkspaceRepositoryForMemory {
+    async fn find_by_slug(&self, slug: &str) -> anyhow::Result<Workspace> {
+        let store = self.store.read().await;
+        let workspace = store
+            .values()
+            .find(|workspace| workspace.slug == slug)
+            .cloned()
+            .context(RepositoryError::NotFound(0))?;
+        Ok(workspace)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::test_utils::prepare_test_workspace;
+    use super::*;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+        let workspace_id = prepare_test_workspace(&pool, "crud-scenario").await;
+        let repository = WorkspaceRepositoryForDB::new(pool.clone());
+
+        // find
+        let workspace = repository
+            .find_by_slug("crud-scenario")
+            .await
+            .expect("[find_by_slug] returned Err");
+        assert_eq!(workspace_id, workspace.id);
+        let workspace = repository
+            .find_by_slug(DEFAULT_WORKSPACE)
+            .await
+            .expect("[find_by_slug] returned Err");
+        assert_eq!(DEFAULT_WORKSPACE, workspace.slug);
+        assert!(repository.find_by_slug("missing").await.is_err());
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    /// id of the workspace with `slug`, created on first use
+    #[cfg(feature = "database-test")]
+    pub async fn prepare_test_workspace(pool: &PgPool, slug: &str) -> i32 {
+        let (workspace_id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            insert into workspaces (slug, name)
+            values ($1, $1)
+            on conflict (slug) do update set name = excluded.name
+            returning id
+            "#,
+        )
+        .bind(slug)
+        .fetch_one(pool)
+        .await
+        .expect("Failed to prepare workspace data.");
+        workspace_id
+    }
+
+    impl WorkspaceRepositoryForMemory {
+        pub fn new() -> Self {
+            Self::open(&Journal::memory()).unwrap()
+        }
+
+        pub async fn insert(&self, slug: &str) -> Workspace {
+            let mut store = self.store.write().await;
+            let id = store.next_id();
+            let workspace = Workspace {
+                id,
+                slug: slug.to_string(),
+                name: slug.to_string(),
+            };
+            store.insert(id, workspace.clone());
+            store.commit().await.unwrap();
+            workspace
+        }
+    }
+}
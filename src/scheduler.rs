@@ -0,0 +1,182 @@
+use chrono::{Duration, Utc};
+use std::future::Future;
+
+use crate::{notifier::Notifier, repositories::reminder::ReminderRepository};
+
+/// periodically sends due reminders through a notifier
+pub struct ReminderScheduler<R: ReminderRepository> {
+    repository: R,
+    notifier: Box<dyn Notifier>,
+    interval: std::time::Duration,
+    max_attempts: i32,
+}
+
+impl<R: ReminderRepository> ReminderScheduler<R> {
+    /// claimed reminders stay hidden from other schedulers this long
+    const LEASE_SECONDS: i64 = 300;
+    const BATCH_SIZE: i64 = 100;
+
+    pub fn new(repository: R, notifier: Box<dyn Notifier>, interval: std::time::Duration) -> Self {
+        Self {
+            repository,
+            notifier,
+            interval,
+            max_attempts: 5,
+        }
+    }
+
+    /// ticks until `shutdown` resolves, a running tick is finished first
+    pub async fn run(self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(self.interval);
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = &mut shutdown => return,
+            }
+            if let Err(e) = self.tick().await {
+                tracing::error!("reminder scheduler: {}", e);
+            }
+        }
+    }
+
+    /// sends reminders due now, returns how many were delivered
+    pub async fn tick(&self) -> anyhow::Result<usize> {
+        let now = Utc::now();
+        let due = self
+            .repository
+            .claim_due(
+                now,
+                Duration::seconds(Self::LEASE_SECONDS),
+                Self::BATCH_SIZE,
+            )
+            .await?;
+
+        let mut sent = 0;
+        for reminder in due {
+            match self.notifier.notify(&reminder).await {
+                Ok(()) => {
+                    self.repository.mark_sent(reminder.id, Utc::now()).await?;
+                    sent += 1;
+                }
+                Err(e) => {
+                    let attempts = reminder.attempts + 1;
+                    let retry_at = (attempts < self.max_attempts)
+                        .then(|| Utc::now() + Self::backoff(attempts));
+                    tracing::warn!(
+                        "reminder {} failed ({} attempts): {}",
+                        reminder.id,
+                        attempts,
+                        e
+                    );
+                    self.repository
+                        .mark_failed(reminder.id, e.to_string(), retry_at)
+                        .await?;
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    /// 30s, 1m, 2m, ... capped at one hour
+    fn backoff(attempts: i32) -> Duration {
+        let seconds = 30i64 << (attempts - 1).clamp(0, 7);
+        Duration::seconds(seconds.min(3600))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::reminder::{CreateReminder, DueReminder, ReminderRepositoryForMemory};
+    use axum::async_trait;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// fails the first `failures` deliveries
+    struct FlakyNotifier {
+        failures: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Notifier for FlakyNotifier {
+        async fn notify(&self, _reminder: &DueReminder) -> anyhow::Result<()> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                anyhow::bail!("refused");
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reminder_fires_once() {
+        let repository = ReminderRepositoryForMemory::new().with_todo(1, "todo");
+        let reminder = repository
+            .create(
+                1,
+                CreateReminder {
+                    remind_at: Utc::now() - Duration::seconds(1),
+                },
+            )
+            .await
+            .unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let scheduler = ReminderScheduler::new(
+            repository.clone(),
+            Box::new(FlakyNotifier {
+                failures: 0,
+                calls: calls.clone(),
+            }),
+            std::time::Duration::from_secs(1),
+        );
+
+        assert_eq!(1, scheduler.tick().await.unwrap());
+        assert_eq!(0, scheduler.tick().await.unwrap());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+        let reminders = repository.all(1).await.unwrap();
+        assert_eq!(reminder.id, reminders[0].id);
+        assert!(reminders[0].sent_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn reminder_retries_with_backoff() {
+        let repository = ReminderRepositoryForMemory::new().with_todo(1, "todo");
+        repository
+            .create(
+                1,
+                CreateReminder {
+                    remind_at: Utc::now() - Duration::seconds(1),
+                },
+            )
+            .await
+            .unwrap();
+        let scheduler = ReminderScheduler::new(
+            repository.clone(),
+            Box::new(FlakyNotifier {
+                failures: 1,
+                calls: Arc::default(),
+            }),
+            std::time::Duration::from_secs(1),
+        );
+
+        assert_eq!(0, scheduler.tick().await.unwrap());
+        let reminder = &repository.all(1).await.unwrap()[0];
+        assert_eq!(1, reminder.attempts);
+        assert_eq!(Some("refused".to_string()), reminder.last_error);
+        assert!(reminder.next_attempt_at.unwrap() > Utc::now());
+        // not due again before the backoff passed
+        assert_eq!(0, scheduler.tick().await.unwrap());
+    }
+
+    #[test]
+    fn backoff_test() {
+        type Scheduler = ReminderScheduler<ReminderRepositoryForMemory>;
+        assert_eq!(Duration::seconds(30), Scheduler::backoff(1));
+        assert_eq!(Duration::seconds(60), Scheduler::backoff(2));
+        assert_eq!(Duration::seconds(3600), Scheduler::backoff(10));
+    }
+}
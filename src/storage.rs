@@ -0,0 +1,163 @@
+use axum::async_trait;
+use hyper::body::Bytes;
+use std::{io::ErrorKind, path::PathBuf};
+
+/// blob store for uploaded files
+/// put: store data under key, replacing existing data
+/// get: read data stored under key
+/// delete: remove data under key, succeeds when nothing is stored
+/// delete_all: remove data under every key starting with `prefix/`
+#[async_trait]
+pub trait Storage: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn put(&self, key: &str, data: Bytes) -> anyhow::Result<()>;
+    async fn get(&self, key: &str) -> anyhow::Result<Bytes>;
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+    async fn delete_all(&self, prefix: &str) -> anyhow::Result<()>;
+}
+
+/// stores blobs as files below a root directory
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes) -> anyhow::Result<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // write aside and rename so readers never see a partial file
+        let tmp = path.with_extension("part");
+        tokio::fs::write(&tmp, &data).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Bytes> {
+        let data = tokio::fs::read(self.path(key)).await?;
+        Ok(Bytes::from(data))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_file(self.path(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_all(&self, prefix: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_dir_all(self.path(prefix)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_storage_scenario() {
+        let root = std::env::temp_dir().join(format!("todo-storage-{}", std::process::id()));
+        let storage = LocalStorage::new(&root);
+        let key = "todos/1/1";
+
+        storage
+            .put(key, Bytes::from_static(b"hello"))
+            .await
+            .expect("failed put blob");
+        let data = storage.get(key).await.expect("failed get blob");
+        assert_eq!(Bytes::from_static(b"hello"), data);
+
+        storage.delete(key).await.expect("failed delete blob");
+        assert!(storage.get(key).await.is_err());
+        // deleting twice is not an error
+        storage.delete(key).await.expect("failed delete blob");
+
+        for key in ["todos/1/2", "todos/1/3", "todos/10/1"] {
+            storage
+                .put(key, Bytes::from_static(b"hello"))
+                .await
+                .expect("failed put blob");
+        }
+        storage
+            .delete_all("todos/1")
+            .await
+            .expect("failed delete blobs");
+        assert!(storage.get("todos/1/2").await.is_err());
+        assert!(storage.get("todos/1/3").await.is_err());
+        assert!(storage.get("todos/10/1").await.is_ok());
+        storage
+            .delete_all("todos/1")
+            .await
+            .expect("failed delete blobs");
+
+        tokio::fs::remove_dir_all(root).await.ok();
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+    use anyhow::Context;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, RwLock},
+    };
+
+    #[derive(Debug, Clone, Default)]
+    pub struct StorageForMemory {
+        store: Arc<RwLock<HashMap<String, Bytes>>>,
+    }
+
+    impl StorageForMemory {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn contains(&self, key: &str) -> bool {
+            self.store.read().unwrap().contains_key(key)
+        }
+    }
+
+    #[async_trait]
+    impl Storage for StorageForMemory {
+        async fn put(&self, key: &str, data: Bytes) -> anyhow::Result<()> {
+            self.store.write().unwrap().insert(key.to_string(), data);
+            Ok(())
+        }
+
+        async fn get(&self, key: &str) -> anyhow::Result<Bytes> {
+            let store = self.store.read().unwrap();
+            let data = store.get(key).cloned().context("blob not found")?;
+            Ok(data)
+        }
+
+        async fn delete(&self, key: &str) -> anyhow::Result<()> {
+            self.store.write().unwrap().remove(key);
+            Ok(())
+        }
+
+        async fn delete_all(&self, prefix: &str) -> anyhow::Result<()> {
+            let prefix = format!("{}/", prefix);
+            self.store
+                .write()
+                .unwrap()
+                .retain(|key, _| !key.starts_with(&prefix));
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! runs the server binary against the database of `.env`
+#![cfg(all(unix, feature = "database-test"))]
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn get_root(port: u16) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    Some(response)
+}
+
+#[test]
+fn should_shutdown_on_sigterm() {
+    dotenv::dotenv().ok();
+    let port = free_port();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_todo"))
+        .args(["--listen", &format!("127.0.0.1:{}", port)])
+        .args(["--log-format", "compact", "--log-level", "info"])
+        .env_remove("CONFIG_FILE")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let started = Instant::now();
+    let response = loop {
+        if let Some(response) = get_root(port) {
+            break response;
+        }
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "server did not start"
+        );
+        sleep(Duration::from_millis(100));
+    };
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &server.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let stopped = Instant::now();
+    let status = loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            break status;
+        }
+        if stopped.elapsed() > Duration::from_secs(10) {
+            server.kill().unwrap();
+            panic!("server did not stop after SIGTERM");
+        }
+        sleep(Duration::from_millis(50));
+    };
+    assert!(status.success(), "{}", status);
+
+    let mut output = String::new();
+    server
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    assert!(output.contains("received SIGTERM"), "{}", output);
+    assert!(output.contains("shutdown complete"), "{}", output);
+    assert!(get_root(port).is_none());
+}
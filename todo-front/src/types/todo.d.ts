@@ -3,6 +3,17 @@ export type Todo = {
     text: string
     completed: boolean
     labels: Label[]
+    items: TodoItem[]
+    blocked: boolean
+    blocked_by: number[]
+  }
+
+  export type TodoItem = {
+    id: number
+    todo_id: number
+    text: string
+    done: boolean
+    position: number
   }
   
   export type NewTodoPayload = {